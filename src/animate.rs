@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use i3ipc::{EstablishError, I3Connection};
+
+use crate::command::Command;
+
+/// A sequence of resizes running on its own thread, so that the daemon keeps handling events
+/// while it plays out.
+pub struct Animation {
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Animation {
+    /// Starts running the given steps, waiting `interval` between each. Every step is sent as its
+    /// own message over a separate connection, so that i3 renders each one.
+    pub fn spawn(steps: Vec<Command>, interval: Duration) -> Result<Animation, EstablishError> {
+        let mut connection = I3Connection::connect()?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread_cancelled = Arc::clone(&cancelled);
+
+        let handle = thread::spawn(move || {
+            for (index, step) in steps.iter().enumerate() {
+                if index > 0 {
+                    thread::sleep(interval);
+                }
+                if thread_cancelled.load(Ordering::SeqCst) {
+                    trace!("Animation cancelled before {}", step);
+                    return;
+                }
+
+                trace!("Animating {}", step);
+                match connection.run_command(&step.to_string()) {
+                    Ok(reply) if reply.outcomes.iter().all(|o| o.success) => {}
+                    // The usual cause is the container having closed.
+                    result => {
+                        info!("Stopping animation at {}: {:?}", step, result);
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Animation { cancelled, handle })
+    }
+
+    /// Stops the animation before its next step.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::str::FromStr;
+
+use i3ipc::I3Connection;
+use serde::Serialize;
+
+use crate::settings::{MultiOutput, ResizeUnit, Settings};
+
+/// The window manager on the other end of the IPC socket. sway speaks i3's protocol, but
+/// accepts a slightly different command language.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    I3,
+    Sway,
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i3" => Ok(Backend::I3),
+            "sway" => Ok(Backend::Sway),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::I3 => write!(f, "i3"),
+            Backend::Sway => write!(f, "sway"),
+        }
+    }
+}
+
+/// The oldest i3 ratiosplit runs on. Older versions send events in a shape it can't read.
+pub const MIN_I3_VERSION: (i32, i32) = (4, 14);
+
+/// Commands and events that only some versions of the window manager understand.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Capabilities {
+    /// `swap container with`, added in i3 4.14. sway has always had it.
+    pub swap: bool,
+    /// `resize set` with a width and a height in one command, added in i3 4.16.
+    pub resize_set_both: bool,
+    /// `resize set` in ppt rather than px, added in i3 4.16.
+    pub ppt_resize: bool,
+    /// Tick events, added in i3 4.15.
+    pub tick_events: bool,
+}
+
+/// What a window manager of the given version supports. sway has everything ratiosplit uses.
+pub fn capabilities_for(backend: Backend, version: (i32, i32)) -> Capabilities {
+    let since = |i3_version| backend == Backend::Sway || version >= i3_version;
+    Capabilities {
+        swap: since((4, 14)),
+        resize_set_both: since((4, 16)),
+        ppt_resize: since((4, 16)),
+        tick_events: since((4, 15)),
+    }
+}
+
+/// Works out which window manager we're connected to, and what it supports, from its version
+/// reply. sway reports its own name in the human readable version string, while i3 never does.
+/// Fails for an i3 older than `MIN_I3_VERSION`.
+pub fn detect_backend(connection: &mut I3Connection) -> Result<(Backend, Capabilities), String> {
+    match connection.get_version() {
+        Ok(version) => {
+            info!("Connected to {:?}", version.human_readable);
+            let backend = backend_from_version(&version.human_readable);
+            let numbers = (version.major, version.minor);
+            if backend == Backend::I3 && numbers < MIN_I3_VERSION {
+                return Err(format!(
+                    "i3 {} is too old, ratiosplit needs i3 {}.{} or newer",
+                    version.human_readable, MIN_I3_VERSION.0, MIN_I3_VERSION.1
+                ));
+            }
+            Ok((backend, capabilities_for(backend, numbers)))
+        }
+        Err(error) => {
+            warn!(
+                "Error {:?} retrieving the version, assuming a recent i3",
+                error
+            );
+            Ok((Backend::I3, capabilities_for(Backend::I3, (i32::MAX, 0))))
+        }
+    }
+}
+
+/// Turns off the settings that need commands the window manager doesn't have, with a warning
+/// for each, so they don't fail on every window instead.
+pub fn restrict_settings(settings: &mut Settings, capabilities: &Capabilities) {
+    if !capabilities.ppt_resize {
+        if settings.resize_unit == ResizeUnit::Ppt {
+            warn!("This i3 can't resize in ppt, using resize_unit = px instead");
+            settings.resize_unit = ResizeUnit::Px;
+        }
+        if settings.multi_output_container == MultiOutput::Dominant {
+            warn!("This i3 can't resize in ppt, using multi_output_container = skip instead");
+            settings.multi_output_container = MultiOutput::Skip;
+        }
+    }
+}
+
+fn backend_from_version(human_readable: &str) -> Backend {
+    if human_readable.to_lowercase().contains("sway") {
+        Backend::Sway
+    } else {
+        Backend::I3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::default_settings;
+
+    #[test]
+    fn capabilities_follow_the_i3_version() {
+        let old = capabilities_for(Backend::I3, (4, 14));
+        assert!(old.swap);
+        assert!(!old.tick_events);
+        assert!(!old.ppt_resize);
+        assert!(!old.resize_set_both);
+
+        let middle = capabilities_for(Backend::I3, (4, 15));
+        assert!(middle.tick_events);
+        assert!(!middle.ppt_resize);
+
+        let recent = capabilities_for(Backend::I3, (4, 22));
+        assert!(recent.swap && recent.tick_events && recent.ppt_resize && recent.resize_set_both);
+    }
+
+    #[test]
+    fn sway_has_everything() {
+        let sway = capabilities_for(Backend::Sway, (1, 8));
+        assert!(sway.swap && sway.tick_events && sway.ppt_resize && sway.resize_set_both);
+    }
+
+    #[test]
+    fn settings_fall_back_to_px_without_ppt() {
+        let mut settings = default_settings();
+        settings.resize_unit = ResizeUnit::Ppt;
+        settings.multi_output_container = MultiOutput::Dominant;
+        restrict_settings(&mut settings, &capabilities_for(Backend::I3, (4, 14)));
+        assert_eq!(settings.resize_unit, ResizeUnit::Px);
+        assert_eq!(settings.multi_output_container, MultiOutput::Skip);
+
+        let mut settings = default_settings();
+        settings.resize_unit = ResizeUnit::Ppt;
+        settings.multi_output_container = MultiOutput::Dominant;
+        restrict_settings(&mut settings, &capabilities_for(Backend::I3, (4, 16)));
+        assert_eq!(settings.resize_unit, ResizeUnit::Ppt);
+        assert_eq!(settings.multi_output_container, MultiOutput::Dominant);
+    }
+
+    #[test]
+    fn sway_is_told_apart_by_its_version_string() {
+        assert_eq!(backend_from_version("sway version 1.8.1"), Backend::Sway);
+        assert_eq!(backend_from_version("Sway 1.9"), Backend::Sway);
+        assert_eq!(
+            backend_from_version("4.22 (2023-01-02) © 2009 Michael Stapelberg and contributors"),
+            Backend::I3
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use i3ipc::reply::{Node, NodeLayout};
+
+use crate::command::Command;
+use crate::planner::{ratio_to_ppt, target_px, DRIFT_TOLERANCE};
+use crate::settings::{ColumnFill, ResizeUnit, Settings, SplitDirection};
+use crate::tree::{effective_percent, find_node, find_output, find_workspace, tiled_leaves};
+
+/// The mark used to point `move container to mark` at a column for a moment.
+const COLUMN_MARK: &str = "_ratiosplit_column";
+
+/// Decides how to fit `new_node` into the columns of its workspace, with `mode = columns`. The
+/// workspace's own children are the columns. Until there are as many as configured, each new
+/// window becomes a column of its own; after that it's moved to the bottom of the column
+/// `column_fill` picks. Either way, the column widths are set to their ratios afterwards.
+pub fn plan_columns(tree: &Node, new_node: &Node, settings: &Settings) -> Option<Vec<Command>> {
+    let workspace = find_workspace(new_node.id, tree)?;
+    let ratios = column_ratios(tree, workspace, settings);
+
+    // How many windows each column holds, not counting the new one.
+    let counts: Vec<(&Node, usize)> = workspace
+        .nodes
+        .iter()
+        .filter(|column| column.id != new_node.id)
+        .map(|column| {
+            let windows = tiled_leaves(column)
+                .iter()
+                .filter(|leaf| leaf.id != new_node.id)
+                .count();
+            (column, windows)
+        })
+        .filter(|&(_, windows)| windows > 0)
+        .collect();
+
+    let mut commands = Vec::new();
+    if workspace.layout != NodeLayout::SplitH {
+        info!("Switching {:?} to columns", workspace.name);
+        commands.push(Command::Layout {
+            con_id: Some(workspace.nodes.first()?.id),
+            direction: SplitDirection::Horizontal,
+        });
+    }
+
+    let in_own_column = workspace.nodes.iter().any(|c| c.id == new_node.id);
+    let mut columns: Vec<i64> = counts
+        .iter()
+        .map(|(column, _)| first_leaf(column))
+        .collect();
+    if counts.len() < ratios.len() {
+        if in_own_column {
+            let index = workspace
+                .nodes
+                .iter()
+                .filter(|c| c.id == new_node.id || counts.iter().any(|(n, _)| n.id == c.id))
+                .position(|c| c.id == new_node.id)?;
+            columns.insert(index, new_node.id);
+        } else {
+            // Moving to a mark puts the window right after the marked container, in its parent.
+            let last = counts.last()?.0;
+            info!("Moving {:?} into a column of its own", new_node.name);
+            move_beside(&mut commands, new_node.id, last.id);
+            columns.push(new_node.id);
+        }
+    } else {
+        let (target, windows) = match settings.column_fill {
+            ColumnFill::Emptiest => *counts.iter().min_by_key(|&&(_, windows)| windows)?,
+            // Going by the number of windows keeps this from needing any state: once every
+            // column has one, each new window goes one column further along.
+            ColumnFill::RoundRobin => {
+                let total: usize = counts.iter().map(|&(_, windows)| windows).sum();
+                counts[total % counts.len()]
+            }
+        };
+        if find_node(new_node.id, target).is_none() {
+            info!(
+                "Moving {:?} into column {} with {} windows",
+                new_node.name, target.id, windows
+            );
+            match target.nodes.last() {
+                // i3 wraps a lone window in a container of its own when it's split.
+                None => {
+                    commands.push(Command::Focus(target.id));
+                    commands.push(Command::Split {
+                        con_id: None,
+                        direction: SplitDirection::Vertical,
+                    });
+                    move_beside(&mut commands, new_node.id, target.id);
+                }
+                Some(last) => {
+                    if target.layout != NodeLayout::SplitV {
+                        commands.push(Command::Layout {
+                            con_id: Some(last.id),
+                            direction: SplitDirection::Vertical,
+                        });
+                    }
+                    move_beside(&mut commands, new_node.id, last.id);
+                }
+            }
+        }
+    }
+
+    commands.extend(width_commands(workspace, &columns, &ratios, settings));
+    commands.push(Command::Focus(new_node.id));
+    Some(commands)
+}
+
+/// Decides how to put the columns of `workspace` back to their ratios, for when a column closed
+/// and the rest should spread out again. Returns `None` when every column is already there.
+pub fn plan_column_widths(
+    tree: &Node,
+    workspace: &Node,
+    settings: &Settings,
+) -> Option<Vec<Command>> {
+    if workspace.layout != NodeLayout::SplitH || workspace.nodes.len() < 2 {
+        return None;
+    }
+
+    let ratios = shares(
+        &column_ratios(tree, workspace, settings),
+        workspace.nodes.len(),
+    );
+    if workspace.nodes.iter().zip(&ratios).all(|(column, ratio)| {
+        (effective_percent(column, workspace) - ratio).abs() <= DRIFT_TOLERANCE
+    }) {
+        trace!("Columns of {:?} are at their ratios", workspace.name);
+        return None;
+    }
+
+    let columns: Vec<i64> = workspace.nodes.iter().map(first_leaf).collect();
+    Some(width_commands(workspace, &columns, &ratios, settings))
+}
+
+/// The column ratios for the output `workspace` is on.
+fn column_ratios(tree: &Node, workspace: &Node, settings: &Settings) -> Vec<f64> {
+    let output = find_output(workspace.id, tree).and_then(|o| o.name.as_deref());
+    settings.column_ratios(output).to_vec()
+}
+
+/// The first `count` ratios, scaled up to fill the workspace while it has fewer columns than
+/// configured. Columns past the configured ones get an equal share.
+fn shares(ratios: &[f64], count: usize) -> Vec<f64> {
+    if count > ratios.len() {
+        return vec![1.0 / count as f64; count];
+    }
+
+    let total: f64 = ratios[..count].iter().sum();
+    ratios[..count].iter().map(|ratio| ratio / total).collect()
+}
+
+/// Sets the width of each column, given by a window in it, from left to right. Each resize takes
+/// its space from the columns to its right, so the last one ends up with whatever is left.
+fn width_commands(
+    workspace: &Node,
+    columns: &[i64],
+    ratios: &[f64],
+    settings: &Settings,
+) -> Vec<Command> {
+    let shares = shares(ratios, columns.len());
+    columns
+        .iter()
+        .zip(&shares)
+        .take(columns.len().saturating_sub(1))
+        .map(|(&column, &share)| Command::Resize {
+            con_id: Some(column),
+            horizontal: true,
+            amount: match settings.resize_unit {
+                ResizeUnit::Ppt => ratio_to_ppt(share, settings.rounding),
+                ResizeUnit::Px => target_px(workspace.rect, true, share),
+            },
+            unit: settings.resize_unit,
+        })
+        .collect()
+}
+
+fn move_beside(commands: &mut Vec<Command>, con_id: i64, target: i64) {
+    commands.push(Command::Mark {
+        con_id: target,
+        mark: COLUMN_MARK.to_string(),
+    });
+    commands.push(Command::MoveToMark {
+        con_id,
+        mark: COLUMN_MARK.to_string(),
+    });
+    commands.push(Command::Unmark(COLUMN_MARK.to_string()));
+}
+
+/// The first window in the given column. A `resize set width` on any window in a column resizes
+/// the whole column.
+fn first_leaf(column: &Node) -> i64 {
+    tiled_leaves(column)
+        .first()
+        .map_or(column.id, |leaf| leaf.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{split, tree, window};
+    use crate::settings::default_settings;
+
+    fn columns_settings() -> Settings {
+        let mut settings = default_settings();
+        settings.column_ratios = vec![0.25, 0.5, 0.25];
+        settings.resize_unit = ResizeUnit::Ppt;
+        settings
+    }
+
+    fn width(con_id: i64, amount: i32) -> Command {
+        Command::Resize {
+            con_id: Some(con_id),
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Ppt,
+        }
+    }
+
+    fn moved_beside(con_id: i64, target: i64) -> Vec<Command> {
+        let mut commands = Vec::new();
+        move_beside(&mut commands, con_id, target);
+        commands
+    }
+
+    #[test]
+    fn shares_fill_the_workspace() {
+        let ratios = [0.25, 0.5, 0.25];
+        let two = shares(&ratios, 2);
+        assert!((two[0] - 1.0 / 3.0).abs() < 1e-9);
+        assert!((two[1] - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(shares(&ratios, 3), vec![0.25, 0.5, 0.25]);
+        assert_eq!(shares(&ratios, 4), vec![0.25; 4]);
+    }
+
+    #[test]
+    fn new_windows_get_a_column_of_their_own() {
+        let rect = (0, 0, 500, 500);
+        let tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(11, "A", rect), window(12, "B", rect)],
+        );
+        let new_node = find_node(12, &tree).unwrap();
+
+        assert_eq!(
+            plan_columns(&tree, new_node, &columns_settings()),
+            Some(vec![width(11, 33), Command::Focus(12)])
+        );
+    }
+
+    #[test]
+    fn windows_opened_inside_a_column_are_moved_out() {
+        let rect = (0, 0, 1000, 500);
+        let column = split(
+            20,
+            NodeLayout::SplitV,
+            rect,
+            vec![window(11, "A", rect), window(12, "B", rect)],
+        );
+        let tree = tree("1", NodeLayout::SplitH, vec![column]);
+        let new_node = find_node(12, &tree).unwrap();
+
+        let mut expected = moved_beside(12, 20);
+        expected.push(width(11, 33));
+        expected.push(Command::Focus(12));
+        assert_eq!(
+            plan_columns(&tree, new_node, &columns_settings()),
+            Some(expected)
+        );
+    }
+
+    /// A lone window, a column of two and another lone window, with window 14 just opened as a
+    /// fourth workspace child.
+    fn full_columns() -> Node {
+        let rect = (0, 0, 250, 500);
+        let middle = split(
+            20,
+            NodeLayout::SplitV,
+            rect,
+            vec![window(12, "B", rect), window(15, "E", rect)],
+        );
+        tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(11, "A", rect),
+                middle,
+                window(13, "C", rect),
+                window(14, "D", rect),
+            ],
+        )
+    }
+
+    #[test]
+    fn full_columns_take_the_emptiest() {
+        let tree = full_columns();
+        let new_node = find_node(14, &tree).unwrap();
+        let mut settings = columns_settings();
+        settings.column_fill = ColumnFill::Emptiest;
+
+        let mut expected = vec![
+            Command::Focus(11),
+            Command::Split {
+                con_id: None,
+                direction: SplitDirection::Vertical,
+            },
+        ];
+        expected.extend(moved_beside(14, 11));
+        expected.extend(vec![width(11, 25), width(12, 50), Command::Focus(14)]);
+        assert_eq!(plan_columns(&tree, new_node, &settings), Some(expected));
+    }
+
+    #[test]
+    fn full_columns_take_turns_round_robin() {
+        let tree = full_columns();
+        let new_node = find_node(14, &tree).unwrap();
+        let mut settings = columns_settings();
+        settings.column_fill = ColumnFill::RoundRobin;
+
+        let mut expected = moved_beside(14, 15);
+        expected.extend(vec![width(11, 25), width(12, 50), Command::Focus(14)]);
+        assert_eq!(plan_columns(&tree, new_node, &settings), Some(expected));
+    }
+
+    #[test]
+    fn column_widths_are_put_back_after_a_window_leaves() {
+        let rect = (0, 0, 500, 500);
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(11, "A", rect), window(12, "B", rect)],
+        );
+        let settings = columns_settings();
+        let workspace = find_workspace(11, &tree).unwrap();
+        assert_eq!(
+            plan_column_widths(&tree, workspace, &settings),
+            Some(vec![width(11, 33)])
+        );
+
+        let workspace = &mut tree.nodes[0].nodes[0];
+        workspace.nodes[0].percent = Some(1.0 / 3.0);
+        workspace.nodes[1].percent = Some(2.0 / 3.0);
+        let workspace = find_workspace(11, &tree).unwrap();
+        assert_eq!(plan_column_widths(&tree, workspace, &settings), None);
+    }
+
+    #[test]
+    fn lone_and_stacked_columns_are_left_alone() {
+        let rect = (0, 0, 1000, 500);
+        let settings = columns_settings();
+        let lone = tree("1", NodeLayout::SplitH, vec![window(11, "A", rect)]);
+        let workspace = find_workspace(11, &lone).unwrap();
+        assert_eq!(plan_column_widths(&lone, workspace, &settings), None);
+
+        let stacked = tree(
+            "1",
+            NodeLayout::Stacked,
+            vec![window(11, "A", rect), window(12, "B", rect)],
+        );
+        let workspace = find_workspace(11, &stacked).unwrap();
+        assert_eq!(plan_column_widths(&stacked, workspace, &settings), None);
+    }
+}
@@ -0,0 +1,554 @@
+use std::fmt;
+
+use i3ipc::reply::CommandOutcome;
+
+use crate::backend::Backend;
+use crate::settings::{OverflowLayout, ResizeUnit, SplitDirection};
+
+/// A single i3 command the planner wants to run. Kept structured until it's sent, so the
+/// executor can reason about what each command does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Focuses the container with the given id.
+    Focus(i64),
+    /// Makes the given container, or the focused one, split in the given direction for its next
+    /// child.
+    Split {
+        con_id: Option<i64>,
+        direction: SplitDirection,
+    },
+    /// Changes the layout of the given container's parent, or the focused container's parent.
+    /// i3 applies `layout` to the parent of whatever it targets, except for workspaces, so a
+    /// container's own layout is changed by targeting one of its children.
+    Layout {
+        con_id: Option<i64>,
+        direction: SplitDirection,
+    },
+    /// Switches the given container's parent to a stacked or tabbed layout.
+    Overflow { con_id: i64, layout: OverflowLayout },
+    /// Grows or shrinks the given container, or the focused one, along one axis by `ppt`, or by
+    /// `px` for floating containers. Negative amounts shrink.
+    ResizeBy {
+        con_id: Option<i64>,
+        horizontal: bool,
+        ppt: i32,
+        px: i32,
+    },
+    /// Exchanges the positions of two containers.
+    Swap { con_id: i64, with: i64 },
+    /// Moves the given container one step right, or down for vertical.
+    Move {
+        con_id: i64,
+        direction: SplitDirection,
+    },
+    /// Adds the given mark to a container, keeping any others it has.
+    Mark { con_id: i64, mark: String },
+    /// Moves the given container right after the container with the given mark, in its parent.
+    MoveToMark { con_id: i64, mark: String },
+    /// Removes the given mark from whichever container has it.
+    Unmark(String),
+    /// Moves the given container to the workspace with the given number.
+    MoveToWorkspace { con_id: i64, number: i32 },
+    /// Switches to the workspace with the given name.
+    Workspace(String),
+    /// Sets a container's size along one axis: the given one, or the focused one.
+    Resize {
+        con_id: Option<i64>,
+        horizontal: bool,
+        amount: i32,
+        unit: ResizeUnit,
+    },
+    /// Sets the given container's width and height at once.
+    ResizeBoth {
+        con_id: i64,
+        width: i32,
+        height: i32,
+        unit: ResizeUnit,
+    },
+    /// A command from the config, run as it is.
+    Raw(String),
+}
+
+impl Command {
+    pub fn is_resize(&self) -> bool {
+        matches!(
+            self,
+            Command::Resize { .. } | Command::ResizeBy { .. } | Command::ResizeBoth { .. }
+        )
+    }
+
+    /// Whether this command resizes horizontally, or `None` if it isn't a resize.
+    pub fn resize_horizontal(&self) -> Option<bool> {
+        match self {
+            Command::Resize { horizontal, .. } | Command::ResizeBy { horizontal, .. } => {
+                Some(*horizontal)
+            }
+            _ => None,
+        }
+    }
+
+    /// The command as the given window manager should receive it. Logs and control responses
+    /// use the i3 form from `Display`.
+    pub fn render(&self, backend: Backend) -> String {
+        match self {
+            Command::Split {
+                con_id: Some(id),
+                direction,
+            } => format!("[con_id={}] {}", id, split_command(*direction, backend)),
+            Command::Split {
+                con_id: None,
+                direction,
+            } => split_command(*direction, backend).to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Whether this command changes how containers are split, rather than their sizes.
+    pub fn is_split_phase(&self) -> bool {
+        matches!(
+            self,
+            Command::Split { .. }
+                | Command::Layout { .. }
+                | Command::Overflow { .. }
+                | Command::Swap { .. }
+                | Command::MoveToMark { .. }
+        )
+    }
+}
+
+/// The containers the given commands split, based on which container each split command targets
+/// or runs with focus on.
+pub fn split_targets(commands: &[Command]) -> Vec<i64> {
+    let mut focused = None;
+    let mut targets = Vec::new();
+    for command in commands {
+        match command {
+            Command::Focus(id) => focused = Some(*id),
+            Command::Split {
+                con_id: Some(id), ..
+            } => targets.push(*id),
+            Command::Split { con_id: None, .. } => targets.extend(focused),
+            _ => {}
+        }
+    }
+    targets
+}
+
+/// Merges each pair of adjacent resizes that set the same container's width and height into a
+/// single `ResizeBoth`. Everything else is left as it is.
+pub fn combine_resizes(commands: &[Command]) -> Vec<Command> {
+    let mut combined: Vec<Command> = Vec::with_capacity(commands.len());
+    for command in commands {
+        let merged = match (combined.last(), command) {
+            (
+                Some(Command::Resize {
+                    con_id: Some(first),
+                    horizontal: first_horizontal,
+                    amount: first_amount,
+                    unit: first_unit,
+                }),
+                Command::Resize {
+                    con_id: Some(second),
+                    horizontal,
+                    amount,
+                    unit,
+                },
+            ) if first == second && first_horizontal != horizontal && first_unit == unit => {
+                let (width, height) = if *horizontal {
+                    (*amount, *first_amount)
+                } else {
+                    (*first_amount, *amount)
+                };
+                Some(Command::ResizeBoth {
+                    con_id: *first,
+                    width,
+                    height,
+                    unit: *unit,
+                })
+            }
+            _ => None,
+        };
+        match merged {
+            Some(merged) => *combined.last_mut().unwrap() = merged,
+            None => combined.push(command.clone()),
+        }
+    }
+    combined
+}
+
+/// Renders a `;`-separated list of commands from the config, filling in `{name}` placeholders
+/// with `lookup`. Values are escaped so that they can be used within double quotes, and `;`
+/// within double quotes doesn't separate commands. Commands using a placeholder `lookup` has no
+/// value for are left out, with a warning.
+pub fn render_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> Vec<Command> {
+    let mut commands = Vec::new();
+    'commands: for part in split_template(template) {
+        let mut rendered = String::new();
+        let mut rest = part.trim();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => {
+                    rest = &rest[start..];
+                    break;
+                }
+            };
+            let name = &rest[start + 1..end];
+            match lookup(name) {
+                Some(value) => rendered.push_str(&escape(&value)),
+                None => {
+                    warn!("No value for {{{}}}, not running {:?}", name, part.trim());
+                    continue 'commands;
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        rendered.push_str(rest);
+
+        if !rendered.is_empty() {
+            commands.push(Command::Raw(rendered));
+        }
+    }
+    commands
+}
+
+/// Splits a template at every `;` outside double quotes.
+fn split_template(template: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in template.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&template[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&template[start..]);
+    parts
+}
+
+/// Escapes backslashes and double quotes, for use within a double-quoted i3 string.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The command that splits the focused container in `direction`. i3 has always taken
+/// `split vertical`, while some sway versions only recognise the short `splitv` form.
+pub fn split_command(direction: SplitDirection, backend: Backend) -> &'static str {
+    match (backend, direction) {
+        (Backend::I3, SplitDirection::Horizontal) => "split horizontal",
+        (Backend::I3, SplitDirection::Vertical) => "split vertical",
+        (Backend::Sway, SplitDirection::Horizontal) => "splith",
+        (Backend::Sway, SplitDirection::Vertical) => "splitv",
+    }
+}
+
+/// i3's explanation for the first command in a reply that failed, if any did. A chained
+/// command gets one outcome per part, and i3 doesn't always say why one failed.
+pub fn failed_outcome(outcomes: &[CommandOutcome]) -> Option<String> {
+    outcomes.iter().find(|o| !o.success).map(|o| {
+        o.error
+            .clone()
+            .unwrap_or_else(|| "no reason given".to_string())
+    })
+}
+
+/// Renders a command list for logs and control responses.
+pub fn render_all(commands: &[Command]) -> Vec<String> {
+    commands.iter().map(Command::to_string).collect()
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // `id` in i3 criteria is the X11 window id; node ids have to be matched with `con_id`.
+            Command::Focus(id) => write!(f, "[con_id={}] focus", id),
+            Command::Split { con_id, direction } => {
+                if let Some(id) = con_id {
+                    write!(f, "[con_id={}] ", id)?;
+                }
+                write!(f, "{}", split_command(*direction, Backend::I3))
+            }
+            Command::Layout { con_id, direction } => {
+                if let Some(id) = con_id {
+                    write!(f, "[con_id={}] ", id)?;
+                }
+                match direction {
+                    SplitDirection::Horizontal => write!(f, "layout splith"),
+                    SplitDirection::Vertical => write!(f, "layout splitv"),
+                }
+            }
+            Command::Overflow { con_id, layout } => write!(
+                f,
+                "[con_id={}] layout {}",
+                con_id,
+                match layout {
+                    OverflowLayout::Tabbed => "tabbed",
+                    _ => "stacking",
+                }
+            ),
+            Command::ResizeBy {
+                con_id,
+                horizontal,
+                ppt,
+                px,
+            } => {
+                if let Some(id) = con_id {
+                    write!(f, "[con_id={}] ", id)?;
+                }
+                write!(
+                    f,
+                    "resize {} {} {} px or {} ppt",
+                    if *ppt < 0 || (*ppt == 0 && *px < 0) {
+                        "shrink"
+                    } else {
+                        "grow"
+                    },
+                    if *horizontal { "width" } else { "height" },
+                    px.abs(),
+                    ppt.abs()
+                )
+            }
+            Command::Swap { con_id, with } => {
+                write!(f, "[con_id={}] swap container with con_id {}", con_id, with)
+            }
+            Command::Move { con_id, direction } => write!(
+                f,
+                "[con_id={}] move {}",
+                con_id,
+                match direction {
+                    SplitDirection::Horizontal => "right",
+                    SplitDirection::Vertical => "down",
+                }
+            ),
+            Command::Mark { con_id, mark } => {
+                write!(f, "[con_id={}] mark --add \"{}\"", con_id, escape(mark))
+            }
+            Command::MoveToMark { con_id, mark } => write!(
+                f,
+                "[con_id={}] move container to mark \"{}\"",
+                con_id,
+                escape(mark)
+            ),
+            Command::Unmark(mark) => write!(f, "unmark \"{}\"", escape(mark)),
+            Command::MoveToWorkspace { con_id, number } => write!(
+                f,
+                "[con_id={}] move container to workspace number {}",
+                con_id, number
+            ),
+            Command::Workspace(name) => write!(f, "workspace \"{}\"", escape(name)),
+            Command::Resize {
+                con_id,
+                horizontal,
+                amount,
+                unit,
+            } => {
+                if let Some(id) = con_id {
+                    write!(f, "[con_id={}] ", id)?;
+                }
+                write!(
+                    f,
+                    "resize set {} {} {}",
+                    if *horizontal { "width" } else { "height" },
+                    amount,
+                    match unit {
+                        ResizeUnit::Ppt => "ppt",
+                        ResizeUnit::Px => "px",
+                    }
+                )
+            }
+            Command::ResizeBoth {
+                con_id,
+                width,
+                height,
+                unit,
+            } => {
+                let unit = match unit {
+                    ResizeUnit::Ppt => "ppt",
+                    ResizeUnit::Px => "px",
+                };
+                write!(
+                    f,
+                    "[con_id={}] resize set width {} {} height {} {}",
+                    con_id, width, unit, height, unit
+                )
+            }
+            Command::Raw(command) => write!(f, "{}", command),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resize(con_id: i64, horizontal: bool, amount: i32, unit: ResizeUnit) -> Command {
+        Command::Resize {
+            con_id: Some(con_id),
+            horizontal,
+            amount,
+            unit,
+        }
+    }
+
+    #[test]
+    fn width_and_height_of_one_container_are_combined() {
+        let combined = combine_resizes(&[
+            resize(5, false, 40, ResizeUnit::Ppt),
+            resize(5, true, 60, ResizeUnit::Ppt),
+        ]);
+        assert_eq!(
+            combined,
+            vec![Command::ResizeBoth {
+                con_id: 5,
+                width: 60,
+                height: 40,
+                unit: ResizeUnit::Ppt,
+            }]
+        );
+        assert_eq!(
+            combined[0].to_string(),
+            "[con_id=5] resize set width 60 ppt height 40 ppt"
+        );
+    }
+
+    #[test]
+    fn other_resizes_are_left_alone() {
+        let commands = vec![
+            // Different containers.
+            resize(5, true, 60, ResizeUnit::Ppt),
+            resize(6, false, 40, ResizeUnit::Ppt),
+            // The same axis twice.
+            resize(7, true, 60, ResizeUnit::Ppt),
+            resize(7, true, 50, ResizeUnit::Ppt),
+            // Different units.
+            resize(8, true, 60, ResizeUnit::Ppt),
+            resize(8, false, 300, ResizeUnit::Px),
+            // Not adjacent.
+            resize(9, true, 60, ResizeUnit::Ppt),
+            Command::Focus(9),
+            resize(9, false, 40, ResizeUnit::Ppt),
+        ];
+        assert_eq!(combine_resizes(&commands), commands);
+    }
+
+    #[test]
+    fn only_a_pair_is_combined() {
+        let combined = combine_resizes(&[
+            resize(5, true, 60, ResizeUnit::Ppt),
+            resize(5, false, 40, ResizeUnit::Ppt),
+            resize(5, true, 50, ResizeUnit::Ppt),
+        ]);
+        assert_eq!(combined.len(), 2);
+        assert_eq!(combined[1], resize(5, true, 50, ResizeUnit::Ppt));
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "id" => Some("94".to_string()),
+            "title" => Some("say \"hi\"; or not".to_string()),
+            _ => None,
+        }
+    }
+
+    fn raw(commands: &[&str]) -> Vec<Command> {
+        commands
+            .iter()
+            .map(|command| Command::Raw(command.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn templates_fill_in_placeholders() {
+        assert_eq!(
+            render_template("[con_id={id}] border pixel 2; mark main", lookup),
+            raw(&["[con_id=94] border pixel 2", "mark main"])
+        );
+    }
+
+    #[test]
+    fn template_values_are_escaped() {
+        assert_eq!(
+            render_template("title_format \"{title}\"", lookup),
+            raw(&["title_format \"say \\\"hi\\\"; or not\""])
+        );
+    }
+
+    #[test]
+    fn commands_with_unknown_placeholders_are_left_out() {
+        assert_eq!(
+            render_template("mark {nope}; [con_id={id}] floating disable", lookup),
+            raw(&["[con_id=94] floating disable"])
+        );
+    }
+
+    #[test]
+    fn unclosed_braces_are_kept() {
+        assert_eq!(
+            render_template("exec echo {id", lookup),
+            raw(&["exec echo {id"])
+        );
+    }
+
+    #[test]
+    fn templates_split_outside_quotes_only() {
+        assert_eq!(
+            split_template("exec \"a; b\"; mark \"x\\\";\"; nop"),
+            vec!["exec \"a; b\"", " mark \"x\\\";\"", " nop"]
+        );
+        assert_eq!(split_template(""), vec![""]);
+        assert!(render_template(" ; ", lookup).is_empty());
+    }
+
+    #[test]
+    fn splits_are_rendered_for_each_backend() {
+        let split = |con_id, direction| Command::Split { con_id, direction };
+        let cases = [
+            (Backend::I3, SplitDirection::Horizontal, "split horizontal"),
+            (Backend::I3, SplitDirection::Vertical, "split vertical"),
+            (Backend::Sway, SplitDirection::Horizontal, "splith"),
+            (Backend::Sway, SplitDirection::Vertical, "splitv"),
+        ];
+        for &(backend, direction, expected) in &cases {
+            assert_eq!(split(None, direction).render(backend), expected);
+            assert_eq!(
+                split(Some(7), direction).render(backend),
+                format!("[con_id=7] {}", expected)
+            );
+        }
+
+        // Everything else renders the same on both.
+        let focus = Command::Focus(7);
+        assert_eq!(focus.render(Backend::Sway), focus.render(Backend::I3));
+    }
+
+    #[test]
+    fn the_first_failure_gives_its_reason() {
+        let outcome = |success, error: Option<&str>| CommandOutcome {
+            success,
+            error: error.map(String::from),
+        };
+        assert_eq!(failed_outcome(&[]), None);
+        assert_eq!(failed_outcome(&[outcome(true, None)]), None);
+        assert_eq!(
+            failed_outcome(&[
+                outcome(true, None),
+                outcome(false, Some("Cannot resize: this is the only child")),
+                outcome(false, Some("No such mark")),
+            ]),
+            Some("Cannot resize: this is the only child".to_string())
+        );
+        assert_eq!(
+            failed_outcome(&[outcome(false, None)]),
+            Some("no reason given".to_string())
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::daemon::Message;
+
+/// The structured reply the daemon sends for every control request, serialized as a single
+/// JSON line.
+#[derive(Debug)]
+pub struct Response {
+    pub ok: bool,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+impl Response {
+    pub fn ok(message: impl Into<String>) -> Response {
+        Response {
+            ok: true,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Response {
+        Response {
+            ok: false,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(mut self, data: Value) -> Response {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = json!({
+            "ok": self.ok,
+            "message": self.message,
+            "data": self.data,
+        })
+        .to_string();
+        line.push('\n');
+        line
+    }
+
+    pub fn from_line(line: &str) -> Option<Response> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        Some(Response {
+            ok: value.get("ok")?.as_bool()?,
+            message: value.get("message")?.as_str()?.to_string(),
+            data: value.get("data").filter(|d| !d.is_null()).cloned(),
+        })
+    }
+}
+
+/// The location of the daemon's control socket. Uses `$XDG_RUNTIME_DIR` when available, as
+/// that is private to the user, and falls back to a per-user path in `/tmp`.
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("ratiosplit.sock"),
+        None => PathBuf::from(format!(
+            "/tmp/ratiosplit-{}.sock",
+            env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// The location of the control fifo, next to the control socket.
+pub fn fifo_path() -> PathBuf {
+    socket_path().with_extension("fifo")
+}
+
+/// The longest request line taken from the fifo. Longer lines are dropped.
+const MAX_FIFO_LINE: usize = 4096;
+
+/// Creates the control fifo and forwards every line written to it to the daemon's main loop,
+/// without anywhere to write the response. Any number of writers can come and go.
+pub fn spawn_fifo(sender: Sender<Message>) -> io::Result<()> {
+    spawn_fifo_at(fifo_path(), sender)
+}
+
+fn spawn_fifo_at(path: PathBuf, sender: Sender<Message>) -> io::Result<()> {
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    info!("Listening for control requests on {:?}", path);
+
+    thread::spawn(move || loop {
+        // Opening blocks until a writer shows up, and reading ends once every writer has closed
+        // the fifo, after which it's opened again for the next one.
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                warn!("Error opening the control fifo, closing it: {:?}", error);
+                return;
+            }
+        };
+        let result = read_lines(BufReader::new(file), |line| {
+            let _ = sender.send(Message::Control(line, None));
+        });
+        if let Err(error) = result {
+            warn!("Error reading the control fifo: {:?}", error);
+        }
+    });
+
+    Ok(())
+}
+
+/// Reads request lines until the end of the input, passing each non-empty one to `handle`. A last
+/// line without a newline still counts, while lines over `MAX_FIFO_LINE` bytes or that aren't
+/// UTF-8 are dropped with a warning.
+fn read_lines(mut reader: impl BufRead, mut handle: impl FnMut(String)) -> io::Result<()> {
+    loop {
+        let mut line = Vec::new();
+        let read = (&mut reader)
+            .take(MAX_FIFO_LINE as u64 + 1)
+            .read_until(b'\n', &mut line)?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        if line.len() > MAX_FIFO_LINE && !line.ends_with(b"\n") {
+            warn!(
+                "Dropping a control request longer than {} bytes",
+                MAX_FIFO_LINE
+            );
+            skip_line(&mut reader)?;
+            continue;
+        }
+
+        match String::from_utf8(line) {
+            Ok(line) if !line.trim().is_empty() => handle(line.trim().to_string()),
+            Ok(_) => {}
+            Err(_) => warn!("Dropping a control request that isn't UTF-8"),
+        }
+    }
+}
+
+/// Discards the input up to and including the next newline, without holding on to it.
+fn skip_line(reader: &mut impl BufRead) -> io::Result<()> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        match buffer.iter().position(|&b| b == b'\n') {
+            Some(newline) => {
+                reader.consume(newline + 1);
+                return Ok(());
+            }
+            None => {
+                let length = buffer.len();
+                reader.consume(length);
+            }
+        }
+    }
+}
+
+/// Binds the control socket and forwards every request line to the daemon's main loop, along
+/// with the stream the response should be written to.
+pub fn spawn_server(sender: Sender<Message>) -> io::Result<()> {
+    let path = socket_path();
+    if path.exists() {
+        // Either a previous daemon crashed, or one is still running. Only replace the socket
+        // if nothing answers on it.
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another daemon is listening on {:?}", path),
+            ));
+        }
+        fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    info!("Listening for control requests on {:?}", path);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(error) => {
+                    warn!("Error accepting control connection: {:?}", error);
+                    continue;
+                }
+            };
+
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let mut reader = match stream.try_clone() {
+                    Ok(s) => BufReader::new(s),
+                    Err(error) => {
+                        warn!("Error cloning control connection: {:?}", error);
+                        return;
+                    }
+                };
+
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) => {}
+                    Ok(_) => {
+                        let _ =
+                            sender.send(Message::Control(line.trim().to_string(), Some(stream)));
+                    }
+                    Err(error) => warn!("Error reading control request: {:?}", error),
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Removes the control socket, used on shutdown by the daemon that bound it.
+pub fn remove_socket() {
+    let _ = fs::remove_file(socket_path());
+}
+
+/// Removes the control fifo, used on shutdown by the daemon that created it.
+pub fn remove_fifo() {
+    let _ = fs::remove_file(fifo_path());
+}
+
+/// Opens a connection to the daemon and sends the given request line, without waiting for a
+/// response.
+pub fn connect(line: &str) -> io::Result<BufReader<UnixStream>> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.write_all(line.as_bytes())?;
+    stream.write_all(b"\n")?;
+    Ok(BufReader::new(stream))
+}
+
+/// Sends a single request to the running daemon and waits for its response.
+pub fn request(line: &str) -> io::Result<Response> {
+    let mut reader = connect(line)?;
+    let mut response = String::new();
+    reader.read_line(&mut response)?;
+    Response::from_line(&response).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed response from daemon: {:?}", response),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses_round_trip() {
+        let line = Response::error("no such command").to_line();
+        assert!(line.ends_with('\n'));
+        let response = Response::from_line(&line).unwrap();
+        assert!(!response.ok);
+        assert_eq!(response.message, "no such command");
+        assert_eq!(response.data, None);
+
+        let line = Response::ok("status")
+            .with_data(json!({ "paused": false }))
+            .to_line();
+        let response = Response::from_line(&line).unwrap();
+        assert!(response.ok);
+        assert_eq!(response.message, "status");
+        assert_eq!(response.data, Some(json!({ "paused": false })));
+    }
+
+    fn lines(input: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        read_lines(io::Cursor::new(input), |line| lines.push(line)).unwrap();
+        lines
+    }
+
+    #[test]
+    fn request_lines_are_read_until_the_end() {
+        assert_eq!(
+            lines(b"pause\n\n  \nresume  \nstatus"),
+            vec!["pause", "resume", "status"]
+        );
+        assert!(lines(b"").is_empty());
+    }
+
+    #[test]
+    fn oversized_and_binary_lines_are_dropped() {
+        let mut input = vec![b'x'; MAX_FIFO_LINE * 3];
+        input.extend_from_slice(b"\npause\n\xff\xfe\nresume\n");
+        assert_eq!(lines(&input), vec!["pause", "resume"]);
+    }
+
+    #[test]
+    fn fifo_requests_reach_the_daemon_across_writers() {
+        let path = env::temp_dir().join(format!("ratiosplit-control-{}.fifo", std::process::id()));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        spawn_fifo_at(path.clone(), sender).unwrap();
+
+        // Each writer opens the fifo, writes and closes it again, like `echo pause > fifo`.
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            for request in &["pause", "resume", "toggle"] {
+                let mut fifo = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&writer_path)
+                    .unwrap();
+                fifo.write_all(format!("{}\n", request).as_bytes()).unwrap();
+            }
+        });
+
+        let mut requests = Vec::new();
+        for _ in 0..3 {
+            match receiver.recv_timeout(std::time::Duration::from_secs(5)) {
+                Ok(Message::Control(line, None)) => requests.push(line),
+                _ => panic!("no request from the fifo after {:?}", requests),
+            }
+        }
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(requests, vec!["pause", "resume", "toggle"]);
+    }
+
+    #[test]
+    fn malformed_responses_are_rejected() {
+        assert!(Response::from_line("not json").is_none());
+        assert!(Response::from_line("{\"ok\":true}").is_none());
+        assert!(Response::from_line("{\"ok\":\"yes\",\"message\":\"\"}").is_none());
+    }
+}
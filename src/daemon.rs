@@ -0,0 +1,3720 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use i3ipc::{
+    event::{
+        inner::{WindowChange, WorkspaceChange},
+        BindingEventInfo, Event, WindowEventInfo, WorkspaceEventInfo,
+    },
+    reply::{Node, NodeLayout, NodeType},
+    EstablishError, I3Connection, I3EventListener, MessageError, Subscription,
+};
+use serde_json::{json, Value};
+
+use crate::animate::Animation;
+use crate::backend::{detect_backend, restrict_settings, Backend, Capabilities};
+use crate::columns::plan_column_widths;
+use crate::command::{combine_resizes, failed_outcome, render_all, split_targets, Command};
+use crate::control::{self, Response};
+use crate::diagnostics::{
+    current_event, render_rules, render_table, summarize_tree, verify_line, ActionLog, EventRecord,
+    EventScope, ExplainScope, Explanation, FailureLog,
+};
+use crate::hooks::{notify, Hooks};
+use crate::ipc::{Extras, TreeConnection};
+use crate::marks::{managed_mark, sweep};
+use crate::planner::{
+    effective_ratio, hold_focus, plan_animation, plan_commands, plan_convert, plan_escape,
+    plan_focus_master, plan_fullscreen_change, plan_hook, plan_locks, plan_overflow_move,
+    plan_overflow_restore, plan_promote, plan_repair, plan_restore, plan_sibling_resize,
+    plan_title_ratio, plan_workspace, snapshot, verify_resize, Conversion, Decision, SkipReason,
+    Snapshot, Verification, DRIFT_TOLERANCE, RATIO_LOCK_PREFIX,
+};
+use crate::procinfo::{x11_window_pid, ProcessNames};
+use crate::settings::{
+    legacy_log_file, state_log_file, Mode, PreCommandFailure, ResizeNoop, Settings, SplitDirection,
+};
+use crate::state::{load_state, prune_remembered, rename_remembered, save_state, State};
+use crate::tree::{
+    container_path, effective_percent, find_focused, find_node, find_output, find_parent,
+    find_workspace, has_placeholders, is_docked, is_floating_focused, shown_fullscreen,
+    split_direction, split_pairs, window_class, window_identity, window_ids, workspaces,
+};
+
+/// The commands which, when run from a binding, mean the user is arranging a workspace by hand.
+const MANUAL_LAYOUT_COMMANDS: [&str; 6] = ["move", "resize", "layout", "split", "splith", "splitv"];
+
+/// Everything the main loop reacts to. i3 events and control requests arrive on separate
+/// threads and are funneled through a single channel so that all state is owned by the loop.
+pub enum Message {
+    I3(Box<Result<Event, MessageError>>),
+    /// A control request, with the stream to answer it on. Requests from the fifo have none.
+    Control(String, Option<UnixStream>),
+    /// SIGTERM or SIGINT arrived, and the daemon should clean up and exit.
+    Stop(i32),
+}
+
+struct Daemon {
+    connection: I3Connection,
+    /// Where the tree is fetched from, along with its `extras`.
+    tree_connection: TreeConnection,
+    /// The fields i3ipc doesn't expose for the tree fetched last.
+    extras: Extras,
+    backend: Backend,
+    capabilities: Capabilities,
+    settings: Settings,
+    state: State,
+    subscribers: Vec<UnixStream>,
+    /// When the user last changed each workspace's layout by hand, keyed by workspace name.
+    manual_changes: HashMap<String, Instant>,
+    /// When to next log suggestions, in suggest mode.
+    next_suggestion: Option<Instant>,
+    /// When to next put drifted workspaces back, with `reassert_interval_secs`.
+    next_reassert: Option<Instant>,
+    /// Containers switched to `overflow_layout`, with the split direction they had before.
+    overflowed: HashMap<i64, SplitDirection>,
+    /// When recent window events arrived, for detecting bursts of activity.
+    recent_events: VecDeque<Instant>,
+    /// When quiet mode ends, if it is active. Pushed back by every event that arrives meanwhile.
+    quiet_until: Option<Instant>,
+    /// Windows handled recently enough to be repaired if a `for_window` rule floats or moves them.
+    pending: HashMap<i64, PendingWindow>,
+    /// New windows waiting for their class, and when to stop waiting.
+    awaiting_class: HashMap<i64, Timer>,
+    /// New windows waiting out `handle_delay_ms`, and when to handle them.
+    delayed: HashMap<i64, Timer>,
+    /// Windows handled recently enough for a title change to give them a `[title_ratios]` ratio,
+    /// with the parent they were handled in and when that stops.
+    titled: HashMap<i64, (i64, Instant)>,
+    hooks: Hooks,
+    /// The id the last event from i3 was given, for telling their log lines apart.
+    last_event_id: u64,
+    /// Names of the processes owning windows, for `exclude_processes`.
+    processes: ProcessNames,
+    /// What was done for recent events, for `history`.
+    actions: ActionLog,
+    /// The most recent layout changes, oldest first.
+    history: VecDeque<UndoEntry>,
+    /// Running resize animations, keyed by the parent of the container being resized.
+    animations: HashMap<i64, Animation>,
+    /// Windows created during quiet mode, whose workspaces are rebalanced once it ends.
+    deferred: Vec<i64>,
+    /// Resizes waiting to be checked, when `verify` is on.
+    verifications: Vec<PendingVerification>,
+    /// How many resizes were still wrong after all their retries.
+    verify_failures: u64,
+    /// With `mode = remember`, the last child of each two-child split container and its share as
+    /// ratiosplit last saw them, for noticing resizes made without a key binding, like with the
+    /// mouse.
+    observed: HashMap<i64, (i64, f64)>,
+    /// The last few commands i3 refused, for `status`.
+    failures: FailureLog,
+    /// How many commands have been run in all, to tell whether handling an event ran any.
+    commands_run: u64,
+    /// Events handled in a row that i3 refused commands for, for `failsafe_threshold`.
+    failed_handlings: u32,
+    /// The window focused last with `mode = focus_master`, and when to enlarge it.
+    focus_pending: Option<(i64, Timer)>,
+    /// Windows ratiosplit focused itself, and when, so that the focus events this causes aren't
+    /// taken for the user's.
+    own_focus: HashMap<i64, Instant>,
+    /// Workspaces a layout was restored on recently, and when a window last arrived there.
+    restores: HashMap<String, Instant>,
+    /// The current name of every workspace, by id, for following renames.
+    workspace_names: HashMap<i64, String>,
+    /// Every open window ratiosplit knows of, for telling windows moved in from elsewhere apart
+    /// from ones it has never seen. Windows are forgotten when they close.
+    seen: HashSet<i64>,
+    /// Whether fetching the tree failed, which ends the main loop.
+    lost_connection: bool,
+}
+
+/// How long after the last window arrives on a workspace with a restored layout the restore is
+/// taken to be over.
+const RESTORE_GRACE: Duration = Duration::from_secs(2);
+
+/// How long after ratiosplit focuses a window a focus event for it is put down to ratiosplit.
+const OWN_FOCUS_WINDOW: Duration = Duration::from_millis(500);
+
+/// How many layout changes `undo` can step back through.
+const UNDO_HISTORY: usize = 10;
+
+/// The verbs the control socket understands, listed when it gets one it doesn't.
+const CONTROL_VERBS: [&str; 16] = [
+    "status",
+    "pause",
+    "resume",
+    "toggle",
+    "cycle-ratio",
+    "undo",
+    "explain",
+    "tree",
+    "promote",
+    "lock",
+    "unlock",
+    "set-mode",
+    "history",
+    "rules",
+    "suggest",
+    "subscribe",
+];
+
+/// What was done for a recently handled window, so that it can be undone if the window leaves.
+struct PendingWindow {
+    before: Snapshot,
+    expires: Instant,
+}
+
+/// When something put off is due, and the event it was put off while handling, so that what's
+/// logged when it's done is told apart as part of that event.
+#[derive(Debug, Clone, Copy)]
+struct Timer {
+    due: Instant,
+    event: Option<u64>,
+}
+
+impl Timer {
+    fn after(delay: Duration) -> Timer {
+        Timer {
+            due: Instant::now() + delay,
+            event: current_event(),
+        }
+    }
+}
+
+/// The windows among `timers` whose timers are due by `now`, earliest first.
+fn due_timers(timers: &HashMap<i64, Timer>, now: Instant) -> Vec<(i64, Timer)> {
+    let mut due: Vec<(i64, Timer)> = timers
+        .iter()
+        .filter(|(_, timer)| timer.due <= now)
+        .map(|(&id, &timer)| (id, timer))
+        .collect();
+    due.sort_by_key(|(_, timer)| timer.due);
+    due
+}
+
+/// The number of events in a row i3 refused commands for, after handling one changed the counts
+/// of refused and run commands from `before` to `after`.
+fn failed_in_a_row(count: u32, (failures, commands): (u64, u64), after: (u64, u64)) -> u32 {
+    if after.0 > failures {
+        count + 1
+    } else if after.1 > commands {
+        0
+    } else {
+        count
+    }
+}
+
+/// Whether that many failed events in a row pause ratiosplit, with `failsafe_threshold`.
+fn failsafe_trips(failed_in_a_row: u32, settings: &Settings) -> bool {
+    settings.failsafe_threshold != 0 && failed_in_a_row >= settings.failsafe_threshold
+}
+
+/// Whether a new window is still arranged once one of its `pre_split_command` commands failed,
+/// with `pre_command_failure`. The rest of the commands aren't run either way.
+fn arranges_after_failed_pre_split(settings: &Settings) -> bool {
+    settings.pre_command_failure == PreCommandFailure::Continue
+}
+
+/// How much longer a workspace changed by hand at `changed` is left alone at `now`, with
+/// `manual_cooldown`.
+fn cooldown_remaining(changed: Instant, now: Instant, settings: &Settings) -> Option<Duration> {
+    let cooldown = Duration::from_secs(settings.manual_cooldown);
+    cooldown
+        .checked_sub(now.saturating_duration_since(changed))
+        .filter(|d| !d.is_zero())
+}
+
+/// A resize to check once i3 has had time to apply it.
+struct PendingVerification {
+    parent: i64,
+    node: i64,
+    resize_horizontal: bool,
+    ratio: f64,
+    retries_left: u32,
+    due: Instant,
+    /// The event the resize was made for.
+    event: Option<u64>,
+    /// Whether this is the `settle_ms` check rather than `verify`, which only retries once and
+    /// doesn't count as a failure when that doesn't help either.
+    settle: bool,
+    class: Option<String>,
+}
+
+/// A layout change ratiosplit made, and the state it can be undone to.
+struct UndoEntry {
+    description: String,
+    before: Snapshot,
+}
+
+/// Why the daemon stopped, other than being shut down cleanly. Each has its own exit code, so
+/// that supervisors can tell them apart, and for example restart after a lost connection but not
+/// after a configuration mistake.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitReason {
+    /// Something went wrong that there's no specific code for.
+    Fatal = 1,
+    /// The command line or the config can't be used.
+    Config = 2,
+    /// i3 couldn't be reached, or the connection to it was lost.
+    Connection = 3,
+    /// i3 wouldn't send the events ratiosplit needs.
+    Subscription = 4,
+    /// i3 is older than `MIN_I3_VERSION`.
+    Unsupported = 5,
+}
+
+impl ExitReason {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+pub fn run(mut settings: Settings, fresh: bool, watch: bool) -> Result<(), ExitReason> {
+    info!("Starting i3 ratiosplit, connecting to i3");
+
+    let (mut connection, tree_connection, mut listener) = match setup_i3_connection() {
+        Ok(t) => t,
+        Err(error) => {
+            error!("{}", error);
+            return Err(ExitReason::Connection);
+        }
+    };
+
+    let (backend, capabilities) = match detect_backend(&mut connection) {
+        Ok(detected) => detected,
+        Err(error) => {
+            error!("{}", error);
+            return Err(ExitReason::Unsupported);
+        }
+    };
+    info!("Detected backend {} with {:?}", backend, capabilities);
+    if let Some(expected) = settings.backend_guard {
+        if backend != expected {
+            info!(
+                "backend_guard expects {} but running on {}, exiting",
+                expected, backend
+            );
+            return Ok(());
+        }
+    }
+    restrict_settings(&mut settings, &capabilities);
+
+    wait_for_tree(&mut connection);
+
+    let events = [
+        Subscription::Window,
+        Subscription::Binding,
+        Subscription::Workspace,
+        Subscription::Shutdown,
+    ];
+    info!("Subscribing to events: {:?}", events);
+    if let Err(error) = listener.subscribe(&events) {
+        error!("Error subscribing to events: {:?}", error);
+        return Err(ExitReason::Subscription);
+    }
+
+    let (sender, receiver) = channel();
+
+    // Before any other thread starts, so that they all leave these signals to the listener.
+    if let Err(error) = spawn_signal_listener(sender.clone()) {
+        warn!(
+            "Error setting up signal handling, stopping won't clean up: {:?}",
+            error
+        );
+    }
+
+    // A watching daemon runs next to the real one, which keeps the control channels.
+    // Only what this daemon created is removed on exit, not another daemon's.
+    let mut another_daemon = false;
+    let mut bound_socket = false;
+    let mut made_fifo = false;
+    if settings.control.socket() && !watch {
+        match control::spawn_server(sender.clone()) {
+            Ok(()) => bound_socket = true,
+            Err(error) => {
+                another_daemon = error.kind() == io::ErrorKind::AddrInUse;
+                warn!(
+                    "Error starting control server, control commands are unavailable: {:?}",
+                    error
+                );
+            }
+        }
+    }
+    // The fifo would be taken away from the daemon that has the socket.
+    if another_daemon {
+        warn!("Leaving the control fifo to the other daemon");
+    } else if settings.control.fifo() && !watch {
+        match control::spawn_fifo(sender.clone()) {
+            Ok(()) => made_fifo = true,
+            Err(error) => warn!(
+                "Error creating the control fifo, it is unavailable: {:?}",
+                error
+            ),
+        }
+    }
+
+    thread::spawn(move || {
+        for event in listener.listen() {
+            if sender.send(Message::I3(Box::new(event))).is_err() {
+                return;
+            }
+        }
+    });
+
+    let state = if fresh {
+        info!("Ignoring saved state");
+        State::default()
+    } else {
+        load_state()
+    };
+
+    if let Some(&ratio) = state
+        .ratio_preset
+        .and_then(|index| settings.ratio_presets.get(index))
+    {
+        info!("Restoring ratio preset {}", ratio);
+        settings.set_ratio(ratio);
+    }
+
+    let mut daemon = Daemon::new(
+        connection,
+        tree_connection,
+        backend,
+        capabilities,
+        settings,
+        state,
+        watch,
+    );
+    daemon.hooks.run("connected", &[]);
+
+    // The control socket and fifo are cleaned up either way.
+    let result = match daemon.get_tree() {
+        Some(tree) => {
+            daemon.prune_state(&tree, None);
+            daemon.seen = window_ids(&tree).into_iter().collect();
+            daemon.workspace_names = workspaces(&tree)
+                .into_iter()
+                .filter_map(|w| Some((w.id, w.name.clone()?)))
+                .collect();
+            daemon.observe(&tree);
+            daemon.schedule_suggestion();
+            daemon.schedule_reassert();
+            daemon.notice_legacy_log();
+            daemon.run_loop(receiver)
+        }
+        None => Err(ExitReason::Connection),
+    };
+    if bound_socket {
+        control::remove_socket();
+    }
+    if made_fifo {
+        control::remove_fifo();
+    }
+    result
+}
+
+/// Turns SIGTERM and SIGINT into `Message::Stop`, so that the daemon cleans up before exiting
+/// instead of dying wherever it is. The signals are blocked in this thread, which every thread
+/// started later inherits, and waited for in a thread of their own.
+fn spawn_signal_listener(sender: Sender<Message>) -> io::Result<()> {
+    // SAFETY: the set is initialized by sigemptyset before it's used, and only read afterwards.
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        let result = libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+        if result != 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        signals
+    };
+
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        // SAFETY: both pointers are to locals that outlive the call.
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            let _ = sender.send(Message::Stop(signal));
+            return;
+        }
+    });
+    Ok(())
+}
+
+/// How long to wait at startup for i3 to set up its outputs and workspaces.
+const TREE_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the tree is checked while waiting for it.
+const TREE_READY_POLL: Duration = Duration::from_millis(100);
+
+/// Waits until i3's tree has at least one workspace. Started at login, ratiosplit can connect
+/// before i3 has set up its outputs, and windows handled against that tree are mishandled.
+/// Carries on regardless after `TREE_READY_TIMEOUT`.
+fn wait_for_tree(connection: &mut I3Connection) {
+    let deadline = Instant::now() + TREE_READY_TIMEOUT;
+    let mut logged = false;
+    loop {
+        match connection.get_tree() {
+            Ok(tree) if !workspaces(&tree).is_empty() => {
+                if logged {
+                    info!("i3's tree is ready");
+                }
+                return;
+            }
+            Ok(_) => {}
+            Err(error) => warn!("Error retrieving the tree at startup: {:?}", error),
+        }
+
+        if Instant::now() >= deadline {
+            warn!(
+                "i3 still has no workspaces after {:?}, starting anyway",
+                TREE_READY_TIMEOUT
+            );
+            return;
+        }
+        if !logged {
+            info!("i3 has no workspaces yet, waiting for its tree");
+            logged = true;
+        }
+        thread::sleep(TREE_READY_POLL);
+    }
+}
+
+/// How many times each connection to i3 is attempted before giving up.
+const CONNECT_ATTEMPTS: u32 = 5;
+
+/// How long to wait before the first retry. Doubles with every attempt.
+const CONNECT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Which of the two connections to i3 could not be established.
+#[derive(Debug)]
+enum ConnectError {
+    /// i3 couldn't be reached at all.
+    Command(EstablishError),
+    /// i3 accepted commands, but not a second connection for fetching the tree.
+    Tree(EstablishError),
+    /// i3 accepted commands, but not an event subscription.
+    Listener(EstablishError),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectError::Command(error) => write!(
+                f,
+                "Could not connect to i3 after {} attempts, is it running? {:?}",
+                CONNECT_ATTEMPTS, error
+            ),
+            ConnectError::Tree(error) => write!(
+                f,
+                "Connected to i3, but could not open the tree connection after {} attempts: {:?}",
+                CONNECT_ATTEMPTS, error
+            ),
+            ConnectError::Listener(error) => write!(
+                f,
+                "Connected to i3, but could not open the event connection after {} attempts: {:?}",
+                CONNECT_ATTEMPTS, error
+            ),
+        }
+    }
+}
+
+/// Opens the command, tree and event connections, retrying each on its own with backoff, so that
+/// a listener failure doesn't throw away a working command connection.
+fn setup_i3_connection() -> Result<(I3Connection, TreeConnection, I3EventListener), ConnectError> {
+    connect_all(
+        CONNECT_BACKOFF,
+        I3Connection::connect,
+        TreeConnection::connect,
+        I3EventListener::connect,
+    )
+}
+
+/// Opens each connection in turn with the given ways of connecting, waiting `backoff` before the
+/// first retry of each.
+fn connect_all<C, T, L>(
+    backoff: Duration,
+    command: impl Fn() -> Result<C, EstablishError>,
+    tree: impl Fn() -> Result<T, EstablishError>,
+    listener: impl Fn() -> Result<L, EstablishError>,
+) -> Result<(C, T, L), ConnectError> {
+    info!("Main connection connecting");
+    let connection =
+        connect_with_retries("main connection", backoff, command).map_err(ConnectError::Command)?;
+    info!("Tree connection connecting");
+    let tree_connection =
+        connect_with_retries("tree connection", backoff, tree).map_err(ConnectError::Tree)?;
+    info!("Listener connecting");
+    let listener =
+        connect_with_retries("listener", backoff, listener).map_err(ConnectError::Listener)?;
+    Ok((connection, tree_connection, listener))
+}
+
+fn connect_with_retries<T>(
+    name: &str,
+    mut backoff: Duration,
+    connect: impl Fn() -> Result<T, EstablishError>,
+) -> Result<T, EstablishError> {
+    let mut attempt = 1;
+    loop {
+        match connect() {
+            Ok(connected) => return Ok(connected),
+            Err(error) if attempt < CONNECT_ATTEMPTS => {
+                warn!(
+                    "Error opening the {} (attempt {}), retrying in {:?}: {:?}",
+                    name, attempt, backoff, error
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+impl Daemon {
+    /// A daemon with nothing handled yet, talking to i3 over the given connections.
+    fn new(
+        connection: I3Connection,
+        tree_connection: TreeConnection,
+        backend: Backend,
+        capabilities: Capabilities,
+        settings: Settings,
+        state: State,
+        watch: bool,
+    ) -> Daemon {
+        let hooks = Hooks::new(&settings);
+        Daemon {
+            connection,
+            tree_connection,
+            extras: Extras::new(),
+            backend,
+            capabilities,
+            settings,
+            state,
+            subscribers: Vec::new(),
+            manual_changes: HashMap::new(),
+            next_suggestion: None,
+            next_reassert: None,
+            overflowed: HashMap::new(),
+            recent_events: VecDeque::new(),
+            quiet_until: None,
+            deferred: Vec::new(),
+            pending: HashMap::new(),
+            animations: HashMap::new(),
+            history: VecDeque::new(),
+            hooks: hooks.clone(),
+            last_event_id: 0,
+            processes: ProcessNames::new(),
+            actions: ActionLog::new(hooks, watch),
+            awaiting_class: HashMap::new(),
+            delayed: HashMap::new(),
+            titled: HashMap::new(),
+            verifications: Vec::new(),
+            verify_failures: 0,
+            observed: HashMap::new(),
+            failures: FailureLog::default(),
+            commands_run: 0,
+            failed_handlings: 0,
+            focus_pending: None,
+            own_focus: HashMap::new(),
+            restores: HashMap::new(),
+            seen: HashSet::new(),
+            workspace_names: HashMap::new(),
+            lost_connection: false,
+        }
+    }
+
+    fn run_loop(&mut self, receiver: Receiver<Message>) -> Result<(), ExitReason> {
+        loop {
+            if self.lost_connection {
+                error!("Lost the tree connection to i3");
+                return Err(ExitReason::Connection);
+            }
+
+            let deadline = [self.next_suggestion, self.next_reassert, self.quiet_until]
+                .iter()
+                .flatten()
+                .chain(self.awaiting_class.values().map(|timer| &timer.due))
+                .chain(self.delayed.values().map(|timer| &timer.due))
+                .chain(self.verifications.iter().map(|v| &v.due))
+                .chain(self.focus_pending.iter().map(|(_, timer)| &timer.due))
+                .min()
+                .copied();
+            let message = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match receiver.recv_timeout(timeout) {
+                        Ok(message) => message,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let before = self.handling_counts();
+                            self.handle_timers();
+                            self.check_failsafe(before);
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            error!("Every event source stopped");
+                            return Err(ExitReason::Fatal);
+                        }
+                    }
+                }
+                None => match receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => {
+                        error!("Every event source stopped");
+                        return Err(ExitReason::Fatal);
+                    }
+                },
+            };
+
+            match message {
+                Message::I3(event) => {
+                    let _scope = EventScope::enter(self.next_event_id());
+                    let before = self.handling_counts();
+                    match *event {
+                        Ok(Event::WindowEvent(event_info)) => self.handle_window_event(event_info),
+                        Ok(Event::BindingEvent(event_info)) => {
+                            self.handle_binding_event(event_info)
+                        }
+                        Ok(Event::WorkspaceEvent(event_info)) => {
+                            self.handle_workspace_event(event_info)
+                        }
+                        // The connection drops right after, which is handled as before.
+                        Ok(Event::ShutdownEvent(event_info)) => {
+                            info!("i3 is shutting down ({:?})", event_info.change);
+                            self.sweep_debug_marks();
+                        }
+                        Err(error) => {
+                            error!("Lost the event connection to i3: {:?}", error);
+                            return Err(ExitReason::Connection);
+                        }
+                        Ok(event) => {
+                            error!("Unexpected event: {:?}", event);
+                            return Err(ExitReason::Fatal);
+                        }
+                    }
+                    self.check_failsafe(before);
+                }
+                Message::Control(line, stream) => self.handle_control(&line, stream),
+                Message::Stop(signal) => {
+                    info!("Stopping on signal {}", signal);
+                    self.sweep_debug_marks();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// How many commands i3 has refused and how many have been run so far, to tell afterwards
+    /// whether handling something went wrong.
+    fn handling_counts(&self) -> (u64, u64) {
+        (self.failures.total(), self.commands_run)
+    }
+
+    /// Counts the events in a row that i3 refused commands for, given the counts from before
+    /// one was handled, and pauses ratiosplit after `failsafe_threshold` of them: by then
+    /// something is broken for good, like a conflicting layout daemon, and every new window
+    /// would only fail the same way. Events that ran commands without failures start the count
+    /// again, and those that ran none don't count either way.
+    fn check_failsafe(&mut self, before: (u64, u64)) {
+        self.failed_handlings =
+            failed_in_a_row(self.failed_handlings, before, self.handling_counts());
+        if self.state.paused || !failsafe_trips(self.failed_handlings, &self.settings) {
+            return;
+        }
+        let errors: Vec<String> = self
+            .failures
+            .recent()
+            .map(|failure| failure.error.clone())
+            .collect();
+        error!(
+            "i3 refused commands for {} events in a row, pausing until resumed. Last errors: {}",
+            self.failed_handlings,
+            errors.join("; ")
+        );
+        self.state.paused = true;
+        self.state.failsafe = true;
+        self.state_changed();
+        self.hooks.run("paused", &[]);
+        if self.settings.failsafe_notify {
+            notify(
+                "ratiosplit paused itself",
+                &format!(
+                    "i3 refused its commands {} times in a row: {}",
+                    self.failed_handlings,
+                    errors.last().map_or("", String::as_str)
+                ),
+            );
+        }
+    }
+
+    fn next_event_id(&mut self) -> u64 {
+        self.last_event_id += 1;
+        self.last_event_id
+    }
+
+    /// Enters the scope of the event something due now was put off from, or of a new one when it
+    /// wasn't put off while handling one.
+    fn timer_scope(&mut self, event: Option<u64>) -> EventScope {
+        let id = match event {
+            Some(id) => id,
+            None => self.next_event_id(),
+        };
+        EventScope::enter(id)
+    }
+
+    fn handle_timers(&mut self) {
+        let now = Instant::now();
+        let (due, waiting) = self
+            .verifications
+            .drain(..)
+            .partition(|v: &PendingVerification| v.due <= now);
+        self.verifications = waiting;
+        for verification in due {
+            let _scope = self.timer_scope(verification.event);
+            self.verify(verification);
+        }
+        if let Some((id, timer)) = self.focus_pending.filter(|(_, timer)| timer.due <= now) {
+            self.focus_pending = None;
+            let _scope = self.timer_scope(timer.event);
+            self.enlarge_focused(id);
+        }
+
+        if self.quiet_until.is_some_and(|until| until <= now) {
+            let _scope = self.timer_scope(None);
+            self.leave_quiet_mode();
+        }
+        if self.next_suggestion.is_some_and(|next| next <= now) {
+            let _scope = self.timer_scope(None);
+            self.log_suggestions();
+            self.schedule_suggestion();
+        }
+        if self.next_reassert.is_some_and(|next| next <= now) {
+            let _scope = self.timer_scope(None);
+            self.reassert();
+            self.schedule_reassert();
+        }
+
+        for (id, timer) in due_timers(&self.delayed, now) {
+            self.delayed.remove(&id);
+            let _scope = self.timer_scope(timer.event);
+            let tree = match self.get_tree() {
+                Some(tree) => tree,
+                None => return,
+            };
+            match find_node(id, &tree) {
+                Some(node) => self.handle_new(node.clone()),
+                None => info!("Window {} closed or floated during handle_delay_ms", id),
+            }
+        }
+
+        for (id, timer) in due_timers(&self.awaiting_class, now) {
+            self.awaiting_class.remove(&id);
+            if self.state.paused {
+                continue;
+            }
+            let _scope = self.timer_scope(timer.event);
+            let tree = match self.get_tree() {
+                Some(tree) => tree,
+                None => return,
+            };
+            match find_node(id, &tree) {
+                Some(node) => {
+                    info!("{:?} never got a class, handling it without one", node.name);
+                    self.handle_child(node.clone());
+                }
+                None => trace!("Window {} closed while waiting for its class", id),
+            }
+        }
+    }
+
+    /// Records a window event's arrival, entering quiet mode when more than `quiet_threshold` have
+    /// arrived within `quiet_window_ms`. Returns whether the daemon is in quiet mode.
+    fn track_activity(&mut self) -> bool {
+        if self.settings.quiet_threshold == 0 {
+            return false;
+        }
+
+        let now = Instant::now();
+        let window = Duration::from_millis(self.settings.quiet_window_ms);
+        self.recent_events.push_back(now);
+        while self
+            .recent_events
+            .front()
+            .is_some_and(|&arrived| now.duration_since(arrived) > window)
+        {
+            self.recent_events.pop_front();
+        }
+
+        let settle = now + Duration::from_millis(self.settings.quiet_settle_ms);
+        if self.quiet_until.is_some() {
+            self.quiet_until = Some(settle);
+        } else if self.recent_events.len() > self.settings.quiet_threshold {
+            info!(
+                "{} window events in {}ms, entering quiet mode",
+                self.recent_events.len(),
+                self.settings.quiet_window_ms
+            );
+            self.quiet_until = Some(settle);
+        }
+
+        self.quiet_until.is_some()
+    }
+
+    /// Ends quiet mode, rebalancing each workspace that gained windows while it was active.
+    fn leave_quiet_mode(&mut self) {
+        self.quiet_until = None;
+        self.recent_events.clear();
+        let deferred = std::mem::take(&mut self.deferred);
+        info!(
+            "Activity settled, leaving quiet mode with {} new windows",
+            deferred.len()
+        );
+
+        if deferred.is_empty() || self.state.paused {
+            return;
+        }
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let mut seen = HashSet::new();
+        self.expire_cooldowns();
+        for id in deferred {
+            let workspace = match find_workspace(id, &tree) {
+                Some(w) => w,
+                None => continue,
+            };
+            if !seen.insert(workspace.id) {
+                continue;
+            }
+            if workspace
+                .name
+                .as_deref()
+                .is_some_and(|name| self.remaining_cooldown(name).is_some())
+            {
+                info!(
+                    "Workspace {:?} was changed by hand recently, not rebalancing",
+                    workspace.name
+                );
+                continue;
+            }
+            if self.is_restoring(workspace) {
+                info!(
+                    "A layout was restored on {:?}, not rebalancing",
+                    workspace.name
+                );
+                continue;
+            }
+            self.apply_workspace(&tree, workspace);
+        }
+    }
+
+    fn handle_window_event(&mut self, event_info: WindowEventInfo) {
+        if let WindowChange::Floating | WindowChange::Move = event_info.change {
+            self.forget_unhandled(&event_info.container);
+        }
+
+        match event_info.change {
+            WindowChange::New => {
+                self.seen.insert(event_info.container.id);
+            }
+            WindowChange::Close => {
+                self.seen.remove(&event_info.container.id);
+                self.delayed.remove(&event_info.container.id);
+            }
+            _ => {}
+        }
+
+        // Marks don't change any layout, and with debug_marks ratiosplit sets them itself.
+        if let WindowChange::Mark = event_info.change {
+            trace!("Ignoring event Mark: {:?}", event_info.container.name);
+            return;
+        }
+
+        if self.track_activity() {
+            if let WindowChange::New = event_info.change {
+                if !self.state.paused {
+                    self.deferred.push(event_info.container.id);
+                }
+                // A restore large enough to set off quiet mode is still going.
+                let now = Instant::now();
+                for arrived in self.restores.values_mut() {
+                    if arrived.elapsed() < RESTORE_GRACE {
+                        *arrived = now;
+                    }
+                }
+            }
+            trace!(
+                "Quiet mode, deferring event {:?}: {:?}",
+                event_info.change,
+                event_info.container.name
+            );
+            return;
+        }
+
+        let WindowEventInfo { change, container } = event_info;
+        let name = container.name.clone();
+        // Each handler returns whether the event was any of its business, so everything else ends
+        // up in the same trace below.
+        let taken = match change {
+            WindowChange::New => self.on_new(container),
+            WindowChange::Title => self.on_title(container),
+            WindowChange::Focus => self.on_focus(container),
+            WindowChange::FullscreenMode => self.on_fullscreen(container),
+            WindowChange::Floating => self.on_left(container),
+            WindowChange::Move => self.on_move(container),
+            WindowChange::Close => self.on_close(),
+            WindowChange::Urgent | WindowChange::Mark => false,
+            WindowChange::Unknown => {
+                trace!("Ignoring unknown window event for {:?}", name);
+                return;
+            }
+        };
+        if !taken {
+            trace!("Ignoring event {:?}: {:?}", change, name);
+        }
+    }
+
+    fn on_new(&mut self, container: Node) -> bool {
+        info!("New window created {:?}", container.name);
+        trace!("Container properties: {:?}", container);
+        if self.settings.handle_delay_ms > 0 {
+            let delay = Duration::from_millis(self.settings.handle_delay_ms);
+            self.delayed.insert(container.id, Timer::after(delay));
+            return true;
+        }
+        self.handle_new(container);
+        true
+    }
+
+    /// Stops waiting to handle a window that floated or moved away before it was handled, like a
+    /// dropdown terminal being hidden, so that it isn't handled at all.
+    fn forget_unhandled(&mut self, container: &Node) {
+        let id = container.id;
+        if self.awaiting_class.remove(&id).is_some() {
+            info!(
+                "{:?} left before it got its class, not handling it",
+                container.name
+            );
+        }
+        if self.delayed.remove(&id).is_some() {
+            info!(
+                "{:?} left before handle_delay_ms passed, not handling it",
+                container.name
+            );
+        }
+        self.deferred.retain(|&deferred| deferred != id);
+    }
+
+    /// Handles a new window, or waits for its class first when class rules need one.
+    fn handle_new(&mut self, container: Node) {
+        if self.settings.class_match_timeout_ms > 0
+            && self.settings.has_class_rules()
+            && window_class(&container).is_none()
+        {
+            info!("{:?} has no class yet, waiting for one", container.name);
+            let timeout = Duration::from_millis(self.settings.class_match_timeout_ms);
+            self.awaiting_class
+                .insert(container.id, Timer::after(timeout));
+            return;
+        }
+        if self.state.paused {
+            // Not worth a tree request just to say where the window was.
+            EventRecord::new("new", &container, &container, &self.settings, &self.actions)
+                .skip(SkipReason::Paused);
+            return;
+        }
+        self.handle_child(container);
+    }
+
+    /// Handles windows waiting for their class, and recently handled windows that may have a
+    /// `[title_ratios]` ratio now.
+    fn on_title(&mut self, container: Node) -> bool {
+        if self.awaiting_class.contains_key(&container.id) && window_class(&container).is_some() {
+            self.awaiting_class.remove(&container.id);
+            info!(
+                "{:?} got its class {:?}",
+                container.name,
+                window_class(&container)
+            );
+            if !self.state.paused {
+                self.handle_child(container);
+            }
+            true
+        } else if self.titled.contains_key(&container.id) {
+            if !self.state.paused {
+                self.handle_title(container);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn on_focus(&mut self, container: Node) -> bool {
+        if self.settings.mode != Mode::FocusMaster {
+            return false;
+        }
+        if self
+            .own_focus
+            .remove(&container.id)
+            .is_some_and(|focused| focused.elapsed() < OWN_FOCUS_WINDOW)
+        {
+            trace!("Ignoring our own focus on {:?}", container.name);
+            return true;
+        }
+        if !self.state.paused {
+            let delay = Duration::from_millis(self.settings.focus_delay_ms);
+            self.focus_pending = Some((container.id, Timer::after(delay)));
+        }
+        true
+    }
+
+    fn on_fullscreen(&mut self, container: Node) -> bool {
+        if !self.state.paused {
+            self.handle_fullscreen_change(container);
+        }
+        true
+    }
+
+    fn on_left(&mut self, container: Node) -> bool {
+        if !self.state.paused {
+            self.handle_left(container);
+            if self.settings.mode == Mode::Columns {
+                self.apply_columns();
+            }
+            self.restore_locks();
+        }
+        true
+    }
+
+    /// Repairs the container a window left, then handles the window where it is now if it's
+    /// one ratiosplit hasn't seen, or with `manage_moved_existing`, any window.
+    fn on_move(&mut self, container: Node) -> bool {
+        let known = !self.seen.insert(container.id);
+        if known && !self.settings.manage_moved_existing {
+            return self.on_left(container);
+        }
+        info!(
+            "{} window moved in {:?}",
+            if known { "Existing" } else { "Unseen" },
+            container.name
+        );
+        self.on_left(container.clone());
+        if !self.state.paused {
+            self.handle_child(container);
+        }
+        true
+    }
+
+    fn on_close(&mut self) -> bool {
+        let restore = self.settings.overflow_restore && !self.overflowed.is_empty();
+        let columns = self.settings.mode == Mode::Columns;
+        if !restore && !columns && !self.settings.ratio_locks {
+            return false;
+        }
+        if !self.state.paused {
+            if restore {
+                self.restore_overflowed();
+            }
+            if columns {
+                self.apply_columns();
+            }
+            self.restore_locks();
+        }
+        true
+    }
+
+    /// Puts locked containers on the focused workspace back to their locked shares, with
+    /// `ratio_locks`.
+    fn restore_locks(&mut self) {
+        if !self.settings.ratio_locks {
+            return;
+        }
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let focused = match find_focused(&tree) {
+            Some(focused) => focused,
+            None => return,
+        };
+        let workspace = match find_workspace(focused.id, &tree) {
+            Some(workspace) => workspace,
+            None => return,
+        };
+
+        let commands = plan_locks(workspace, &self.get_extras(), focused, &self.settings);
+        if !commands.is_empty() {
+            self.run_commands(&commands, workspace);
+        }
+    }
+
+    /// Locks the focused container at the given percent of its parent with a `ratio_lock` mark,
+    /// replacing any lock it had, or with `None`, removes its lock. Either way, the locks on its
+    /// workspace are put back afterwards.
+    fn lock(&mut self, percent: Option<&str>) -> Response {
+        if !self.settings.ratio_locks {
+            return Response::error("ratio_locks is off, not locking");
+        }
+        let percent = match percent.map(str::parse::<u32>) {
+            Some(Ok(percent)) if (1..=99).contains(&percent) => Some(percent),
+            Some(_) => return Response::error("Locks must be between 1 and 99 percent"),
+            None => None,
+        };
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return Response::error("Could not get the tree from i3"),
+        };
+        let focused = match find_focused(&tree) {
+            Some(f) => f,
+            None if is_floating_focused(&tree) => {
+                return Response::error("The focused window is floating, not locking it")
+            }
+            None => return Response::error("Could not find the focused window"),
+        };
+
+        let extras = self.get_extras();
+        let mut commands: Vec<Command> = extras
+            .get(&focused.id)
+            .map(|extras| extras.marks.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .filter(|mark| mark.starts_with(RATIO_LOCK_PREFIX))
+            .map(|mark| Command::Unmark(mark.clone()))
+            .collect();
+        if let Some(percent) = percent {
+            commands.push(Command::Mark {
+                con_id: focused.id,
+                mark: format!("{}{}", RATIO_LOCK_PREFIX, percent),
+            });
+        }
+        if !self.run_commands(&commands, focused) {
+            return Response::error(format!("Could not mark {:?}", focused.name));
+        }
+
+        self.restore_locks();
+        match percent {
+            Some(percent) => Response::ok(format!("Locked {:?} at {}%", focused.name, percent)),
+            None => Response::ok(format!("Unlocked {:?}", focused.name)),
+        }
+    }
+
+    fn handle_binding_event(&mut self, event_info: BindingEventInfo) {
+        if self.settings.mode == Mode::Remember && is_resize_command(&event_info.binding.command) {
+            self.remember_splits();
+            if let Some(tree) = self.get_tree() {
+                self.observe(&tree);
+            }
+        }
+
+        if self.settings.manual_cooldown == 0
+            || !is_manual_layout_command(&event_info.binding.command)
+        {
+            return;
+        }
+
+        let workspace = match self.connection.get_workspaces() {
+            Ok(reply) => reply.workspaces.into_iter().find(|w| w.focused),
+            Err(error) => {
+                warn!("Error retrieving workspaces: {:?}", error);
+                return;
+            }
+        };
+
+        if let Some(workspace) = workspace {
+            trace!(
+                "Manual layout change {:?} on workspace {:?}",
+                event_info.binding.command,
+                workspace.name
+            );
+            self.manual_changes.insert(workspace.name, Instant::now());
+        }
+    }
+
+    /// Takes the `debug_marks` marks off every container again, when ratiosplit or i3 stops.
+    fn sweep_debug_marks(&mut self) {
+        if !self.settings.debug_marks || self.settings.suggest {
+            return;
+        }
+        match sweep(&mut self.connection) {
+            Ok(removed) => info!("Removed {} debug mark(s)", removed),
+            Err(error) => warn!("{}", error),
+        }
+    }
+
+    /// Says once, in the log itself, that it's still at the old place in the i3 config directory.
+    fn notice_legacy_log(&mut self) {
+        if self.state.legacy_log_noticed || self.settings.log_file != legacy_log_file() {
+            return;
+        }
+        info!(
+            "Logging to {} because a log is already there. The default is now {}; move or delete \
+             the old log to switch, or set log_file to keep it",
+            self.settings.log_file,
+            state_log_file()
+        );
+        self.state.legacy_log_noticed = true;
+        self.state_changed();
+    }
+
+    /// The settings for the workspace holding the node with the given id, with its
+    /// `[workspace ...]` section applied.
+    fn settings_for(&self, tree: &Node, id: i64) -> Cow<'_, Settings> {
+        let name = find_workspace(id, tree).and_then(|w| w.name.as_deref());
+        self.settings.for_workspace(name)
+    }
+
+    /// Keeps track of workspace names, moving everything kept by name over when a workspace is
+    /// renamed, and forgetting workspaces i3 destroys.
+    fn handle_workspace_event(&mut self, event_info: WorkspaceEventInfo) {
+        let workspace = match event_info.current {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        let name = match (event_info.change, workspace.name) {
+            (WorkspaceChange::Empty, name) => {
+                self.workspace_names.remove(&workspace.id);
+                // i3 may not have taken the emptied workspace out of the tree yet.
+                let tree = match self.get_tree() {
+                    Some(tree) => tree,
+                    None => return,
+                };
+                self.prune_state(&tree, name.as_deref());
+                return;
+            }
+            (
+                WorkspaceChange::Init | WorkspaceChange::Focus | WorkspaceChange::Rename,
+                Some(name),
+            ) => name,
+            _ => return,
+        };
+
+        let old = match self.workspace_names.insert(workspace.id, name.clone()) {
+            Some(old) if old != name => old,
+            _ => return,
+        };
+        info!("Workspace {:?} was renamed to {:?}", old, name);
+        self.settings.rename_workspace(&old, &name);
+        if let Some(changed) = self.manual_changes.remove(&old) {
+            self.manual_changes.insert(name.clone(), changed);
+        }
+        if let Some(arrived) = self.restores.remove(&old) {
+            self.restores.insert(name.clone(), arrived);
+        }
+        if rename_remembered(&mut self.state.remembered, &old, &name) {
+            self.state_changed();
+        }
+    }
+
+    /// With `mode = remember`, records the splits a resize bound to a key may have changed: those
+    /// of the nearest horizontally and vertically split containers holding the focused window,
+    /// when they hold two windows.
+    fn remember_splits(&mut self) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let mut node = match find_focused(&tree) {
+            Some(node) => node,
+            None => return,
+        };
+
+        let (mut seen_horizontal, mut seen_vertical) = (false, false);
+        let mut remembered = Vec::new();
+        while node.nodetype != NodeType::Workspace {
+            let parent = match find_parent(node.id, &tree) {
+                Some(parent) => parent,
+                None => break,
+            };
+            let seen = match parent.layout {
+                NodeLayout::SplitH => &mut seen_horizontal,
+                NodeLayout::SplitV => &mut seen_vertical,
+                _ => {
+                    node = parent;
+                    continue;
+                }
+            };
+            if !*seen {
+                *seen = true;
+                if let (Some(path), [_, last]) =
+                    (container_path(parent.id, &tree), &parent.nodes[..])
+                {
+                    remembered.push((path, effective_percent(last, parent)));
+                }
+            }
+            node = parent;
+        }
+
+        if remembered.is_empty() {
+            return;
+        }
+        for (path, share) in remembered {
+            info!("Remembering a ratio of {:.2} for {}", share, path);
+            self.state.remembered.insert(path, share);
+        }
+        self.state_changed();
+    }
+
+    /// With `mode = remember`, notes the current share of the last child of every two-child
+    /// split container, so that later changes not made by ratiosplit can be told apart.
+    fn observe(&mut self, tree: &Node) {
+        if self.settings.mode != Mode::Remember {
+            return;
+        }
+        self.observed = split_pairs(tree)
+            .into_iter()
+            .filter_map(|parent| {
+                let last = parent.nodes.last()?;
+                Some((parent.id, (last.id, effective_percent(last, parent))))
+            })
+            .collect();
+    }
+
+    /// With `mode = remember`, remembers the ratio of every two-child container whose split
+    /// changed since ratiosplit last looked, which i3 doesn't send an event for when it's dragged
+    /// with the mouse. Key bindings are remembered as they happen by `remember_splits`.
+    fn remember_changed_splits(&mut self, tree: &Node) {
+        if self.settings.mode != Mode::Remember {
+            return;
+        }
+        let mut changed = false;
+        for parent in split_pairs(tree) {
+            // Animated resizes finish after ratiosplit last looked.
+            if self.animations.contains_key(&parent.id) {
+                continue;
+            }
+            let last = match parent.nodes.last() {
+                Some(last) => last,
+                None => continue,
+            };
+            let share = effective_percent(last, parent);
+            // A different last child is a window that opened or closed, not a resize.
+            let moved = self
+                .observed
+                .get(&parent.id)
+                .is_some_and(|&(child, before)| {
+                    child == last.id && (before - share).abs() > DRIFT_TOLERANCE
+                });
+            if let (true, Some(path)) = (moved, container_path(parent.id, tree)) {
+                info!(
+                    "{} was resized by hand, remembering a ratio of {:.2}",
+                    path, share
+                );
+                self.state.remembered.insert(path, share);
+                changed = true;
+            }
+        }
+        self.animations
+            .retain(|_, animation| !animation.is_finished());
+        self.observe(tree);
+        if changed {
+            self.state_changed();
+        }
+    }
+
+    /// How much longer the given workspace should be left alone after a manual layout change.
+    fn remaining_cooldown(&self, workspace: &str) -> Option<Duration> {
+        let changed = *self.manual_changes.get(workspace)?;
+        cooldown_remaining(changed, Instant::now(), &self.settings)
+    }
+
+    /// Whether a layout is being restored on the given workspace, with `respect_restored_layouts`:
+    /// it still has placeholders, or a window arrived there while it had some moments ago.
+    fn is_restoring(&mut self, workspace: &Node) -> bool {
+        if !self.settings.respect_restored_layouts {
+            return false;
+        }
+        self.restores
+            .retain(|_, arrived| arrived.elapsed() < RESTORE_GRACE);
+
+        let name = workspace.name.clone().unwrap_or_default();
+        if !has_placeholders(workspace) && !self.restores.contains_key(&name) {
+            return false;
+        }
+        self.restores.insert(name, Instant::now());
+        true
+    }
+
+    fn expire_cooldowns(&mut self) {
+        let (now, settings) = (Instant::now(), &self.settings);
+        self.manual_changes
+            .retain(|_, &mut changed| cooldown_remaining(changed, now, settings).is_some());
+    }
+
+    /// Goes through the checks that can leave a new window alone before anything is planned for
+    /// it, in order, stopping at the first that does. Each check made is added to `checks`, so
+    /// that `explain` can show them; the last one is the one that failed, if any did.
+    fn check_window(
+        &mut self,
+        tree: &Node,
+        extras: &Extras,
+        new_node: &Node,
+        checks: &mut Vec<&'static str>,
+    ) -> Result<(), SkipReason> {
+        checks.push("dock");
+        if is_docked(new_node.id, tree) {
+            return Err(SkipReason::Dock);
+        }
+
+        // The tree is newer than the event, and apps often set the hint right after opening.
+        if self.settings.skip_urgent {
+            checks.push("skip_urgent");
+            if new_node.urgent || find_node(new_node.id, tree).is_some_and(|node| node.urgent) {
+                return Err(SkipReason::Urgent);
+            }
+        }
+
+        if self.settings.pause_on_fullscreen {
+            checks.push("pause_on_fullscreen");
+            let fullscreen = find_output(new_node.id, tree)
+                .and_then(|output| shown_fullscreen(output, extras, new_node.id));
+            if let Some(fullscreen) = fullscreen {
+                info!("{:?} is fullscreen on the output", fullscreen.name);
+                return Err(SkipReason::Fullscreen);
+            }
+        }
+
+        checks.push("dropdown_classes");
+        if self.is_dropdown(new_node) {
+            return Err(SkipReason::Dropdown);
+        }
+
+        checks.push("exclude");
+        if let Some(matcher) = self.settings.excluded_by(&window_identity(new_node)) {
+            return Err(SkipReason::Excluded(matcher.to_string()));
+        }
+
+        if !self.settings.exclude_processes.is_empty() {
+            checks.push("exclude_processes");
+            if let Some(pid) = window_pid(new_node, extras) {
+                let excluded = &self.settings.exclude_processes;
+                if let Some(process) = self.processes.excluded(pid, excluded) {
+                    return Err(SkipReason::ExcludedProcess(process));
+                }
+            }
+        }
+
+        checks.push("manual_cooldown");
+        self.expire_cooldowns();
+        if let Some(workspace) = find_workspace(new_node.id, tree).and_then(|w| w.name.as_ref()) {
+            if let Some(remaining) = self.remaining_cooldown(workspace) {
+                return Err(SkipReason::Cooldown(remaining));
+            }
+        }
+
+        checks.push("restored layout");
+        if find_workspace(new_node.id, tree).is_some_and(|w| self.is_restoring(w)) {
+            return Err(SkipReason::RestoredLayout);
+        }
+        Ok(())
+    }
+
+    fn is_dropdown(&self, node: &Node) -> bool {
+        window_class(node).is_some_and(|class| {
+            self.settings
+                .dropdown_classes
+                .iter()
+                .any(|dropdown| dropdown == class)
+        })
+    }
+
+    /// The tree, or the workspace with the given name or number, with what the daemon makes of
+    /// each node, for `tree`.
+    fn tree(&mut self, workspace: Option<&str>) -> Response {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return Response::error("Could not get the tree from i3"),
+        };
+        let extras = self.get_extras();
+        match summarize_tree(&tree, &extras, workspace, &|node| self.annotate(node)) {
+            Ok(summary) => Response::ok(summary.to_string()).with_data(json!(summary)),
+            Err(error) => Response::error(error),
+        }
+    }
+
+    /// Whether a workspace is managed, and why a window would be left alone by its properties,
+    /// for `tree`. Checks that depend on when a window opens, like cooldowns, are left out.
+    fn annotate(&self, node: &Node) -> Option<String> {
+        if node.nodetype == NodeType::Workspace {
+            let managed = self.settings.is_workspace_managed(node.name.as_deref()?);
+            return Some(if managed { "managed" } else { "unmanaged" }.to_string());
+        }
+        node.window?;
+        if self.is_dropdown(node) {
+            return Some(SkipReason::Dropdown.to_string());
+        }
+        self.settings
+            .excluded_by(&window_identity(node))
+            .map(|matcher| SkipReason::Excluded(matcher.to_string()).to_string())
+    }
+
+    /// Decides for the focused container as if it had just opened, for `explain`, and describes
+    /// how without running anything. It goes through the same checks and planner as a new
+    /// window, and what they log along the way makes up most of the explanation. Moving the
+    /// window out of a group or converting its container would change the tree before planning,
+    /// so those are only listed first, and the plan is for the tree as it is.
+    fn explain(&mut self) -> Response {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return Response::error("Could not get the tree from i3"),
+        };
+        let node = match find_focused(&tree) {
+            Some(node) => node.clone(),
+            None => return Response::error("Nothing is focused"),
+        };
+
+        let mut explanation = Explanation::new(&node, &tree);
+        let scope = ExplainScope::enter();
+        if self.state.paused {
+            explanation.check("paused", Some(&SkipReason::Paused));
+        }
+
+        let extras = self.get_extras();
+        let mut checks = Vec::new();
+        let checked = self.check_window(&tree, &extras, &node, &mut checks);
+        let last = checks.len().saturating_sub(1);
+        for (i, check) in checks.into_iter().enumerate() {
+            let failed = checked.as_ref().err().filter(|_| i == last);
+            explanation.check(check, failed);
+        }
+
+        if let Err(reason) = checked {
+            explanation.decision = reason.to_string();
+        } else if let Some(destination) = plan_overflow_move(&tree, &node, &self.settings) {
+            explanation.decision = format!("move to workspace {}", destination);
+        } else {
+            let settings = self.settings_for(&tree, node.id).into_owned();
+            let mut commands = Vec::new();
+            if let Some(escape) = plan_escape(&tree, &extras, &node, &settings) {
+                info!("Would move {:?} out of its group first", node.name);
+                commands.push(escape);
+            }
+            if let Some(conversion) = plan_convert(&tree, &node, &settings) {
+                info!("Would convert the container of {:?} first", node.name);
+                commands.push(conversion.convert);
+            }
+            let plan = plan_commands(
+                &tree,
+                &extras,
+                &self.state.remembered,
+                &node,
+                Some(node.id),
+                &settings,
+            );
+            match plan {
+                Decision::Act(planned) => {
+                    commands.extend(planned);
+                    explanation.decision = "resize".to_string();
+                    explanation.commands = render_all(&commands);
+                }
+                Decision::Skip(reason) => explanation.decision = reason.to_string(),
+            }
+        }
+        explanation.reasoning = scope.finish();
+
+        Response::ok(explanation.to_string()).with_data(json!(explanation))
+    }
+
+    fn handle_child(&mut self, new_node: Node) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        self.remember_changed_splits(&tree);
+        let mut record = EventRecord::new("new", &new_node, &tree, &self.settings, &self.actions);
+
+        let extras = self.get_extras();
+        if let Err(reason) = self.check_window(&tree, &extras, &new_node, &mut Vec::new()) {
+            record.skip(reason);
+            return;
+        }
+
+        if let Some(destination) = plan_overflow_move(&tree, &new_node, &self.settings) {
+            record.decide(format!("move to workspace {}", destination));
+            let origin = find_workspace(new_node.id, &tree).and_then(|w| w.name.clone());
+            self.move_overflow(new_node, destination, origin);
+            return;
+        }
+
+        // Containers ratiosplit stacked itself for overflow stay that way.
+        let overflowed =
+            find_parent(new_node.id, &tree).is_some_and(|p| self.overflowed.contains_key(&p.id));
+        let tree = if overflowed {
+            tree
+        } else {
+            self.escape_group(tree, &new_node)
+        };
+        let tree = match plan_convert(&tree, &new_node, &self.settings) {
+            Some(conversion) if !overflowed => match self.convert_tabbed(&new_node, conversion) {
+                Some(converted) => converted,
+                None => {
+                    record.decide("convert failed");
+                    return;
+                }
+            },
+            _ => tree,
+        };
+
+        let parent_id = find_parent(new_node.id, &tree).map(|p| p.id);
+        if let Some(parent_id) = parent_id {
+            self.cancel_animation(parent_id);
+        }
+
+        let extras = self.get_extras();
+        let focused = find_focused(&tree).map(|f| f.id);
+        let commands = match plan_commands(
+            &tree,
+            &extras,
+            &self.state.remembered,
+            &new_node,
+            focused,
+            &self.settings_for(&tree, new_node.id),
+        ) {
+            Decision::Act(c) => c,
+            Decision::Skip(reason) => {
+                record.skip(reason);
+                return;
+            }
+        };
+        record.set_commands(&commands);
+        let before = find_parent(new_node.id, &tree)
+            .and_then(|parent| snapshot(parent, split_targets(&commands)));
+
+        // When animating, the final resize is replaced by the animation's steps.
+        let final_resize = commands.iter().rposition(Command::is_resize);
+        let animation = match final_resize {
+            Some(index) if self.settings.animate_ms > 0 => {
+                plan_animation(&tree, &new_node, &commands[index], &self.settings)
+            }
+            _ => None,
+        };
+        let mut immediate = commands.clone();
+        if let (Some(_), Some(index)) = (&animation, final_resize) {
+            immediate.remove(index);
+        }
+
+        if !self.run_pre_split(&tree, &new_node) {
+            record.decide("pre_split_command failed");
+            return;
+        }
+        if !self.run_commands(&immediate, &new_node) {
+            record.decide("not run");
+            return;
+        }
+
+        record.decide("resize");
+        if self.settings.debug_marks {
+            self.run_commands(&[managed_mark(new_node.id)], &new_node);
+        }
+        if self.settings.verify_log {
+            if let Some(before) = find_parent(new_node.id, &tree) {
+                self.log_verify(before);
+            }
+        }
+        let animated = animation.is_some();
+        if let (Some(steps), Some(parent_id)) = (animation, parent_id) {
+            record.decide("resize animated");
+            self.start_animation(parent_id, steps);
+        }
+        if self.settings.verify {
+            self.schedule_verification(&tree, &new_node, &commands, animated, false);
+        }
+        if self.settings.settle_ms > 0 {
+            self.schedule_verification(&tree, &new_node, &commands, animated, true);
+        }
+
+        let post_resize = plan_hook(
+            self.settings.post_resize_command.as_deref(),
+            &tree,
+            &new_node,
+        );
+        if !post_resize.is_empty() {
+            info!(
+                "Running {:?} for {:?}",
+                render_all(&post_resize),
+                new_node.name
+            );
+            self.run_commands(&post_resize, &new_node);
+        }
+
+        for command in &commands {
+            if let Command::Overflow { con_id, .. } = command {
+                if let Some(container) = find_parent(*con_id, &tree) {
+                    let direction =
+                        split_direction(container).unwrap_or(SplitDirection::Horizontal);
+                    self.overflowed.insert(container.id, direction);
+                }
+            }
+        }
+
+        if !self.settings.title_ratios.is_empty() {
+            if let Some(parent_id) = parent_id {
+                let window = Duration::from_millis(self.settings.title_ratio_window_ms);
+                self.titled
+                    .insert(new_node.id, (parent_id, Instant::now() + window));
+            }
+        }
+
+        if let Some(before) = before {
+            if self.settings.repair_window_ms > 0 {
+                self.pending.insert(
+                    new_node.id,
+                    PendingWindow {
+                        before: before.clone(),
+                        expires: Instant::now()
+                            + Duration::from_millis(self.settings.repair_window_ms),
+                    },
+                );
+            }
+            self.remember(format!("resize for {:?}", new_node.name), before);
+        }
+    }
+
+    /// Queues a check that the final resize for `new_node` took effect, for `verify`, or that it
+    /// still holds once the app has settled, for `settle_ms`. Only the two-window split is
+    /// checked; equalizing and master layouts don't have a single ratio to compare against.
+    fn schedule_verification(
+        &mut self,
+        tree: &Node,
+        new_node: &Node,
+        commands: &[Command],
+        animated: bool,
+        settle: bool,
+    ) {
+        if !matches!(
+            self.settings.mode,
+            Mode::Spiral | Mode::Dynamic | Mode::MatchReference
+        ) {
+            return;
+        }
+        let parent = match find_parent(new_node.id, tree) {
+            Some(parent) if parent.nodes.len() == 2 => parent,
+            _ => return,
+        };
+        let resize_horizontal = match commands.iter().rev().find_map(Command::resize_horizontal) {
+            Some(horizontal) => horizontal,
+            None => return,
+        };
+
+        let mut delay = self.verify_delay(settle);
+        if animated {
+            delay += self.settings.animate_ms;
+        }
+        self.verifications.push(PendingVerification {
+            parent: parent.id,
+            node: new_node.id,
+            resize_horizontal,
+            ratio: effective_ratio(tree, parent, resize_horizontal, &self.settings),
+            retries_left: if settle {
+                1
+            } else {
+                self.settings.verify_retries
+            },
+            due: Instant::now() + Duration::from_millis(delay),
+            event: current_event(),
+            settle,
+            class: window_class(new_node).map(String::from),
+        });
+    }
+
+    fn verify_delay(&self, settle: bool) -> u64 {
+        if settle {
+            self.settings.settle_ms
+        } else {
+            self.settings.verify_delay_ms
+        }
+    }
+
+    fn verify(&mut self, mut verification: PendingVerification) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let (actual, retry) = match verify_resize(
+            &tree,
+            verification.parent,
+            verification.node,
+            verification.resize_horizontal,
+            verification.ratio,
+        ) {
+            Verification::Matches | Verification::Gone => return,
+            Verification::Mismatch { actual, retry } => (actual, retry),
+        };
+
+        if verification.retries_left == 0 && verification.settle {
+            info!(
+                "Window {} resized itself to {:.3} of its container again, leaving it",
+                verification.node, actual
+            );
+            if let Some(class) = verification.class {
+                if self.state.self_resizing.insert(class) {
+                    info!(
+                        "Apps that resize their own windows so far: {:?}",
+                        self.state.self_resizing
+                    );
+                    self.state_changed();
+                }
+            }
+            return;
+        }
+        if verification.retries_left == 0 {
+            warn!(
+                "Window {} takes up {:.3} of its container instead of {:.3}, giving up",
+                verification.node, actual, verification.ratio
+            );
+            self.verify_failures += 1;
+            return;
+        }
+
+        info!(
+            "Window {} takes up {:.3} of its container instead of {:.3}, retrying",
+            verification.node, actual, verification.ratio
+        );
+        let node = match find_node(verification.node, &tree) {
+            Some(node) => node.clone(),
+            None => return,
+        };
+        if self.run_commands(&[retry], &node) {
+            verification.retries_left -= 1;
+            verification.due =
+                Instant::now() + Duration::from_millis(self.verify_delay(verification.settle));
+            self.verifications.push(verification);
+        }
+    }
+
+    /// Resizes a recently handled window whose title changed to one with a `[title_ratios]` ratio.
+    fn handle_title(&mut self, node: Node) {
+        let now = Instant::now();
+        self.titled.retain(|_, (_, expires)| *expires > now);
+        let parent = match self.titled.get(&node.id) {
+            Some(&(parent, _)) => parent,
+            None => return,
+        };
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let command = match plan_title_ratio(&tree, parent, &node, &self.settings) {
+            Some(command) => command,
+            None => return,
+        };
+        let mut record = EventRecord::new("title", &node, &tree, &self.settings, &self.actions);
+        record.set_commands(std::slice::from_ref(&command));
+        record.decide(if self.run_commands(&[command], &node) {
+            "title ratio"
+        } else {
+            "not run"
+        });
+    }
+
+    /// Gives the focused window `1 - ratio` of its container, with `mode = focus_master`, unless
+    /// focus has moved on since it was scheduled.
+    fn enlarge_focused(&mut self, id: i64) {
+        if self.state.paused || self.settings.mode != Mode::FocusMaster {
+            return;
+        }
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let node = match find_focused(&tree) {
+            Some(node) if node.id == id => node.clone(),
+            _ => {
+                trace!("Focus moved on from {} before it was enlarged", id);
+                return;
+            }
+        };
+
+        self.expire_cooldowns();
+        if let Some(workspace) = find_workspace(id, &tree).and_then(|w| w.name.as_ref()) {
+            if self.remaining_cooldown(workspace).is_some() {
+                trace!("Workspace {:?} was changed by hand recently", workspace);
+                return;
+            }
+        }
+
+        let command = match plan_focus_master(&tree, &node, &self.settings) {
+            Some(command) => command,
+            None => return,
+        };
+        let mut record = EventRecord::new("focus", &node, &tree, &self.settings, &self.actions);
+        record.set_commands(std::slice::from_ref(&command));
+        if self.run_commands(&[command], &node) {
+            record.decide("enlarge");
+        } else {
+            record.decide("not run");
+        }
+    }
+
+    /// Moves `new_node` out of the tab group it opened in, with `escape_tabbed`, checking the
+    /// tree after every move. Returns the tree as it is afterwards.
+    fn escape_group(&mut self, tree: Node, new_node: &Node) -> Node {
+        let extras = self.get_extras();
+        let (group, size) = match find_parent(new_node.id, &tree) {
+            Some(group) => (group.id, group.nodes.len()),
+            None => return tree,
+        };
+
+        let mut tree = tree;
+        for _ in 0..size {
+            let command = match plan_escape(&tree, &extras, new_node, &self.settings) {
+                Some(command) => command,
+                None => return tree,
+            };
+            if !self.run_commands(&[command], new_node) {
+                return tree;
+            }
+
+            tree = match self.get_tree() {
+                Some(current) => current,
+                None => return tree,
+            };
+            if find_parent(new_node.id, &tree).is_none_or(|p| p.id != group) {
+                info!("Moved {:?} out of group {}", new_node.name, group);
+                return tree;
+            }
+        }
+
+        warn!("Could not move {:?} out of group {}", new_node.name, group);
+        tree
+    }
+
+    /// Switches a tabbed or stacked container to a split, and checks that i3 actually did so
+    /// before the window is resized in it. Returns the tree after the conversion, or `None` when
+    /// it didn't work, in which case the container is put back how it was.
+    fn convert_tabbed(&mut self, new_node: &Node, conversion: Conversion) -> Option<Node> {
+        if !self.run_commands(&[conversion.convert], new_node) {
+            return None;
+        }
+
+        let tree = self.get_tree()?;
+        if find_parent(new_node.id, &tree).is_some_and(|p| split_direction(p).is_some()) {
+            return Some(tree);
+        }
+
+        warn!(
+            "Converting the container of {:?} didn't take effect, reverting",
+            new_node.name
+        );
+        self.run_commands(&[conversion.revert], new_node);
+        None
+    }
+
+    /// Repairs the container a recently handled window was in, when it floats or moves away
+    /// shortly after ratiosplit split and resized for it.
+    fn handle_left(&mut self, node: Node) {
+        let now = Instant::now();
+        self.pending.retain(|_, pending| pending.expires > now);
+
+        let pending = match self.pending.remove(&node.id) {
+            Some(p) => p,
+            None => return,
+        };
+
+        self.cancel_animation(pending.before.parent);
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let mut record = EventRecord::new("left", &node, &tree, &self.settings, &self.actions);
+        if find_parent(node.id, &tree).is_some_and(|p| p.id == pending.before.parent) {
+            record.decide("still in container");
+            return;
+        }
+
+        match plan_repair(&tree, &pending.before, &self.settings) {
+            Some(commands) => {
+                record.set_commands(&commands);
+                record.decide(if self.run_commands(&commands, &node) {
+                    "repair"
+                } else {
+                    "repair not run"
+                });
+            }
+            None => record.decide("nothing to repair"),
+        }
+    }
+
+    /// Plays the given resize steps out over `animate_ms` on a separate thread, keyed by the
+    /// parent container so that later events in it can cancel the rest.
+    fn start_animation(&mut self, parent_id: i64, steps: Vec<Command>) {
+        self.animations
+            .retain(|_, animation| !animation.is_finished());
+
+        let interval = Duration::from_millis(self.settings.animate_ms) / steps.len() as u32;
+        match Animation::spawn(steps, interval) {
+            Ok(animation) => {
+                self.animations.insert(parent_id, animation);
+            }
+            Err(error) => warn!("Error starting animation: {:?}", error),
+        }
+    }
+
+    fn cancel_animation(&mut self, parent_id: i64) {
+        if let Some(animation) = self.animations.remove(&parent_id) {
+            trace!("Cancelling animation in {}", parent_id);
+            animation.cancel();
+        }
+    }
+
+    /// Moves a new window to the given workspace, handles it there like any other new window, and
+    /// then either follows it or goes back to the workspace it came from. The Move event this
+    /// causes is ignored like every other Move event, so the window is only handled once.
+    fn move_overflow(&mut self, new_node: Node, destination: i32, origin: Option<String>) {
+        let move_command = Command::MoveToWorkspace {
+            con_id: new_node.id,
+            number: destination,
+        };
+        if !self.run_commands(&[move_command], &new_node) {
+            return;
+        }
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let extras = self.get_extras();
+        let focused = find_focused(&tree).map(|f| f.id);
+        let planned = plan_commands(
+            &tree,
+            &extras,
+            &self.state.remembered,
+            &new_node,
+            focused,
+            &self.settings_for(&tree, new_node.id),
+        )
+        .commands();
+        if let Some(commands) = &planned {
+            self.run_commands(commands, &new_node);
+        }
+
+        // Moving a window leaves focus behind, but the commands run in the destination focus it.
+        let focus = match origin {
+            _ if self.settings.follow_focus => Command::Focus(new_node.id),
+            Some(origin) if planned.is_some() => Command::Workspace(origin),
+            _ => return,
+        };
+        if self.run_commands(&[focus], &new_node) {
+            info!("Moved {:?} to workspace {}", new_node.name, destination);
+        }
+    }
+
+    /// Puts overflowed containers that have dropped below `overflow_threshold` back to their
+    /// split layout, and forgets the ones that no longer exist.
+    fn restore_overflowed(&mut self) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let overflowed: Vec<(i64, SplitDirection)> =
+            self.overflowed.iter().map(|(&id, &d)| (id, d)).collect();
+
+        for (id, direction) in overflowed {
+            let container = match find_node(id, &tree) {
+                Some(c) if matches!(c.layout, NodeLayout::Stacked | NodeLayout::Tabbed) => c,
+                _ => {
+                    trace!(
+                        "Container {} is gone or no longer overflowed, forgetting it",
+                        id
+                    );
+                    self.overflowed.remove(&id);
+                    continue;
+                }
+            };
+
+            if let Some(commands) = plan_overflow_restore(container, direction, &self.settings) {
+                if self.run_commands(&commands, container) {
+                    self.overflowed.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn handle_fullscreen_change(&mut self, node: Node) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let mut record =
+            EventRecord::new("fullscreen", &node, &tree, &self.settings, &self.actions);
+
+        let extras = self.get_extras();
+        let commands = match plan_fullscreen_change(&tree, &extras, &node, &self.settings) {
+            Some(c) => c,
+            None => {
+                record.decide("skip");
+                return;
+            }
+        };
+
+        record.set_commands(&commands);
+        let before = find_parent(node.id, &tree).and_then(|parent| snapshot(parent, Vec::new()));
+        if !self.run_commands(&commands, &node) {
+            record.decide("rebalance not run");
+            return;
+        }
+
+        record.decide("rebalance");
+        if let Some(before) = before {
+            self.remember(
+                format!("rebalance after {:?} left fullscreen", node.name),
+                before,
+            );
+        }
+    }
+
+    /// Records a layout change so that `undo` can revert it, forgetting the oldest past
+    /// `UNDO_HISTORY`.
+    fn remember(&mut self, description: String, before: Snapshot) {
+        if self.history.len() == UNDO_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(UndoEntry {
+            description,
+            before,
+        });
+    }
+
+    /// Reverts the most recent layout change whose containers still exist. Changes whose
+    /// containers have since closed can't be reverted, and are dropped on the way.
+    fn undo(&mut self) -> Response {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return Response::error("Could not get the tree from i3"),
+        };
+        while let Some(entry) = self.history.pop_back() {
+            let commands = match plan_restore(&tree, &entry.before, &self.settings) {
+                Some(c) => c,
+                None => {
+                    info!(
+                        "Dropping undo entry {:?}, its containers have changed",
+                        entry.description
+                    );
+                    continue;
+                }
+            };
+
+            if self.run_commands(&commands, &tree) {
+                info!("Undid {}", entry.description);
+                return Response::ok(format!("Undid {}", entry.description));
+            }
+
+            let response = Response::error(format!("Could not undo {}", entry.description));
+            self.history.push_back(entry);
+            return response;
+        }
+
+        Response::ok("Nothing to undo")
+    }
+
+    /// Fetches the current tree. When that fails, the connection to i3 is taken to be lost, and
+    /// the main loop exits once what it's handling gives up.
+    fn get_tree(&mut self) -> Option<Node> {
+        trace!("Retreiving current tree");
+
+        match self.tree_connection.get_tree() {
+            Ok((tree, extras)) => {
+                self.extras = extras;
+                trace!("Retrieved tree.");
+                Some(tree)
+            }
+            Err(error) => {
+                error!("Error retreiving the current i3 tree: {:?}", error);
+                self.lost_connection = true;
+                None
+            }
+        }
+    }
+
+    /// The tree fields i3ipc doesn't expose, as of the tree fetched last.
+    fn get_extras(&self) -> Extras {
+        self.extras.clone()
+    }
+
+    /// Runs `pre_split_command` for `new_node`, checking that i3 accepted every command. Returns
+    /// whether the window should still be arranged.
+    fn run_pre_split(&mut self, tree: &Node, new_node: &Node) -> bool {
+        if self.settings.suggest {
+            return true;
+        }
+
+        let pre_split = plan_hook(self.settings.pre_split_command.as_deref(), tree, new_node);
+        for command in render_all(&pre_split) {
+            info!("Running {:?} for {:?}", command, new_node.name);
+            match self.connection.run_command(&command) {
+                Ok(reply) if reply.outcomes.iter().all(|o| o.success) => {}
+                result => {
+                    warn!("Pre-split command {:?} failed: {:?}", command, result);
+                    return arranges_after_failed_pre_split(&self.settings);
+                }
+            }
+        }
+        true
+    }
+
+    /// Runs the given commands in order, stopping at the first failure. Returns whether all
+    /// commands were run successfully. In suggest mode nothing is ever run.
+    fn run_commands(&mut self, commands: &[Command], node: &Node) -> bool {
+        if self.settings.suggest {
+            info!(
+                "Suggest mode, not running {:?} for {:?}",
+                render_all(commands),
+                node.name
+            );
+            return false;
+        }
+
+        let held;
+        let commands = if self.settings.never_change_focus {
+            let tree = match self.get_tree() {
+                Some(tree) => tree,
+                None => return false,
+            };
+            let focused = find_focused(&tree).map(|node| node.id);
+            match hold_focus(commands.to_vec(), focused) {
+                Ok(commands) => {
+                    held = commands;
+                    &held
+                }
+                Err(_) => return false,
+            }
+        } else {
+            commands
+        };
+
+        let combined;
+        let commands = if self.capabilities.resize_set_both {
+            combined = combine_resizes(commands);
+            &combined
+        } else {
+            commands
+        };
+
+        let mut split_phase = false;
+        let mut focused = None;
+        for command in commands {
+            if command.is_split_phase() {
+                split_phase = true;
+            } else if split_phase && command.is_resize() {
+                split_phase = false;
+                if self.settings.phase_delay_ms > 0 {
+                    trace!("Waiting {}ms before resizing", self.settings.phase_delay_ms);
+                    thread::sleep(Duration::from_millis(self.settings.phase_delay_ms));
+                }
+            }
+
+            if let Command::Focus(id) = command {
+                focused = Some(*id);
+                self.own_focus
+                    .retain(|_, focused| focused.elapsed() < OWN_FOCUS_WINDOW);
+                self.own_focus.insert(*id, Instant::now());
+            }
+            if let Command::Swap { .. } = command {
+                if !self.capabilities.swap {
+                    info!("This i3 does not support swap, skipping {}", command);
+                    continue;
+                }
+            }
+
+            let rendered = command.render(self.backend);
+            trace!("Running {}", rendered);
+            self.commands_run += 1;
+            match self.connection.run_command(rendered.as_str()) {
+                Err(error) => {
+                    debug!("Running {:?} for node {:?} failed", rendered, node.name);
+                    self.failures.record(&rendered, format!("{:?}", error));
+                    return false;
+                }
+                Ok(reply) => {
+                    let error = match failed_outcome(&reply.outcomes) {
+                        Some(error) => error,
+                        None => continue,
+                    };
+                    self.failures.record(&rendered, error.clone());
+                    if !command.is_resize() {
+                        return false;
+                    }
+                    let target = match command {
+                        Command::Resize {
+                            con_id: Some(id), ..
+                        }
+                        | Command::ResizeBoth { con_id: id, .. } => Some(*id),
+                        _ => focused,
+                    };
+                    self.handle_resize_noop(command, target, &error);
+                }
+            }
+        }
+
+        if self.settings.mode == Mode::Remember {
+            if let Some(tree) = self.get_tree() {
+                self.observe(&tree);
+            }
+        }
+        true
+    }
+
+    /// Deals with i3 refusing `resize` for `target`, according to `on_resize_noop`.
+    fn handle_resize_noop(&mut self, resize: &Command, target: Option<i64>, error: &str) {
+        let target = match target {
+            Some(target) if self.settings.on_resize_noop == ResizeNoop::RetrySibling => target,
+            _ => {
+                info!("i3 refused {}: {}, leaving it", resize, error);
+                return;
+            }
+        };
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let commands = match plan_sibling_resize(&tree, target, resize) {
+            Some(commands) => commands,
+            None => {
+                info!(
+                    "i3 refused {}: {}, and there's no sibling to resize",
+                    resize, error
+                );
+                return;
+            }
+        };
+
+        info!(
+            "i3 refused {}: {}, running {:?} instead",
+            resize,
+            error,
+            render_all(&commands)
+        );
+        for command in render_all(&commands) {
+            let error = match self.connection.run_command(&command) {
+                Ok(reply) => failed_outcome(&reply.outcomes),
+                Err(error) => Some(format!("{:?}", error)),
+            };
+            if let Some(error) = error {
+                self.failures.record(&command, error);
+                return;
+            }
+        }
+    }
+
+    /// Answers a control request on `stream`, or in the log for requests from the fifo.
+    fn handle_control(&mut self, line: &str, stream: Option<UnixStream>) {
+        trace!("Control request {:?}", line);
+
+        let mut words = line.split_whitespace();
+        let verb = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        let response = match verb {
+            "status" => Response::ok(self.status_report()).with_data(self.status_json()),
+            "pause" => self.set_paused(true),
+            "resume" => self.set_paused(false),
+            "toggle" => self.set_paused(!self.state.paused),
+            "cycle-ratio" => self.cycle_ratio(args.contains(&"apply")),
+            "undo" => self.undo(),
+            "explain" => self.explain(),
+            "tree" if args.is_empty() => self.tree(None),
+            "tree" => self.tree(Some(&args.join(" "))),
+            "promote" => self.promote(),
+            "lock" => match args.first() {
+                Some(percent) => self.lock(Some(percent)),
+                None => Response::error("lock needs a percent"),
+            },
+            "unlock" => self.lock(None),
+            "set-mode" => self.set_mode(&args),
+            "history" => {
+                let limit = args
+                    .first()
+                    .and_then(|limit| limit.parse().ok())
+                    .unwrap_or(usize::MAX);
+                let actions = self.actions.recent(limit);
+                Response::ok(render_table(&actions)).with_data(json!(actions))
+            }
+            "rules" => {
+                let rules = self.settings.sizing_rules();
+                Response::ok(render_rules(&rules, &self.settings)).with_data(json!({
+                    "rules": rules,
+                    "mode": self.settings.mode,
+                    "ratio": self.settings.ratio_description(),
+                }))
+            }
+            "suggest" => {
+                let suggestions = self.log_suggestions();
+                Response::ok(format!("{} workspace(s) drifted", suggestions.len()))
+                    .with_data(Value::Object(suggestions))
+            }
+            "subscribe" => match stream {
+                Some(mut stream) => {
+                    let response = Response::ok(self.status_text()).with_data(self.status_json());
+                    if stream.write_all(response.to_line().as_bytes()).is_ok() {
+                        self.subscribers.push(stream);
+                    }
+                    return;
+                }
+                None => Response::error("Can't subscribe through the fifo"),
+            },
+            _ => Response::error(format!(
+                "Unknown command {:?}, expected one of {}",
+                verb,
+                CONTROL_VERBS.join(", ")
+            )),
+        };
+
+        match stream {
+            Some(mut stream) => {
+                if let Err(error) = stream.write_all(response.to_line().as_bytes()) {
+                    warn!("Error writing control response: {:?}", error);
+                }
+            }
+            None if response.ok => info!("Control request {:?}: {}", line, response.message),
+            None => warn!("Control request {:?} failed: {}", line, response.message),
+        }
+    }
+
+    /// Switches to the next ratio preset, wrapping around at the end, and optionally re-applies
+    /// the focused workspace with it.
+    fn cycle_ratio(&mut self, apply: bool) -> Response {
+        if self.settings.ratio_presets.is_empty() {
+            warn!("cycle-ratio requested, but no ratio_presets are configured");
+            return Response::ok("No ratio_presets configured");
+        }
+
+        let index = match self.state.ratio_preset {
+            Some(i) => (i + 1) % self.settings.ratio_presets.len(),
+            None => 0,
+        };
+        let ratio = self.settings.ratio_presets[index];
+
+        info!("Switching to ratio preset {} ({})", index, ratio);
+        self.settings.set_ratio(ratio);
+        self.state.ratio_preset = Some(index);
+        self.state_changed();
+
+        if apply {
+            self.apply_focused_workspace();
+        }
+
+        Response::ok(self.status_text()).with_data(self.status_json())
+    }
+
+    /// Swaps the focused window with the largest one on its workspace, then re-applies the ratios
+    /// there.
+    fn promote(&mut self) -> Response {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return Response::error("Could not get the tree from i3"),
+        };
+        let focused = match find_focused(&tree) {
+            Some(f) => f,
+            None if is_floating_focused(&tree) => {
+                return Response::error("The focused window is floating, not promoting it")
+            }
+            None => return Response::error("Could not find the focused window"),
+        };
+
+        let swap = match plan_promote(&tree, focused) {
+            Some(swap) => swap,
+            None => return Response::ok("The focused window is already the largest"),
+        };
+        if !self.run_commands(&[swap], &tree) {
+            return Response::error(format!("Could not promote {:?}", focused.name));
+        }
+
+        self.apply_focused_workspace();
+        Response::ok(format!("Promoted {:?}", focused.name))
+    }
+
+    /// Switches how new windows are laid out, and optionally re-applies the focused window's
+    /// container in the new mode.
+    fn set_mode(&mut self, args: &[&str]) -> Response {
+        let mode: Mode = match args.first().map(|mode| mode.parse()) {
+            Some(Ok(mode)) => mode,
+            _ => {
+                return Response::error(format!(
+                    "Unknown mode {:?}, expected spiral, equalize, master, dynamic, focus_master, columns, match_reference or remember",
+                    args.first().unwrap_or(&"")
+                ))
+            }
+        };
+
+        info!("Switching to {} mode", mode);
+        self.settings.mode = mode;
+        self.notify_subscribers();
+
+        if args.contains(&"apply") {
+            match mode {
+                Mode::Spiral | Mode::Dynamic | Mode::MatchReference | Mode::Remember => {
+                    self.apply_focused_workspace()
+                }
+                Mode::Columns => self.apply_columns(),
+                _ => self.apply_focused_container(),
+            }
+        }
+
+        Response::ok(self.status_text()).with_data(self.status_json())
+    }
+
+    /// Lays the focused window's container out again, as if the focused window had just opened.
+    fn apply_focused_container(&mut self) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let focused = match find_focused(&tree) {
+            Some(f) => f,
+            None => {
+                info!("Could not find the focused window, not applying");
+                return;
+            }
+        };
+
+        let extras = self.get_extras();
+        if let Some(commands) = plan_commands(
+            &tree,
+            &extras,
+            &self.state.remembered,
+            focused,
+            None,
+            &self.settings_for(&tree, focused.id),
+        )
+        .commands()
+        {
+            self.run_commands(&commands, focused);
+        }
+    }
+
+    /// Brings every container on the focused workspace back in line with the planner's layout.
+    fn apply_focused_workspace(&mut self) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        match find_focused(&tree).and_then(|f| find_workspace(f.id, &tree)) {
+            Some(workspace) => self.apply_workspace(&tree, workspace),
+            None => info!("Could not find the focused workspace, not applying"),
+        }
+    }
+
+    /// Logs the container a new window was handled in, as it was in the pre-command tree and is
+    /// now, for `verify_log`.
+    fn log_verify(&mut self, before: &Node) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let after = match find_node(before.id, &tree) {
+            Some(after) => after,
+            None => {
+                info!("verify_log parent={} gone", before.id);
+                return;
+            }
+        };
+        let target = effective_ratio(
+            &tree,
+            after,
+            after.layout == NodeLayout::SplitH,
+            &self.settings,
+        );
+        info!("{}", verify_line(before, after, target));
+    }
+
+    /// Puts the focused workspace's columns back to their ratios, with `mode = columns`. Focus
+    /// stays on the workspace a window closed on, so that's the one that lost a column.
+    fn apply_columns(&mut self) {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        let workspace = match find_focused(&tree).and_then(|f| find_workspace(f.id, &tree)) {
+            Some(workspace) => workspace,
+            None => return,
+        };
+        if !workspace
+            .name
+            .as_deref()
+            .is_none_or(|name| self.settings.is_workspace_managed(name))
+        {
+            return;
+        }
+
+        if let Some(commands) = plan_column_widths(&tree, workspace, &self.settings) {
+            info!("Spreading the columns of {:?} out again", workspace.name);
+            self.run_commands(&commands, workspace);
+        }
+    }
+
+    /// Runs every adjustment the planner finds for the given workspace, if it is managed.
+    fn apply_workspace(&mut self, tree: &Node, workspace: &Node) {
+        if !workspace
+            .name
+            .as_deref()
+            .is_none_or(|name| self.settings.is_workspace_managed(name))
+        {
+            info!(
+                "Workspace {:?} is not managed, not applying",
+                workspace.name
+            );
+            return;
+        }
+
+        let extras = self.get_extras();
+        for adjustment in plan_workspace(
+            tree,
+            &extras,
+            workspace,
+            &self.settings_for(tree, workspace.id),
+        ) {
+            trace!(
+                "Applying to container {}: {}",
+                adjustment.container,
+                adjustment.description
+            );
+            if !self.run_commands(&adjustment.commands, workspace) {
+                return;
+            }
+        }
+        self.restore_locks();
+
+        info!("Applied workspace {:?}", workspace.name);
+    }
+
+    fn schedule_suggestion(&mut self) {
+        self.next_suggestion = if self.settings.suggest && self.settings.suggest_interval > 0 {
+            Some(Instant::now() + Duration::from_secs(self.settings.suggest_interval * 60))
+        } else {
+            None
+        };
+    }
+
+    fn schedule_reassert(&mut self) {
+        self.next_reassert = match self.settings.reassert_interval_secs {
+            0 => None,
+            interval => Some(Instant::now() + Duration::from_secs(interval)),
+        };
+    }
+
+    /// Puts every managed workspace that has drifted back to the ratios. Workspaces the user
+    /// changed by hand within `manual_cooldown`, and ones a layout is being restored on, are left
+    /// alone.
+    fn reassert(&mut self) {
+        if self.state.paused || self.settings.suggest {
+            return;
+        }
+
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return,
+        };
+        self.expire_cooldowns();
+        for workspace in workspaces(&tree) {
+            let name = match &workspace.name {
+                Some(n) if self.settings.is_workspace_managed(n) => n,
+                _ => continue,
+            };
+            if self.remaining_cooldown(name).is_some() || self.is_restoring(workspace) {
+                trace!("Not reasserting {:?}, it was changed recently", name);
+                continue;
+            }
+
+            let extras = self.get_extras();
+            for adjustment in plan_workspace(
+                &tree,
+                &extras,
+                workspace,
+                &self.settings_for(&tree, workspace.id),
+            ) {
+                debug!(
+                    "Reasserting {:?}: container {} {}, running {:?}",
+                    name,
+                    adjustment.container,
+                    adjustment.description,
+                    render_all(&adjustment.commands)
+                );
+                if !self.run_commands(&adjustment.commands, workspace) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Compares every managed workspace against what the planner would produce, logging any
+    /// drift along with the commands that would fix it. Nothing is run.
+    fn log_suggestions(&mut self) -> serde_json::Map<String, Value> {
+        let tree = match self.get_tree() {
+            Some(tree) => tree,
+            None => return serde_json::Map::new(),
+        };
+        let mut suggestions = serde_json::Map::new();
+
+        for workspace in workspaces(&tree) {
+            let name = match &workspace.name {
+                Some(n) if self.settings.is_workspace_managed(n) => n,
+                _ => continue,
+            };
+
+            let extras = self.get_extras();
+            let adjustments = plan_workspace(
+                &tree,
+                &extras,
+                workspace,
+                &self.settings_for(&tree, workspace.id),
+            );
+            if adjustments.is_empty() {
+                trace!("Workspace {:?} matches the spiral", name);
+                continue;
+            }
+
+            for adjustment in &adjustments {
+                info!(
+                    "Workspace {:?} drifted: container {} {}; fix with {:?}",
+                    name,
+                    adjustment.container,
+                    adjustment.description,
+                    render_all(&adjustment.commands)
+                );
+            }
+
+            suggestions.insert(
+                name.clone(),
+                adjustments
+                    .iter()
+                    .map(|a| {
+                        json!({
+                            "container": a.container,
+                            "description": a.description,
+                            "commands": render_all(&a.commands),
+                        })
+                    })
+                    .collect(),
+            );
+        }
+
+        suggestions
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Response {
+        if self.state.paused != paused {
+            info!("{}", if paused { "Pausing" } else { "Resuming" });
+            self.state.paused = paused;
+            self.state.failsafe = false;
+            self.failed_handlings = 0;
+            self.state_changed();
+            self.hooks
+                .run(if paused { "paused" } else { "resumed" }, &[]);
+        } else if self.state.failsafe {
+            // Pausing by hand while the failsafe has paused it keeps it paused, as if by hand.
+            self.state.failsafe = false;
+            self.state_changed();
+        }
+        Response::ok(self.status_text()).with_data(self.status_json())
+    }
+
+    fn status_text(&self) -> String {
+        if self.state.failsafe {
+            "paused by failsafe".to_string()
+        } else if self.state.paused {
+            "paused".to_string()
+        } else {
+            format!(
+                "active {} {}",
+                self.settings.mode,
+                self.settings.ratio_description()
+            )
+        }
+    }
+
+    /// The status line, followed by the commands i3 refused recently, if any.
+    fn status_report(&self) -> String {
+        let mut report = self.status_text();
+        for failure in self.failures.recent() {
+            report.push_str("\nfailed: ");
+            report.push_str(&failure.to_string());
+        }
+        report
+    }
+
+    fn status_json(&self) -> Value {
+        let cooldowns: HashMap<&str, f64> = self
+            .manual_changes
+            .keys()
+            .filter_map(|w| Some((w.as_str(), self.remaining_cooldown(w)?.as_secs_f64())))
+            .collect();
+
+        json!({
+            "paused": self.state.paused,
+            "failsafe": self.state.failsafe,
+            "failed_in_a_row": self.failed_handlings,
+            "mode": self.settings.mode,
+            "ratio": self.settings.ratio,
+            "ratio_preset": self.state.ratio_preset,
+            "ratio_vertical": self.settings.ratio_vertical,
+            "auto_ratio": self.settings.auto_ratio,
+            "cooldowns": cooldowns,
+            "verify_failures": self.verify_failures,
+            "self_resizing": self.state.self_resizing,
+            "failures": self.failures.recent().collect::<Vec<_>>(),
+        })
+    }
+
+    /// Persists the state and lets subscribers know about it. Must be called after every change
+    /// to `self.state`.
+    /// Drops what the state keeps for workspaces that are no longer in the tree, or are `closing`.
+    fn prune_state(&mut self, tree: &Node, closing: Option<&str>) {
+        let names: Vec<&str> = workspaces(tree)
+            .into_iter()
+            .filter_map(|workspace| workspace.name.as_deref())
+            .filter(|&name| Some(name) != closing)
+            .collect();
+        if prune_remembered(&mut self.state.remembered, &names) {
+            info!("Forgot the splits remembered on workspaces that are gone");
+            self.state_changed();
+        }
+    }
+
+    fn state_changed(&mut self) {
+        if let Err(error) = save_state(&self.state) {
+            warn!("Error saving state: {:?}", error);
+        }
+        self.notify_subscribers();
+    }
+
+    /// Pushes the current status to every `subscribe`d client, dropping any that have gone away.
+    fn notify_subscribers(&mut self) {
+        let line = Response::ok(self.status_text())
+            .with_data(self.status_json())
+            .to_line();
+        self.subscribers
+            .retain(|mut stream| stream.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+/// Whether a binding's command changes the layout, meaning the user is arranging things by hand.
+/// Bindings can chain several commands with `;` or `,`, any of which may count.
+fn is_manual_layout_command(command: &str) -> bool {
+    command_verbs(command).any(|verb| MANUAL_LAYOUT_COMMANDS.contains(&verb))
+}
+
+fn is_resize_command(command: &str) -> bool {
+    command_verbs(command).any(|verb| verb == "resize")
+}
+
+/// The first word of each command in a binding's command list, skipping criteria.
+fn command_verbs(command: &str) -> impl Iterator<Item = &str> {
+    command
+        .split([';', ','])
+        .filter_map(|c| c.split_whitespace().find(|word| !word.starts_with('[')))
+}
+
+/// The pid of the process owning the given window, if it can be found. X11 windows are asked
+/// for their pid directly, while native Wayland windows need the pid sway puts in the tree.
+fn window_pid(node: &Node, extras: &Extras) -> Option<u32> {
+    match node.window {
+        Some(window) => x11_window_pid(window),
+        None => extras.get(&node.id).and_then(|extras| extras.pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::capabilities_for;
+    use crate::fake_i3::FakeI3;
+    use crate::fixtures::{
+        capture_logs, dock_bar, focus, node, output, root, split, tree, urgent, window, workspace,
+    };
+    use crate::settings::{default_settings, ManageTabbed, RuleMatch};
+    use i3ipc::reply::WindowProperty;
+    use std::cell::Cell;
+    use std::io;
+
+    /// A daemon talking to the fake i3, as an i3 that has every capability.
+    fn daemon(settings: Settings) -> Daemon {
+        Daemon::new(
+            I3Connection::connect().unwrap(),
+            TreeConnection::connect().unwrap(),
+            Backend::I3,
+            capabilities_for(Backend::I3, (4, 22)),
+            settings,
+            State::default(),
+            false,
+        )
+    }
+
+    /// A workspace holding window 100 and window 101, which has just opened and is focused.
+    fn opened_beside() -> Node {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Firefox", (0, 0, 500, 500)),
+                window(101, "Alacritty", (500, 0, 500, 500)),
+            ],
+        );
+        focus(&mut tree, 101);
+        tree
+    }
+
+    fn new_window(tree: &Node, id: i64) -> WindowEventInfo {
+        WindowEventInfo {
+            change: WindowChange::New,
+            container: find_node(id, tree).unwrap().clone(),
+        }
+    }
+
+    #[test]
+    fn timers_remember_the_event_they_were_set_in() {
+        assert_eq!(Timer::after(Duration::from_millis(10)).event, None);
+        let _scope = EventScope::enter(7);
+        assert_eq!(Timer::after(Duration::from_millis(10)).event, Some(7));
+    }
+
+    #[test]
+    fn only_due_timers_are_handled_earliest_first() {
+        let now = Instant::now();
+        let timer = |millis| Timer {
+            due: now - Duration::from_millis(100) + Duration::from_millis(millis),
+            event: None,
+        };
+        let timers: HashMap<i64, Timer> = vec![(1, timer(50)), (2, timer(10)), (3, timer(500))]
+            .into_iter()
+            .collect();
+        let due: Vec<i64> = due_timers(&timers, now)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(due, vec![2, 1]);
+    }
+
+    #[test]
+    fn failed_events_are_counted_in_a_row() {
+        // Refused commands count, whether or not others ran.
+        assert_eq!(failed_in_a_row(2, (4, 10), (5, 12)), 3);
+        // Running commands without failures starts again.
+        assert_eq!(failed_in_a_row(2, (4, 10), (4, 12)), 0);
+        // Running nothing changes nothing.
+        assert_eq!(failed_in_a_row(2, (4, 10), (4, 10)), 2);
+    }
+
+    #[test]
+    fn failsafe_trips_at_the_threshold() {
+        let mut settings = default_settings();
+        settings.failsafe_threshold = 3;
+        assert!(!failsafe_trips(2, &settings));
+        assert!(failsafe_trips(3, &settings));
+        assert!(failsafe_trips(4, &settings));
+
+        settings.failsafe_threshold = 0;
+        assert!(!failsafe_trips(100, &settings));
+    }
+
+    #[test]
+    fn workspaces_are_left_alone_within_the_cooldown() {
+        let mut settings = default_settings();
+        settings.manual_cooldown = 10;
+        let now = Instant::now();
+        let changed = now - Duration::from_secs(4);
+        assert_eq!(
+            cooldown_remaining(changed, now, &settings),
+            Some(Duration::from_secs(6))
+        );
+        assert_eq!(
+            cooldown_remaining(changed, now + Duration::from_secs(6), &settings),
+            None
+        );
+        assert_eq!(
+            cooldown_remaining(changed, now + Duration::from_secs(60), &settings),
+            None
+        );
+
+        settings.manual_cooldown = 0;
+        assert_eq!(cooldown_remaining(changed, now, &settings), None);
+    }
+
+    #[test]
+    fn new_windows_wait_out_a_manual_change() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        let mut settings = default_settings();
+        settings.manual_cooldown = 10;
+        let mut daemon = daemon(settings);
+
+        daemon
+            .manual_changes
+            .insert("1".to_string(), Instant::now());
+        daemon.handle_window_event(new_window(&tree, 101));
+        assert!(i3.commands().is_empty());
+
+        let long_ago = Instant::now() - Duration::from_secs(11);
+        daemon.manual_changes.insert("1".to_string(), long_ago);
+        daemon.handle_window_event(new_window(&tree, 101));
+        assert_eq!(
+            i3.commands().last().unwrap(),
+            "[con_id=101] resize set width 33 ppt"
+        );
+        assert!(daemon.manual_changes.is_empty());
+    }
+
+    #[test]
+    fn windows_leaving_before_they_are_handled_are_forgotten() {
+        let tree = opened_beside();
+        let _i3 = FakeI3::start(&tree);
+        let mut daemon = daemon(default_settings());
+        let window = find_node(101, &tree).unwrap();
+        let later = || Timer::after(Duration::from_secs(60));
+        daemon.awaiting_class.insert(101, later());
+        daemon.delayed.insert(101, later());
+        daemon.delayed.insert(100, later());
+        daemon.deferred = vec![100, 101];
+
+        let lines = capture_logs(|| daemon.forget_unhandled(window));
+        assert!(daemon.awaiting_class.is_empty());
+        assert_eq!(daemon.delayed.keys().collect::<Vec<_>>(), vec![&100]);
+        assert_eq!(daemon.deferred, vec![100]);
+        assert_eq!(
+            lines,
+            vec![
+                "Some(\"Alacritty window\") left before it got its class, not handling it",
+                "Some(\"Alacritty window\") left before handle_delay_ms passed, not handling it",
+            ]
+        );
+
+        // Nothing is said for windows that weren't waiting.
+        assert!(capture_logs(|| daemon.forget_unhandled(window)).is_empty());
+    }
+
+    #[test]
+    fn lines_logged_while_handling_an_event_carry_its_id() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        i3.refuse("resize", "Cannot resize");
+        let mut daemon = daemon(default_settings());
+
+        let lines = capture_logs(|| {
+            let _scope = EventScope::enter(7);
+            daemon.handle_window_event(new_window(&tree, 101));
+        });
+        let logged = |text: &str| lines.iter().find(|line| line.contains(text)).unwrap();
+        assert!(logged("decision=\"resize\"").starts_with("[#7] id=7 event=new window=101"));
+        assert_eq!(
+            logged("resize set"),
+            "[#7] Running [con_id=101] resize set width 33 ppt"
+        );
+        assert!(logged("i3 refused").starts_with("[#7] i3 refused"));
+        assert!(lines.iter().all(|line| line.starts_with("[#7] ")));
+    }
+
+    #[test]
+    fn losing_i3_ends_the_loop_with_a_connection_error() {
+        let i3 = FakeI3::start(&opened_beside());
+        let mut daemon = daemon(default_settings());
+        i3.hang_up();
+
+        let (sender, receiver) = channel();
+        sender
+            .send(Message::Control("tree".to_string(), None))
+            .unwrap();
+        assert_eq!(daemon.run_loop(receiver), Err(ExitReason::Connection));
+    }
+
+    #[test]
+    fn a_conversion_that_doesnt_take_effect_is_reverted() {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Firefox", (0, 0, 600, 500)),
+                split(
+                    200,
+                    NodeLayout::Tabbed,
+                    (600, 0, 400, 500),
+                    vec![
+                        window(101, "Alacritty", (600, 20, 400, 480)),
+                        window(102, "Alacritty", (600, 20, 400, 480)),
+                    ],
+                ),
+            ],
+        );
+        focus(&mut tree, 102);
+        // The fake i3 keeps reporting the group as tabbed.
+        let i3 = FakeI3::start(&tree);
+        let mut settings = default_settings();
+        settings.manage_tabbed = ManageTabbed::Convert;
+        let mut daemon = daemon(settings);
+
+        daemon.handle_window_event(new_window(&tree, 102));
+        assert_eq!(
+            i3.commands(),
+            vec!["[con_id=102] layout splitv", "[con_id=102] layout tabbed"]
+        );
+    }
+
+    #[test]
+    fn windows_are_resized_once_their_container_is_converted() {
+        let tabs = |layout| {
+            let mut tree = tree(
+                "1",
+                NodeLayout::SplitH,
+                vec![split(
+                    300,
+                    layout,
+                    (0, 0, 1000, 500),
+                    vec![
+                        window(101, "Firefox", (0, 20, 1000, 480)),
+                        window(102, "Alacritty", (0, 20, 1000, 480)),
+                    ],
+                )],
+            );
+            focus(&mut tree, 102);
+            tree
+        };
+        let tree = tabs(NodeLayout::Tabbed);
+        let i3 = FakeI3::start(&tree);
+        let mut converted = tabs(NodeLayout::SplitH);
+        let container = &mut converted.nodes[0].nodes[0].nodes[0];
+        container.nodes[0].rect = (0, 0, 500, 500);
+        container.nodes[1].rect = (500, 0, 500, 500);
+        i3.change_after("layout splith", &converted);
+        let mut settings = default_settings();
+        settings.manage_tabbed = ManageTabbed::Convert;
+        let mut daemon = daemon(settings);
+
+        daemon.handle_window_event(new_window(&tree, 102));
+        assert_eq!(
+            i3.commands(),
+            vec![
+                "[con_id=102] layout splith",
+                "[con_id=101] focus",
+                "split vertical",
+                "[con_id=102] focus",
+                "split vertical",
+                "[con_id=102] focus",
+                "[con_id=102] resize set width 33 ppt",
+            ]
+        );
+    }
+
+    #[test]
+    fn undo_restores_the_sizes_from_before_a_resize() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        let mut resized = opened_beside();
+        let children = &mut resized.nodes[0].nodes[0].nodes;
+        children[0].percent = Some(0.67);
+        children[1].percent = Some(0.33);
+        i3.change_after("resize", &resized);
+        let mut daemon = daemon(default_settings());
+
+        daemon.handle_window_event(new_window(&tree, 101));
+        let ran = i3.commands().len();
+        let response = daemon.undo();
+        assert!(response.ok);
+        assert_eq!(
+            response.message,
+            "Undid resize for Some(\"Alacritty window\")"
+        );
+        assert_eq!(
+            i3.commands()[ran..],
+            [
+                "[con_id=100] layout splith",
+                "[con_id=101] layout splith",
+                "[con_id=100] focus",
+                "resize set width 50 ppt",
+                "[con_id=101] focus",
+            ]
+        );
+        assert_eq!(daemon.undo().message, "Nothing to undo");
+    }
+
+    #[test]
+    fn undo_drops_entries_whose_containers_changed() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        let mut daemon = daemon(default_settings());
+        let workspace = &tree.nodes[0].nodes[0];
+        daemon.remember(
+            "resize".to_string(),
+            snapshot(workspace, Vec::new()).unwrap(),
+        );
+        let mut closed = workspace.clone();
+        closed.id = 11;
+        daemon.remember("closed".to_string(), snapshot(&closed, Vec::new()).unwrap());
+
+        let lines = capture_logs(|| assert_eq!(daemon.undo().message, "Undid resize"));
+        assert!(lines
+            .iter()
+            .any(|line| line == "Dropping undo entry \"closed\", its containers have changed"));
+        assert_eq!(
+            i3.commands(),
+            vec![
+                "[con_id=100] focus",
+                "resize set width 50 ppt",
+                "[con_id=101] focus"
+            ]
+        );
+        assert_eq!(daemon.undo().message, "Nothing to undo");
+    }
+
+    #[test]
+    fn dock_windows_are_left_alone() {
+        let mut tree = opened_beside();
+        dock_bar(&mut tree, 300);
+        let i3 = FakeI3::start(&tree);
+        let mut daemon = daemon(default_settings());
+        let bar = &tree.nodes[0].nodes[0].nodes[0];
+
+        daemon.handle_window_event(WindowEventInfo {
+            change: WindowChange::New,
+            container: bar.clone(),
+        });
+        assert!(i3.commands().is_empty());
+    }
+
+    #[test]
+    fn swaps_are_left_out_on_an_i3_without_swap() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        let mut settings = default_settings();
+        settings.promote_new_to_master = true;
+        let mut daemon = daemon(settings);
+        daemon.capabilities = capabilities_for(Backend::I3, (4, 13));
+
+        daemon.handle_window_event(new_window(&tree, 101));
+        let commands = i3.commands();
+        assert!(!commands.iter().any(|command| command.contains("swap")));
+        assert_eq!(
+            commands.last().unwrap(),
+            "[con_id=101] resize set width 33 ppt"
+        );
+    }
+
+    #[test]
+    fn failed_pre_split_commands_abort_unless_set_to_continue() {
+        let mut settings = default_settings();
+        settings.pre_command_failure = PreCommandFailure::Abort;
+        assert!(!arranges_after_failed_pre_split(&settings));
+        settings.pre_command_failure = PreCommandFailure::Continue;
+        assert!(arranges_after_failed_pre_split(&settings));
+    }
+
+    #[test]
+    fn pre_split_commands_run_first_and_can_stop_the_rest() {
+        let tree = opened_beside();
+        let run = |policy, refused: Option<&str>| {
+            let i3 = FakeI3::start(&tree);
+            if let Some(command) = refused {
+                i3.refuse(command, "No such mark");
+            }
+            let mut settings = default_settings();
+            settings.pre_split_command =
+                Some("mark --add pre_{con_id}; mark --add second_{con_id}".to_string());
+            settings.pre_command_failure = policy;
+            daemon(settings).handle_window_event(new_window(&tree, 101));
+            i3.commands()
+        };
+        let resize = "[con_id=101] resize set width 33 ppt".to_string();
+
+        let commands = run(PreCommandFailure::Abort, None);
+        assert_eq!(
+            commands[..2],
+            ["mark --add pre_101", "mark --add second_101"]
+        );
+        assert_eq!(commands.last(), Some(&resize));
+
+        // The rest of the pre-split commands are left out either way.
+        let commands = run(PreCommandFailure::Abort, Some("pre_"));
+        assert_eq!(commands, vec!["mark --add pre_101"]);
+        let commands = run(PreCommandFailure::Continue, Some("pre_"));
+        assert_eq!(commands[..2], ["mark --add pre_101", "[con_id=100] focus"]);
+        assert_eq!(commands.last(), Some(&resize));
+    }
+
+    #[test]
+    fn refused_resizes_are_left_or_retried_on_the_sibling() {
+        let tree = opened_beside();
+        let run = |action| {
+            let i3 = FakeI3::start(&tree);
+            i3.refuse("[con_id=101] resize", "Cannot resize");
+            let mut settings = default_settings();
+            settings.on_resize_noop = action;
+            let mut daemon = daemon(settings);
+            daemon.handle_window_event(new_window(&tree, 101));
+            assert_eq!(daemon.failures.total(), 1);
+            i3.commands()
+        };
+        let refused = "[con_id=101] resize set width 33 ppt";
+
+        let commands = run(ResizeNoop::Ignore);
+        assert_eq!(commands.last().unwrap(), refused);
+        let commands = run(ResizeNoop::RetrySibling);
+        assert_eq!(
+            commands[commands.len() - 2..],
+            [refused, "[con_id=100] resize set width 67 ppt"]
+        );
+    }
+
+    #[test]
+    fn remembered_ratios_are_used_for_the_next_window_in_the_container() {
+        let beside = |new, share: f64| {
+            let mut tree = tree(
+                "1",
+                NodeLayout::SplitH,
+                vec![
+                    window(100, "Firefox", (0, 0, 500, 500)),
+                    window(new, "Alacritty", (500, 0, 500, 500)),
+                ],
+            );
+            let children = &mut tree.nodes[0].nodes[0].nodes;
+            children[0].percent = Some(1.0 - share);
+            children[1].percent = Some(share);
+            focus(&mut tree, new);
+            tree
+        };
+        let i3 = FakeI3::start(&beside(101, 0.4));
+        let mut settings = default_settings();
+        settings.mode = Mode::Remember;
+        let mut daemon = daemon(settings);
+
+        // Resized with a key binding.
+        daemon.remember_splits();
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.4));
+
+        // Dragged with the mouse, which is only noticed by comparing trees.
+        daemon.observe(&beside(101, 0.4));
+        daemon.remember_changed_splits(&beside(101, 0.25));
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.25));
+        // Moves within DRIFT_TOLERANCE are i3 rounding, not the user.
+        daemon.remember_changed_splits(&beside(101, 0.26));
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.25));
+
+        // Window 101 closed, and window 102 opens in its place.
+        let next = beside(102, 0.5);
+        i3.set_tree(&next);
+        daemon.handle_window_event(new_window(&next, 102));
+        assert_eq!(
+            i3.commands().last().unwrap(),
+            "[con_id=102] resize set width 25 ppt"
+        );
+    }
+
+    /// Windows 100 and 101 side by side, with window 101 focused and taking up `share`.
+    fn sharing(share: f64) -> Node {
+        let mut tree = opened_beside();
+        let children = &mut tree.nodes[0].nodes[0].nodes;
+        children[0].percent = Some(1.0 - share);
+        children[1].percent = Some(share);
+        tree
+    }
+
+    #[test]
+    fn apps_resizing_themselves_are_resized_once_more_then_left() {
+        let tree = opened_beside();
+        let i3 = FakeI3::start(&tree);
+        i3.change_after("resize set width 33 ppt", &sharing(0.33));
+        let mut settings = default_settings();
+        settings.verify = false;
+        settings.settle_ms = 100;
+        let mut daemon = daemon(settings);
+        let settle = |tree: &Node, daemon: &mut Daemon| {
+            i3.set_tree(tree);
+            let ran = i3.commands().len();
+            let verification = daemon.verifications.pop().unwrap();
+            assert!(verification.settle);
+            daemon.verify(verification);
+            i3.commands()[ran..].to_vec()
+        };
+
+        // Drifting within DRIFT_TOLERANCE isn't the app.
+        daemon.handle_window_event(new_window(&tree, 101));
+        assert_eq!(settle(&sharing(0.345), &mut daemon), Vec::<String>::new());
+        assert!(daemon.verifications.is_empty());
+
+        daemon.handle_window_event(new_window(&tree, 101));
+        assert_eq!(
+            settle(&sharing(0.5), &mut daemon),
+            vec!["[con_id=101] resize set width 330 px"]
+        );
+        // It snapped back again, so it's left, and its class noted.
+        assert_eq!(settle(&sharing(0.5), &mut daemon), Vec::<String>::new());
+        assert!(daemon.verifications.is_empty());
+        assert!(daemon.state.self_resizing.contains("Alacritty"));
+    }
+
+    #[test]
+    fn resizing_by_hand_updates_the_remembered_ratio_and_the_cooldown() {
+        let i3 = FakeI3::start(&sharing(0.5));
+        let mut settings = default_settings();
+        settings.mode = Mode::Remember;
+        settings.manual_cooldown = 10;
+        let mut daemon = daemon(settings);
+        let binding = |command: &str| -> BindingEventInfo {
+            json!({
+                "change": "run",
+                "binding": {
+                    "command": command,
+                    "event_state_mask": ["Mod4"],
+                    "input_code": 0,
+                    "symbol": "l",
+                    "input_type": "keyboard",
+                },
+            })
+            .to_string()
+            .parse()
+            .unwrap()
+        };
+
+        i3.set_tree(&sharing(0.6));
+        daemon.handle_binding_event(binding("resize grow width 10 px or 10 ppt"));
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.6));
+        assert!(daemon.remaining_cooldown("1").is_some());
+
+        // Each resize replaces what was remembered.
+        i3.set_tree(&sharing(0.7));
+        daemon.handle_binding_event(binding("resize grow width 10 px or 10 ppt"));
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.7));
+
+        // Other bindings change neither.
+        daemon.manual_changes.clear();
+        i3.set_tree(&sharing(0.45));
+        daemon.handle_binding_event(binding("focus left"));
+        assert_eq!(daemon.state.remembered.get("1"), Some(&0.7));
+        assert!(daemon.remaining_cooldown("1").is_none());
+    }
+
+    #[test]
+    fn windows_without_a_class_wait_for_one_until_the_timeout() {
+        let classed = opened_beside();
+        let mut classless = opened_beside();
+        let properties = &mut classless.nodes[0].nodes[0].nodes[1].window_properties;
+        properties.as_mut().unwrap().remove(&WindowProperty::Class);
+        let i3 = FakeI3::start(&classless);
+        let mut settings = default_settings();
+        settings.class_match_timeout_ms = 1000;
+        settings.exclude = vec![RuleMatch::Class("Alacritty".to_string())];
+        let mut daemon = daemon(settings);
+        let due = || Timer {
+            due: Instant::now(),
+            event: None,
+        };
+
+        // The class arrives in time, and excludes the window.
+        daemon.handle_window_event(new_window(&classless, 101));
+        assert!(daemon.awaiting_class.contains_key(&101));
+        daemon.handle_window_event(WindowEventInfo {
+            change: WindowChange::Title,
+            container: find_node(101, &classed).unwrap().clone(),
+        });
+        assert!(daemon.awaiting_class.is_empty());
+        assert!(i3.commands().is_empty());
+
+        // It never arrives, so the window is handled without one once the timeout passes.
+        daemon.handle_window_event(new_window(&classless, 101));
+        daemon.awaiting_class.insert(101, due());
+        daemon.handle_timers();
+        assert!(daemon.awaiting_class.is_empty());
+        assert_eq!(
+            i3.commands().last().unwrap(),
+            "[con_id=101] resize set width 33 ppt"
+        );
+        let ran = i3.commands().len();
+
+        // The window closed while waiting, which only the tree at the timeout shows.
+        daemon.handle_window_event(new_window(&classless, 101));
+        i3.set_tree(&tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "Firefox", (0, 0, 1000, 500))],
+        ));
+        daemon.awaiting_class.insert(101, due());
+        daemon.handle_timers();
+        assert!(daemon.awaiting_class.is_empty());
+        assert_eq!(i3.commands().len(), ran);
+    }
+
+    #[test]
+    fn each_connection_failing_is_reported_on_its_own() {
+        // Each connection fails the first `failures` times it's opened, counting its attempts.
+        let connect = |failures: u32, attempts: &Cell<u32>| -> Result<(), EstablishError> {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() <= failures {
+                Err(EstablishError::SocketError(io::Error::from(
+                    io::ErrorKind::ConnectionRefused,
+                )))
+            } else {
+                Ok(())
+            }
+        };
+        let attempts = |(command, tree, listener): (u32, u32, u32)| {
+            let counts = [Cell::new(0), Cell::new(0), Cell::new(0)];
+            let result = connect_all(
+                Duration::ZERO,
+                || connect(command, &counts[0]),
+                || connect(tree, &counts[1]),
+                || connect(listener, &counts[2]),
+            );
+            let counts = [counts[0].get(), counts[1].get(), counts[2].get()];
+            (result, counts)
+        };
+        let never = CONNECT_ATTEMPTS;
+
+        let (result, counts) = attempts((never, 0, 0));
+        assert!(matches!(result, Err(ConnectError::Command(_))));
+        assert_eq!(counts, [never, 0, 0]);
+
+        let (result, counts) = attempts((0, never, 0));
+        assert!(matches!(result, Err(ConnectError::Tree(_))));
+        assert_eq!(counts, [1, never, 0]);
+
+        let (result, counts) = attempts((0, 0, never));
+        let error = result.err().unwrap();
+        assert!(matches!(error, ConnectError::Listener(_)));
+        assert!(error
+            .to_string()
+            .starts_with("Connected to i3, but could not open the event connection"));
+        assert_eq!(counts, [1, 1, never]);
+
+        // A connection that comes up before the last attempt doesn't fail at all.
+        let (result, counts) = attempts((0, never - 1, 0));
+        assert!(result.is_ok());
+        assert_eq!(counts, [1, never, 1]);
+    }
+
+    #[test]
+    fn a_dropdown_flashing_tiled_on_its_way_to_the_scratchpad_is_left_alone() {
+        let mut tiled = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Firefox", (0, 0, 500, 500)),
+                window(101, "Guake", (500, 0, 500, 500)),
+            ],
+        );
+        focus(&mut tiled, 101);
+        let mut floating = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "Firefox", (0, 0, 1000, 500))],
+        );
+        let mut wrapper = node(
+            20,
+            NodeType::FloatingCon,
+            NodeLayout::SplitH,
+            (0, 0, 1000, 250),
+        );
+        wrapper.nodes = vec![window(101, "Guake", (0, 0, 1000, 250))];
+        floating.nodes[0].nodes[0].floating_nodes.push(wrapper);
+        focus(&mut floating, 101);
+        let scratched = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "Firefox", (0, 0, 1000, 500))],
+        );
+        let guake = find_node(101, &tiled).unwrap().clone();
+        let event = |change| WindowEventInfo {
+            change,
+            container: guake.clone(),
+        };
+
+        let i3 = FakeI3::start(&tiled);
+        for &delay in &[0, 50] {
+            let mut settings = default_settings();
+            settings.dropdown_classes = vec!["Guake".to_string()];
+            settings.handle_delay_ms = delay;
+            let mut daemon = daemon(settings);
+
+            i3.set_tree(&tiled);
+            daemon.handle_window_event(event(WindowChange::New));
+            i3.set_tree(&floating);
+            daemon.handle_window_event(event(WindowChange::Floating));
+            i3.set_tree(&scratched);
+            daemon.handle_window_event(event(WindowChange::Move));
+            for timer in daemon.delayed.values_mut() {
+                timer.due = Instant::now();
+            }
+            daemon.handle_timers();
+        }
+        assert_eq!(i3.commands(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn urgent_windows_are_skipped_with_skip_urgent() {
+        let plain = opened_beside();
+        let mut flagged = opened_beside();
+        urgent(&mut flagged, 101);
+        let i3 = FakeI3::start(&plain);
+        let check = |daemon: &mut Daemon, tree: &Node, event: &Node| {
+            daemon.check_window(tree, &Extras::new(), event, &mut Vec::new())
+        };
+        let event = find_node(101, &plain).unwrap();
+        let flagged_event = find_node(101, &flagged).unwrap();
+
+        let mut unset = daemon(default_settings());
+        assert_eq!(check(&mut unset, &flagged, flagged_event), Ok(()));
+
+        let mut settings = default_settings();
+        settings.skip_urgent = true;
+        let mut daemon = daemon(settings);
+        assert_eq!(check(&mut daemon, &plain, event), Ok(()));
+        assert_eq!(
+            check(&mut daemon, &plain, flagged_event),
+            Err(SkipReason::Urgent)
+        );
+        // The hint was set after the window opened, so only the tree has it.
+        assert_eq!(check(&mut daemon, &flagged, event), Err(SkipReason::Urgent));
+
+        i3.set_tree(&flagged);
+        daemon.handle_window_event(new_window(&plain, 101));
+        assert_eq!(i3.commands(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn suggestions_list_only_the_workspaces_that_drifted() {
+        let pair = |id, name: &str, x, share: f64| {
+            let mut pair = workspace(
+                id,
+                name,
+                NodeLayout::SplitH,
+                (x, 0, 1000, 500),
+                vec![
+                    window(id * 10, "Firefox", (x, 0, 500, 500)),
+                    window(id * 10 + 1, "Alacritty", (x + 500, 0, 500, 500)),
+                ],
+            );
+            pair.nodes[0].percent = Some(1.0 - share);
+            pair.nodes[1].percent = Some(share);
+            pair
+        };
+        let tree = root(vec![
+            output(2, "eDP-1", pair(10, "1", 0, 0.33)),
+            output(3, "HDMI-1", pair(11, "2", 1000, 0.5)),
+        ]);
+        let i3 = FakeI3::start(&tree);
+        let mut daemon = daemon(default_settings());
+
+        // Workspace 1 is at the ratio, and nothing is run for workspace 2.
+        assert_eq!(
+            Value::Object(daemon.log_suggestions()),
+            json!({
+                "2": [{
+                    "container": 11,
+                    "description": "child 110 is at 0.50, expected 0.33",
+                    "commands": ["[con_id=110] focus", "resize set width 33 ppt"],
+                }],
+            })
+        );
+        assert!(i3.commands().is_empty());
+    }
+}
@@ -0,0 +1,867 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use chrono::{DateTime, Local};
+use i3ipc::reply::{Node, NodeLayout, NodeType};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use crate::command::{render_all, Command};
+use crate::hooks::Hooks;
+use crate::ipc::Extras;
+use crate::planner::SkipReason;
+use crate::settings::{Settings, SizingRule};
+use crate::tree::{
+    effective_percent, find_output, find_parent, find_workspace, window_class, window_identity,
+    workspace_number, workspaces,
+};
+
+/// How many actions `history` keeps.
+const ACTION_HISTORY: usize = 100;
+
+/// How many failed commands `status` shows.
+const FAILURE_HISTORY: usize = 5;
+
+/// Titles longer than this are cut short in the history, to keep its size bounded.
+const MAX_TITLE_LENGTH: usize = 80;
+
+/// Lines longer than this are cut short in explanations, like the whole tree logged at trace.
+const MAX_REASONING_LENGTH: usize = 200;
+
+/// One handled event, as kept in the history.
+#[derive(Debug, Clone, Serialize)]
+pub struct Action {
+    /// The id of the event this was done for, matching the `[#id]` on its log lines.
+    pub id: Option<u64>,
+    pub time: DateTime<Local>,
+    pub event: &'static str,
+    pub window: i64,
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub workspace: Option<String>,
+    pub output: Option<String>,
+    pub ratio: String,
+    pub decision: String,
+    pub commands: Vec<String>,
+}
+
+/// A command i3 refused, or that couldn't be sent at all, as kept for `status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandFailure {
+    /// The id of the event the command was run for, if any.
+    pub id: Option<u64>,
+    pub time: DateTime<Local>,
+    pub command: String,
+    /// i3's own explanation, or the IPC error when the command never got an answer.
+    pub error: String,
+}
+
+impl fmt::Display for CommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.time.format("%H:%M:%S"))?;
+        if let Some(id) = self.id {
+            write!(f, "[#{}] ", id)?;
+        }
+        write!(f, "{}: {}", self.command, self.error)
+    }
+}
+
+/// The most recent command failures, oldest first.
+#[derive(Default)]
+pub struct FailureLog {
+    failures: VecDeque<CommandFailure>,
+    /// How many failures there have been in all, including those no longer kept.
+    total: u64,
+}
+
+impl FailureLog {
+    /// Logs a failure of `command` as a warning, and keeps it for `status`.
+    pub fn record(&mut self, command: &str, error: String) {
+        warn!("i3 failed to run {:?}: {}", command, error);
+        self.total += 1;
+        if self.failures.len() == FAILURE_HISTORY {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(CommandFailure {
+            id: current_event(),
+            time: Local::now(),
+            command: command.to_string(),
+            error,
+        });
+    }
+
+    pub fn recent(&self) -> impl Iterator<Item = &CommandFailure> {
+        self.failures.iter()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+/// The most recent actions, oldest first. Shared between the daemon and the records it creates,
+/// which add themselves when they're done and run the hook script.
+#[derive(Clone, Default)]
+pub struct ActionLog {
+    actions: Rc<RefCell<VecDeque<Action>>>,
+    hooks: Hooks,
+    /// Whether each record is also printed to stdout, for `--watch`.
+    watch: bool,
+}
+
+impl ActionLog {
+    pub fn new(hooks: Hooks, watch: bool) -> ActionLog {
+        ActionLog {
+            actions: Rc::default(),
+            hooks,
+            watch,
+        }
+    }
+
+    fn push(&self, action: Action) {
+        self.hooks.action(&action);
+
+        let mut actions = self.actions.borrow_mut();
+        if actions.len() == ACTION_HISTORY {
+            actions.pop_front();
+        }
+        actions.push_back(action);
+    }
+
+    /// Up to `limit` of the most recent actions, oldest first.
+    pub fn recent(&self, limit: usize) -> Vec<Action> {
+        let actions = self.actions.borrow();
+        actions
+            .iter()
+            .skip(actions.len().saturating_sub(limit))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A summary of how one event was handled, as a single line of key=value pairs. The record is
+/// logged at debug level and added to the history when it's dropped, so every way out of a
+/// handler produces exactly one.
+pub struct EventRecord {
+    action: Action,
+    log: ActionLog,
+}
+
+impl EventRecord {
+    pub fn new(
+        event: &'static str,
+        node: &Node,
+        tree: &Node,
+        settings: &Settings,
+        log: &ActionLog,
+    ) -> EventRecord {
+        EventRecord {
+            action: Action {
+                id: current_event(),
+                time: Local::now(),
+                event,
+                window: node.id,
+                class: window_class(node).map(String::from),
+                title: node.name.as_deref().map(truncate_title),
+                workspace: find_workspace(node.id, tree).and_then(|w| w.name.clone()),
+                output: find_output(node.id, tree).and_then(|o| o.name.clone()),
+                ratio: settings.ratio_description(),
+                decision: "none".to_string(),
+                commands: Vec::new(),
+            },
+            log: log.clone(),
+        }
+    }
+
+    pub fn decide(&mut self, decision: impl Into<String>) {
+        self.action.decision = decision.into();
+    }
+
+    /// Records that the window was left alone, and why.
+    pub fn skip(&mut self, reason: SkipReason) {
+        info!("Not handling {:?}: {}", self.action.title, reason);
+        self.decide(reason.to_string());
+    }
+
+    pub fn set_commands(&mut self, commands: &[Command]) {
+        self.action.commands = render_all(commands);
+    }
+}
+
+impl fmt::Display for EventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = &self.action;
+        write!(
+            f,
+            "id={} event={} window={} class={:?} workspace={:?} output={:?} ratio={} decision={:?} commands={:?}",
+            action.id.map_or("-".to_string(), |id| id.to_string()),
+            action.event,
+            action.window,
+            action.class.as_deref().unwrap_or(""),
+            action.workspace.as_deref().unwrap_or(""),
+            action.output.as_deref().unwrap_or(""),
+            action.ratio,
+            action.decision,
+            action.commands
+        )
+    }
+}
+
+impl Drop for EventRecord {
+    fn drop(&mut self) {
+        debug!("{}", self);
+        if self.log.watch {
+            println!("{} {}", self.action.time.format("%H:%M:%S"), self);
+        }
+        self.log.push(self.action.clone());
+    }
+}
+
+/// Renders the sizing rules as a table for `rules`, in the order they're checked, followed by
+/// what windows no rule matches get.
+pub fn render_rules(rules: &[SizingRule], settings: &Settings) -> String {
+    let mut table = format!(
+        "{:<8}  {:<12}  {:<32}  {}\n",
+        "PRIORITY", "SECTION", "MATCH", "SIZE"
+    );
+
+    for rule in rules {
+        table.push_str(&format!(
+            "{:<8}  {:<12}  {:<32}  {}\n",
+            rule.priority,
+            rule.section,
+            rule.matcher.to_string(),
+            rule.size
+        ));
+    }
+
+    table.push_str(&format!(
+        "Otherwise: mode {:?}, ratio {}",
+        settings.mode,
+        settings.ratio_description()
+    ));
+    table
+}
+
+/// What `explain` found out about deciding for a window, from the checks it went through to the
+/// commands that would be run.
+#[derive(Debug, Default, Serialize)]
+pub struct Explanation {
+    pub window: i64,
+    pub name: Option<String>,
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub role: Option<String>,
+    pub workspace: Option<String>,
+    pub output: Option<String>,
+    /// Each check made before planning, in order, with why it left the window alone, if it did.
+    pub checks: Vec<(String, Option<String>)>,
+    pub parent: Option<ExplainedParent>,
+    /// Everything logged while deciding, at every level.
+    pub reasoning: Vec<String>,
+    pub decision: String,
+    pub commands: Vec<String>,
+}
+
+/// The container of an explained window, as it was before deciding.
+#[derive(Debug, Serialize)]
+pub struct ExplainedParent {
+    pub id: i64,
+    pub layout: &'static str,
+    /// Each child's id, name and share of the container.
+    pub children: Vec<(i64, Option<String>, f64)>,
+}
+
+impl Explanation {
+    pub fn new(node: &Node, tree: &Node) -> Explanation {
+        let identity = window_identity(node);
+        let parent = find_parent(node.id, tree).map(|parent| {
+            let children = parent
+                .nodes
+                .iter()
+                .map(|child| {
+                    (
+                        child.id,
+                        child.name.clone(),
+                        effective_percent(child, parent),
+                    )
+                })
+                .collect();
+            ExplainedParent {
+                id: parent.id,
+                layout: layout_name(&parent.layout),
+                children,
+            }
+        });
+        Explanation {
+            window: node.id,
+            name: node.name.clone(),
+            class: identity.class.map(String::from),
+            instance: identity.instance.map(String::from),
+            role: identity.role.map(String::from),
+            workspace: find_workspace(node.id, tree).and_then(|w| w.name.clone()),
+            output: find_output(node.id, tree).and_then(|o| o.name.clone()),
+            parent,
+            decision: "none".to_string(),
+            ..Explanation::default()
+        }
+    }
+
+    pub fn check(&mut self, check: &str, skip: Option<&SkipReason>) {
+        self.checks
+            .push((check.to_string(), skip.map(SkipReason::to_string)));
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let or_dash = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        writeln!(f, "Window {} {}", self.window, or_dash(&self.name))?;
+        writeln!(
+            f,
+            "  class {}, instance {}, role {}, workspace {}, output {}",
+            or_dash(&self.class),
+            or_dash(&self.instance),
+            or_dash(&self.role),
+            or_dash(&self.workspace),
+            or_dash(&self.output)
+        )?;
+
+        writeln!(f, "Checks:")?;
+        for (check, skip) in &self.checks {
+            match skip {
+                Some(reason) => writeln!(f, "  {:<20} skips it: {}", check, reason)?,
+                None => writeln!(f, "  {:<20} passed", check)?,
+            }
+        }
+
+        match &self.parent {
+            Some(parent) => {
+                writeln!(
+                    f,
+                    "Parent {}: {}, {} children",
+                    parent.id,
+                    parent.layout,
+                    parent.children.len()
+                )?;
+                for (child, name, percent) in &parent.children {
+                    writeln!(
+                        f,
+                        "  {:<10} {:>5.1}%  {}{}",
+                        child,
+                        percent * 100.0,
+                        or_dash(name),
+                        if *child == self.window {
+                            " (this window)"
+                        } else {
+                            ""
+                        }
+                    )?;
+                }
+            }
+            None => writeln!(f, "Parent: none")?,
+        }
+
+        if !self.reasoning.is_empty() {
+            writeln!(f, "Reasoning:")?;
+            for line in &self.reasoning {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        write!(f, "Decision: {}", self.decision)?;
+        if !self.commands.is_empty() {
+            write!(f, "\nCommands, not run:")?;
+            for command in &self.commands {
+                write!(f, "\n  {}", command)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// The id of the event being handled, while there is one.
+    static CURRENT_EVENT: Cell<Option<u64>> = const { Cell::new(None) };
+
+    /// The lines logged while a decision is being explained, while one is.
+    static EXPLAINING: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// The id of the event being handled, if any.
+pub fn current_event() -> Option<u64> {
+    CURRENT_EVENT.with(Cell::get)
+}
+
+/// Marks everything logged and recorded until it's dropped as part of handling one event.
+pub struct EventScope {
+    previous: Option<u64>,
+}
+
+impl EventScope {
+    pub fn enter(id: u64) -> EventScope {
+        EventScope {
+            previous: CURRENT_EVENT.with(|current| current.replace(Some(id))),
+        }
+    }
+}
+
+impl Drop for EventScope {
+    fn drop(&mut self) {
+        CURRENT_EVENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Records every line ratiosplit logs, at every level, until it's finished, for `explain`. The
+/// explanation is made of what the code making the real decisions says about them, rather than
+/// a description of that code that could drift from it.
+pub struct ExplainScope {
+    level: LevelFilter,
+}
+
+impl ExplainScope {
+    pub fn enter() -> ExplainScope {
+        let level = log::max_level();
+        // The loggers themselves still filter by their own levels, so nothing extra is written.
+        log::set_max_level(LevelFilter::Trace);
+        EXPLAINING.with(|lines| *lines.borrow_mut() = Some(Vec::new()));
+        ExplainScope { level }
+    }
+
+    /// Stops recording, and returns the lines logged since the scope was entered.
+    pub fn finish(self) -> Vec<String> {
+        EXPLAINING.with(|lines| lines.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+impl Drop for ExplainScope {
+    fn drop(&mut self) {
+        EXPLAINING.with(|lines| *lines.borrow_mut() = None);
+        log::set_max_level(self.level);
+    }
+}
+
+/// Keeps a line logged by ratiosplit itself, not its dependencies, while explaining.
+fn record_explaining(record: &Record) {
+    if !record.target().starts_with(env!("CARGO_CRATE_NAME")) {
+        return;
+    }
+    EXPLAINING.with(|lines| {
+        if let Some(lines) = lines.borrow_mut().as_mut() {
+            lines.push(truncate(&record.args().to_string(), MAX_REASONING_LENGTH));
+        }
+    });
+}
+
+/// Prefixes lines logged while an event is being handled with its id, like `[#12]`, so that the
+/// lines for windows opening at the same time can be told apart.
+pub struct EventLogger {
+    inner: Box<dyn Log>,
+}
+
+impl EventLogger {
+    pub fn new(inner: Box<dyn Log>) -> EventLogger {
+        EventLogger { inner }
+    }
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        record_explaining(record);
+        match current_event() {
+            Some(id) => self.inner.log(
+                &Record::builder()
+                    .args(format_args!("[#{}] {}", id, record.args()))
+                    .metadata(record.metadata().clone())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+            None => self.inner.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Describes a container before and after a window was handled, for `verify_log`. Kept on one
+/// line, in a fixed format, so that it's easy to grep for and paste.
+pub fn verify_line(before: &Node, after: &Node, target: f64) -> String {
+    let children = |parent: &Node| {
+        parent
+            .nodes
+            .iter()
+            .map(|child| format!("{}:{:.3}", child.id, effective_percent(child, parent)))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "verify_log parent={} layout={} target={:.3} before=[{}] after=[{}]",
+        after.id,
+        layout_name(&after.layout),
+        target,
+        children(before),
+        children(after)
+    )
+}
+
+/// A node of the tree reduced to what matters for layouts, for `tree`.
+#[derive(Debug, Serialize)]
+pub struct TreeSummary {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub nodetype: &'static str,
+    pub layout: &'static str,
+    pub name: Option<String>,
+    pub class: Option<String>,
+    pub rect: (i32, i32, i32, i32),
+    /// The node's share of its parent.
+    pub percent: f64,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub marks: Vec<String>,
+    /// What the daemon makes of the node, like whether a workspace is managed, when it's known.
+    pub annotation: Option<String>,
+    pub children: Vec<TreeSummary>,
+}
+
+/// Reduces the tree, or just the workspace with the given name or number, to a `TreeSummary`,
+/// with `annotate` saying what the daemon makes of each node.
+pub fn summarize_tree(
+    tree: &Node,
+    extras: &Extras,
+    workspace: Option<&str>,
+    annotate: &dyn Fn(&Node) -> Option<String>,
+) -> Result<TreeSummary, String> {
+    let root = match workspace {
+        Some(wanted) => workspaces(tree)
+            .into_iter()
+            .find(|w| {
+                w.name.as_deref() == Some(wanted)
+                    || w.name.as_deref().and_then(workspace_number) == wanted.parse().ok()
+            })
+            .ok_or_else(|| format!("No workspace {:?}", wanted))?,
+        None => tree,
+    };
+    let parent = find_parent(root.id, tree);
+    let floating = parent.is_some_and(|p| p.nodetype == NodeType::FloatingCon);
+    Ok(summarize_node(root, parent, floating, extras, annotate))
+}
+
+fn summarize_node(
+    node: &Node,
+    parent: Option<&Node>,
+    floating: bool,
+    extras: &Extras,
+    annotate: &dyn Fn(&Node) -> Option<String>,
+) -> TreeSummary {
+    let floating = floating || node.nodetype == NodeType::FloatingCon;
+    let extra = extras.get(&node.id);
+    TreeSummary {
+        id: node.id,
+        nodetype: match node.nodetype {
+            NodeType::Root => "root",
+            NodeType::Output => "output",
+            NodeType::Con if node.window.is_some() => "window",
+            NodeType::Con => "con",
+            NodeType::FloatingCon => "floating_con",
+            NodeType::Workspace => "workspace",
+            NodeType::DockArea => "dockarea",
+            _ => "other",
+        },
+        layout: layout_name(&node.layout),
+        name: node.name.clone(),
+        class: window_class(node).map(String::from),
+        rect: node.rect,
+        percent: parent.map_or(1.0, |parent| effective_percent(node, parent)),
+        floating,
+        fullscreen: extra.is_some_and(|e| e.fullscreen),
+        marks: extra.map(|e| e.marks.clone()).unwrap_or_default(),
+        annotation: annotate(node),
+        children: node
+            .nodes
+            .iter()
+            .chain(&node.floating_nodes)
+            .map(|child| summarize_node(child, Some(node), floating, extras, annotate))
+            .collect(),
+    }
+}
+
+impl TreeSummary {
+    fn render(&self, f: &mut fmt::Formatter, prefix: &str, branch: &str) -> fmt::Result {
+        let (x, y, width, height) = self.rect;
+        write!(
+            f,
+            "{}{}{} {} {}",
+            prefix, branch, self.nodetype, self.id, self.layout
+        )?;
+        if let Some(class) = &self.class {
+            write!(f, " {}", class)?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, " {:?}", name)?;
+        }
+        write!(
+            f,
+            " {}x{}+{}+{} {:.1}%",
+            width,
+            height,
+            x,
+            y,
+            self.percent * 100.0
+        )?;
+        if self.floating {
+            write!(f, " floating")?;
+        }
+        if self.fullscreen {
+            write!(f, " fullscreen")?;
+        }
+        if !self.marks.is_empty() {
+            write!(f, " marks={}", self.marks.join(","))?;
+        }
+        if let Some(annotation) = &self.annotation {
+            write!(f, " ({})", annotation)?;
+        }
+
+        let prefix = match branch {
+            "|-- " => format!("{}|   ", prefix),
+            "`-- " => format!("{}    ", prefix),
+            _ => prefix.to_string(),
+        };
+        for (i, child) in self.children.iter().enumerate() {
+            writeln!(f)?;
+            let branch = if i + 1 == self.children.len() {
+                "`-- "
+            } else {
+                "|-- "
+            };
+            child.render(f, &prefix, branch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the tree indented, one node per line, in i3's order, so that two runs can be diffed.
+impl fmt::Display for TreeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(f, "", "")
+    }
+}
+
+/// The name i3 uses for a layout in its commands.
+fn layout_name(layout: &NodeLayout) -> &'static str {
+    match layout {
+        NodeLayout::SplitH => "splith",
+        NodeLayout::SplitV => "splitv",
+        NodeLayout::Stacked => "stacked",
+        NodeLayout::Tabbed => "tabbed",
+        _ => "other",
+    }
+}
+
+/// Renders actions as a table for `history`, one line per action, with the commands on
+/// indented lines below it.
+pub fn render_table(actions: &[Action]) -> String {
+    let mut table = format!(
+        "{:<6}  {:<8}  {:<10}  {:<16}  {:<10}  {:<24}  {}\n",
+        "ID", "TIME", "EVENT", "CLASS", "WORKSPACE", "DECISION", "TITLE"
+    );
+
+    for action in actions {
+        table.push_str(&format!(
+            "{:<6}  {:<8}  {:<10}  {:<16}  {:<10}  {:<24}  {}\n",
+            action.id.map_or("-".to_string(), |id| format!("#{}", id)),
+            action.time.format("%H:%M:%S"),
+            action.event,
+            action.class.as_deref().unwrap_or("-"),
+            action.workspace.as_deref().unwrap_or("-"),
+            action.decision,
+            action.title.as_deref().unwrap_or("-")
+        ));
+        for command in &action.commands {
+            table.push_str(&format!("          {}\n", command));
+        }
+    }
+
+    table.pop();
+    table
+}
+
+fn truncate_title(title: &str) -> String {
+    truncate(title, MAX_TITLE_LENGTH)
+}
+
+fn truncate(text: &str, length: usize) -> String {
+    match text.char_indices().nth(length) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{init_logger, tree, window};
+    use crate::ipc::NodeExtras;
+    use crate::tree::find_node;
+
+    #[test]
+    fn event_scopes_nest() {
+        assert_eq!(current_event(), None);
+        {
+            let _outer = EventScope::enter(4);
+            assert_eq!(current_event(), Some(4));
+            {
+                // Something put off while handling event 2, done while handling event 4.
+                let _inner = EventScope::enter(2);
+                assert_eq!(current_event(), Some(2));
+            }
+            assert_eq!(current_event(), Some(4));
+        }
+        assert_eq!(current_event(), None);
+    }
+
+    fn two_windows() -> Node {
+        tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Firefox", (0, 0, 600, 500)),
+                window(101, "URxvt", (600, 0, 400, 500)),
+            ],
+        )
+    }
+
+    #[test]
+    fn explanation_describes_the_window_and_its_parent() {
+        let tree = two_windows();
+        let node = find_node(101, &tree).unwrap();
+        let mut explanation = Explanation::new(node, &tree);
+        explanation.check("dock", None);
+        explanation.check("exclude", Some(&SkipReason::Dock));
+        explanation.reasoning = vec!["Using ratio 0.600 from ratio".to_string()];
+        explanation.decision = "dock window".to_string();
+
+        assert_eq!(explanation.workspace.as_deref(), Some("1"));
+        assert_eq!(explanation.output.as_deref(), Some("eDP-1"));
+        assert_eq!(
+            explanation.to_string(),
+            "Window 101 URxvt window\n\
+             \x20 class URxvt, instance urxvt, role -, workspace 1, output eDP-1\n\
+             Checks:\n\
+             \x20 dock                 passed\n\
+             \x20 exclude              skips it: dock window\n\
+             Parent 10: splith, 2 children\n\
+             \x20 100         50.0%  Firefox window\n\
+             \x20 101         50.0%  URxvt window (this window)\n\
+             Reasoning:\n\
+             \x20 Using ratio 0.600 from ratio\n\
+             Decision: dock window"
+        );
+    }
+
+    #[test]
+    fn explanation_lists_commands_it_would_run() {
+        let tree = two_windows();
+        let mut explanation = Explanation::new(find_node(100, &tree).unwrap(), &tree);
+        explanation.commands = vec!["[con_id=100] resize set width 60 ppt".to_string()];
+        assert!(explanation.to_string().ends_with(
+            "Decision: none\nCommands, not run:\n  [con_id=100] resize set width 60 ppt"
+        ));
+    }
+
+    #[test]
+    fn explaining_records_only_ratiosplits_own_lines() {
+        init_logger();
+        let record = |target| {
+            record_explaining(
+                &Record::builder()
+                    .args(format_args!("line from {}", target))
+                    .target(target)
+                    .build(),
+            )
+        };
+        record(concat!(env!("CARGO_CRATE_NAME"), "::planner"));
+        assert!(ExplainScope::enter().finish().is_empty());
+
+        let scope = ExplainScope::enter();
+        record(concat!(env!("CARGO_CRATE_NAME"), "::planner"));
+        record("i3ipc");
+        assert_eq!(
+            scope.finish(),
+            vec![concat!("line from ", env!("CARGO_CRATE_NAME"), "::planner").to_string()]
+        );
+    }
+
+    #[test]
+    fn long_text_is_truncated() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly10!", 10), "exactly10!");
+        assert_eq!(truncate("much longer text", 4), "much…");
+        assert_eq!(truncate("ééééé", 2), "éé…");
+    }
+
+    #[test]
+    fn tree_summary_renders_one_node_per_line() {
+        let tree = two_windows();
+        let mut extras = Extras::new();
+        extras.insert(
+            101,
+            NodeExtras {
+                marks: vec!["main".to_string()],
+                fullscreen: true,
+                ..NodeExtras::default()
+            },
+        );
+        let annotate =
+            |node: &Node| (node.nodetype == NodeType::Workspace).then(|| "managed".to_string());
+
+        let summary = summarize_tree(&tree, &extras, None, &annotate).unwrap();
+        assert_eq!(
+            summary.to_string(),
+            "root 1 splith \"root\" 1000x500+0+0 100.0%\n\
+             `-- output 2 other \"eDP-1\" 1000x500+0+0 100.0%\n\
+             \x20   `-- workspace 10 splith \"1\" 1000x500+0+0 100.0% (managed)\n\
+             \x20       |-- window 100 splith Firefox \"Firefox window\" 600x500+0+0 50.0%\n\
+             \x20       `-- window 101 splith URxvt \"URxvt window\" 400x500+600+0 50.0% \
+             fullscreen marks=main"
+        );
+    }
+
+    #[test]
+    fn tree_summary_can_start_at_a_workspace() {
+        let tree = two_windows();
+        let none = |_: &Node| None;
+        for wanted in ["1", "01"] {
+            let summary = summarize_tree(&tree, &Extras::new(), Some(wanted), &none).unwrap();
+            assert_eq!(summary.id, 10);
+            assert_eq!(summary.children.len(), 2);
+        }
+        assert_eq!(
+            summarize_tree(&tree, &Extras::new(), Some("2"), &none).unwrap_err(),
+            "No workspace \"2\""
+        );
+    }
+
+    #[test]
+    fn verify_lines_keep_their_format() {
+        let before = find_workspace(100, &two_windows()).unwrap().clone();
+        let mut after = before.clone();
+        after.nodes[0].percent = Some(0.67);
+        after.nodes[1].percent = Some(0.33);
+        assert_eq!(
+            verify_line(&before, &after, 0.33),
+            "verify_log parent=10 layout=splith target=0.330 before=[100:0.500,101:0.500] \
+             after=[100:0.670,101:0.330]"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+//! A stand-in for i3's IPC socket, for running the daemon against fixture trees in tests. There
+//! is a single socket for the whole test run, since i3ipc finds it through `I3SOCK`, so tests
+//! using it take turns.
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process;
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::thread;
+
+use i3ipc::reply::{Node, NodeLayout, NodeType, WindowProperty};
+use serde_json::{json, Map, Value};
+
+use crate::ipc::Extras;
+
+const MAGIC: &[u8] = b"i3-ipc";
+const RUN_COMMAND: u32 = 0;
+const GET_WORKSPACES: u32 = 1;
+const GET_TREE: u32 = 4;
+
+/// What the fake i3 answers with, and what it has been asked to run.
+struct State {
+    tree: Value,
+    commands: Vec<String>,
+    /// Commands containing the given text are refused with the given error.
+    refusals: Vec<(String, String)>,
+    /// Once a command containing the given text runs, the tree changes to the given one.
+    changes: Vec<(String, Value)>,
+    /// Whether every connection is closed at the next request, like when i3 exits.
+    hung_up: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    tree: Value::Null,
+    commands: Vec::new(),
+    refusals: Vec::new(),
+    changes: Vec::new(),
+    hung_up: false,
+});
+
+static TURN: Mutex<()> = Mutex::new(());
+
+static START: Once = Once::new();
+
+/// A test's turn with the fake i3, which lasts until it's dropped.
+pub struct FakeI3 {
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl FakeI3 {
+    /// Waits for the fake i3 to be free, starting it the first time, and has it report `tree`.
+    pub fn start(tree: &Node) -> FakeI3 {
+        let turn = TURN.lock().unwrap_or_else(PoisonError::into_inner);
+        START.call_once(listen);
+        *state() = State {
+            tree: to_json(tree, &Extras::new()),
+            commands: Vec::new(),
+            refusals: Vec::new(),
+            changes: Vec::new(),
+            hung_up: false,
+        };
+        FakeI3 { _turn: turn }
+    }
+
+    /// Refuses every command containing `command` with `error`, like i3 refusing a resize.
+    pub fn refuse(&self, command: &str, error: &str) {
+        state()
+            .refusals
+            .push((command.to_string(), error.to_string()));
+    }
+
+    /// Reports `tree` from now on, like after the user changed the layout.
+    pub fn set_tree(&self, tree: &Node) {
+        state().tree = to_json(tree, &Extras::new());
+    }
+
+    /// Reports `tree` once a command containing `command` has run, like i3 carrying it out.
+    pub fn change_after(&self, command: &str, tree: &Node) {
+        state()
+            .changes
+            .push((command.to_string(), to_json(tree, &Extras::new())));
+    }
+
+    /// Every command run so far, in order, as i3 received them.
+    pub fn commands(&self) -> Vec<String> {
+        state().commands.clone()
+    }
+
+    /// Closes every connection at its next request.
+    pub fn hang_up(&self) {
+        state().hung_up = true;
+    }
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Binds the socket and points i3ipc at it, along with the state directory, so that nothing the
+/// daemon saves ends up in the real one.
+fn listen() {
+    let dir = env::temp_dir().join(format!("ratiosplit-fake-i3-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ipc.sock");
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    env::set_var("I3SOCK", &path);
+    env::set_var("XDG_STATE_HOME", dir.join("state"));
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || answer(stream));
+        }
+    });
+}
+
+/// Answers every request on one connection until it's closed.
+fn answer(mut stream: UnixStream) {
+    loop {
+        let mut header = [0u8; 14];
+        if stream.read_exact(&mut header).is_err() {
+            return;
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&header[range]);
+            u32::from_le_bytes(bytes)
+        };
+        let (length, kind) = (field(6..10), field(10..14));
+        let mut payload = vec![0u8; length as usize];
+        if stream.read_exact(&mut payload).is_err() {
+            return;
+        }
+
+        let body = match reply(kind, &String::from_utf8_lossy(&payload)) {
+            Some(reply) => reply.to_string(),
+            None => return,
+        };
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        message.extend_from_slice(&kind.to_le_bytes());
+        message.extend_from_slice(body.as_bytes());
+        if stream.write_all(&message).is_err() {
+            return;
+        }
+    }
+}
+
+fn reply(kind: u32, payload: &str) -> Option<Value> {
+    let mut state = state();
+    if state.hung_up {
+        return None;
+    }
+    let reply = match kind {
+        RUN_COMMAND => {
+            state.commands.push(payload.to_string());
+            let refusal = state
+                .refusals
+                .iter()
+                .find(|(command, _)| payload.contains(command.as_str()));
+            if let Some((_, error)) = refusal {
+                return Some(json!([{ "success": false, "error": error }]));
+            }
+            let change = state
+                .changes
+                .iter()
+                .find(|(command, _)| payload.contains(command.as_str()))
+                .map(|(_, tree)| tree.clone());
+            if let Some(tree) = change {
+                state.tree = tree;
+            }
+            json!([{ "success": true }])
+        }
+        GET_WORKSPACES => {
+            let mut workspaces = Vec::new();
+            list_workspaces(&state.tree, "", &mut workspaces);
+            Value::Array(workspaces)
+        }
+        GET_TREE => state.tree.clone(),
+        _ => panic!("The fake i3 doesn't answer message type {}", kind),
+    };
+    Some(reply)
+}
+
+/// Adds the workspaces in `node`, as i3 lists them, to `workspaces`.
+fn list_workspaces(node: &Value, output: &str, workspaces: &mut Vec<Value>) {
+    let output = match node["type"].as_str() {
+        Some("output") => node["name"].as_str().unwrap_or_default(),
+        _ => output,
+    };
+    if node["type"] == "workspace" {
+        let name = node["name"].as_str().unwrap_or_default();
+        let num = name
+            .split(|c: char| !c.is_ascii_digit())
+            .next()
+            .and_then(|digits| digits.parse().ok())
+            .unwrap_or(-1);
+        workspaces.push(json!({
+            "num": num,
+            "name": name,
+            "visible": true,
+            "focused": has_focus(node),
+            "urgent": false,
+            "rect": node["rect"],
+            "output": output,
+        }));
+        return;
+    }
+    for child in node["nodes"].as_array().into_iter().flatten() {
+        list_workspaces(child, output, workspaces);
+    }
+}
+
+fn has_focus(node: &Value) -> bool {
+    node["focused"] == true
+        || node["nodes"]
+            .as_array()
+            .into_iter()
+            .chain(node["floating_nodes"].as_array())
+            .flatten()
+            .any(has_focus)
+}
+
+/// The tree as i3 sends it, with the fields from `extras` that i3ipc leaves out.
+fn to_json(node: &Node, extras: &Extras) -> Value {
+    let children =
+        |nodes: &[Node]| -> Vec<Value> { nodes.iter().map(|n| to_json(n, extras)).collect() };
+    let properties = node.window_properties.as_ref().map(|properties| {
+        properties
+            .iter()
+            .map(|(property, value)| {
+                let key = match property {
+                    WindowProperty::Title => "title",
+                    WindowProperty::Instance => "instance",
+                    WindowProperty::Class => "class",
+                    WindowProperty::WindowRole => "window_role",
+                    WindowProperty::TransientFor => "transient_for",
+                };
+                (key.to_string(), json!(value))
+            })
+            .collect::<Map<String, Value>>()
+    });
+    let extra = extras.get(&node.id).cloned().unwrap_or_default();
+
+    json!({
+        "id": node.id,
+        "name": node.name,
+        "type": match node.nodetype {
+            NodeType::Root => "root",
+            NodeType::Output => "output",
+            NodeType::Con => "con",
+            NodeType::FloatingCon => "floating_con",
+            NodeType::Workspace => "workspace",
+            NodeType::DockArea => "dockarea",
+            NodeType::Unknown => "unknown",
+        },
+        "layout": match node.layout {
+            NodeLayout::SplitH => "splith",
+            NodeLayout::SplitV => "splitv",
+            NodeLayout::Stacked => "stacked",
+            NodeLayout::Tabbed => "tabbed",
+            NodeLayout::DockArea => "dockarea",
+            NodeLayout::Output => "output",
+            NodeLayout::Unknown => "unknown",
+        },
+        "percent": node.percent,
+        "rect": rect_json(node.rect),
+        "window_rect": rect_json(node.window_rect),
+        "deco_rect": rect_json(node.deco_rect),
+        "geometry": rect_json(node.geometry),
+        "window": node.window,
+        "window_properties": properties,
+        "urgent": node.urgent,
+        "focused": node.focused,
+        "focus": node.focus,
+        "nodes": children(&node.nodes),
+        "floating_nodes": children(&node.floating_nodes),
+        "marks": extra.marks,
+        "pid": extra.pid,
+        "fullscreen_mode": if extra.fullscreen { 1 } else { 0 },
+    })
+}
+
+fn rect_json((x, y, width, height): (i32, i32, i32, i32)) -> Value {
+    json!({ "x": x, "y": y, "width": width, "height": height })
+}
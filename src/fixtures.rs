@@ -0,0 +1,197 @@
+//! Small trees for tests, built the way i3 reports them.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Once;
+
+use i3ipc::reply::{Node, NodeBorder, NodeLayout, NodeType, WindowProperty};
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::diagnostics::EventLogger;
+
+/// A bare container of the given type and layout, with no children.
+pub fn node(id: i64, nodetype: NodeType, layout: NodeLayout, rect: (i32, i32, i32, i32)) -> Node {
+    Node {
+        focus: Vec::new(),
+        nodes: Vec::new(),
+        floating_nodes: Vec::new(),
+        id,
+        name: None,
+        nodetype,
+        border: NodeBorder::None,
+        current_border_width: 0,
+        layout,
+        percent: None,
+        rect,
+        window_rect: (0, 0, rect.2, rect.3),
+        deco_rect: (0, 0, 0, 0),
+        geometry: (0, 0, rect.2, rect.3),
+        window: None,
+        window_properties: None,
+        urgent: false,
+        focused: false,
+    }
+}
+
+/// A window with the given class, taking up `rect`.
+pub fn window(id: i64, class: &str, rect: (i32, i32, i32, i32)) -> Node {
+    let mut window = node(id, NodeType::Con, NodeLayout::SplitH, rect);
+    window.name = Some(format!("{} window", class));
+    window.window = Some(id as i32);
+    let mut properties = HashMap::new();
+    properties.insert(WindowProperty::Class, class.to_string());
+    properties.insert(WindowProperty::Instance, class.to_lowercase());
+    window.window_properties = Some(properties);
+    window
+}
+
+/// A split container holding `children`, each given its share of it along the split.
+pub fn split(id: i64, layout: NodeLayout, rect: (i32, i32, i32, i32), children: Vec<Node>) -> Node {
+    let mut container = node(id, NodeType::Con, layout, rect);
+    container.nodes = with_percents(children);
+    container
+}
+
+/// The root of a tree with one output, `eDP-1`, showing a workspace named `name` that holds
+/// `children`.
+pub fn tree(name: &str, layout: NodeLayout, children: Vec<Node>) -> Node {
+    let rect = (0, 0, 1000, 500);
+    root(vec![output(
+        2,
+        "eDP-1",
+        workspace(10, name, layout, rect, children),
+    )])
+}
+
+/// A workspace named `name` covering `rect`, holding `children`.
+pub fn workspace(
+    id: i64,
+    name: &str,
+    layout: NodeLayout,
+    rect: (i32, i32, i32, i32),
+    children: Vec<Node>,
+) -> Node {
+    let mut workspace = node(id, NodeType::Workspace, layout, rect);
+    workspace.name = Some(name.to_string());
+    workspace.nodes = with_percents(children);
+    workspace
+}
+
+/// An output named `name` showing `workspace`, and covering the same rect.
+pub fn output(id: i64, name: &str, workspace: Node) -> Node {
+    let mut output = node(id, NodeType::Output, NodeLayout::Output, workspace.rect);
+    output.name = Some(name.to_string());
+    output.nodes = vec![workspace];
+    output
+}
+
+/// The root of a tree with the given outputs, side by side.
+pub fn root(outputs: Vec<Node>) -> Node {
+    let width = outputs
+        .iter()
+        .map(|output| output.rect.0 + output.rect.2)
+        .max();
+    let height = outputs
+        .iter()
+        .map(|output| output.rect.1 + output.rect.3)
+        .max();
+    let rect = (0, 0, width.unwrap_or(0), height.unwrap_or(0));
+    let mut root = node(1, NodeType::Root, NodeLayout::SplitH, rect);
+    root.name = Some("root".to_string());
+    root.nodes = outputs;
+    root
+}
+
+/// Adds a bar, window `id`, in a dock area above the first output's workspace, like i3bar.
+pub fn dock_bar(tree: &mut Node, id: i64) {
+    let output = &mut tree.nodes[0];
+    let (x, y, width, _) = output.rect;
+    let mut area = node(
+        id - 1,
+        NodeType::DockArea,
+        NodeLayout::DockArea,
+        (x, y, width, 20),
+    );
+    area.nodes = vec![window(id, "i3bar", (x, y, width, 20))];
+    output.nodes.insert(0, area);
+}
+
+/// Adds i3's hidden `__i3` output to `tree`, with `windows` split side by side on its
+/// `__i3_scratch` workspace, like a populated scratchpad.
+pub fn scratchpad(tree: &mut Node, windows: Vec<Node>) {
+    let rect = (0, 0, 1000, 500);
+    let pair = split(32, NodeLayout::SplitH, rect, windows);
+    let scratch = workspace(31, "__i3_scratch", NodeLayout::SplitH, rect, vec![pair]);
+    tree.nodes.insert(0, output(30, "__i3", scratch));
+}
+
+/// Sets the urgency hint on the node with the given id in `tree`.
+pub fn urgent(tree: &mut Node, id: i64) {
+    if tree.id == id {
+        tree.urgent = true;
+        return;
+    }
+    for child in tree.nodes.iter_mut().chain(tree.floating_nodes.iter_mut()) {
+        urgent(child, id);
+    }
+}
+
+/// Marks the node with the given id in `tree` as focused.
+pub fn focus(tree: &mut Node, id: i64) {
+    if tree.id == id {
+        tree.focused = true;
+        return;
+    }
+    for child in tree.nodes.iter_mut().chain(tree.floating_nodes.iter_mut()) {
+        focus(child, id);
+    }
+}
+
+fn with_percents(mut children: Vec<Node>) -> Vec<Node> {
+    let share = 1.0 / children.len().max(1) as f64;
+    for child in &mut children {
+        child.percent = Some(share);
+    }
+    children
+}
+
+thread_local! {
+    /// The lines logged on this thread while they're being captured.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Logs every line at every level, wrapped in `EventLogger` like the daemon's own logger.
+/// Installed once, before any test changes the level, so that `ExplainScope` puts back `Trace`.
+pub fn init_logger() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        log::set_max_level(LevelFilter::Trace);
+        log::set_boxed_logger(Box::new(EventLogger::new(Box::new(Capture)))).unwrap();
+    });
+}
+
+/// Runs `f`, and returns the lines it logged on this thread, as `EventLogger` passed them on.
+pub fn capture_logs(f: impl FnOnce()) -> Vec<String> {
+    init_logger();
+    CAPTURED.with(|lines| *lines.borrow_mut() = Some(Vec::new()));
+    f();
+    CAPTURED.with(|lines| lines.borrow_mut().take().unwrap_or_default())
+}
+
+struct Capture;
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        CAPTURED.with(|lines| {
+            if let Some(lines) = lines.borrow_mut().as_mut() {
+                lines.push(record.args().to_string());
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,209 @@
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::diagnostics::Action;
+use crate::settings::Settings;
+
+/// How often a failing hook script is reported at warn level. Failures in between are only
+/// logged at debug, so that a broken script doesn't flood the log.
+const FAILURE_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs `hook_script` for the events listed in `hook_events`.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    script: Option<String>,
+    events: Vec<String>,
+    last_warning: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Hooks {
+    pub fn new(settings: &Settings) -> Hooks {
+        Hooks {
+            script: settings.hook_script.clone(),
+            events: settings.hook_events.clone(),
+            last_warning: Arc::default(),
+        }
+    }
+
+    /// Runs the script for a handled window event: `handled` when commands were run for it, and
+    /// `skipped` otherwise.
+    pub fn action(&self, action: &Action) {
+        let event = if action.commands.is_empty() {
+            "skipped"
+        } else {
+            "handled"
+        };
+        self.run(
+            event,
+            &[
+                ("RATIOSPLIT_CON_ID", action.window.to_string()),
+                ("RATIOSPLIT_CLASS", action.class.clone().unwrap_or_default()),
+                (
+                    "RATIOSPLIT_WORKSPACE",
+                    action.workspace.clone().unwrap_or_default(),
+                ),
+                ("RATIOSPLIT_DECISION", action.decision.clone()),
+            ],
+        );
+    }
+
+    /// Starts the script for `event` with the given environment variables, if `event` is one of
+    /// `hook_events`. It isn't waited for here; a thread of its own waits for it to exit, so that
+    /// it doesn't linger as a zombie, and reports any failure.
+    pub fn run(&self, event: &str, vars: &[(&str, String)]) {
+        let script = match &self.script {
+            Some(script) if self.events.iter().any(|e| e == event) => script,
+            _ => return,
+        };
+
+        trace!("Running hook script {:?} for {}", script, event);
+        let spawned = Command::new(script)
+            .env("RATIOSPLIT_EVENT", event)
+            .envs(vars.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(error) => {
+                report(
+                    &self.last_warning,
+                    format!("Error running hook script {:?}: {}", script, error),
+                );
+                return;
+            }
+        };
+
+        let last_warning = Arc::clone(&self.last_warning);
+        let event = event.to_string();
+        thread::spawn(move || match child.wait() {
+            Ok(status) if status.success() => trace!("Hook script for {} finished", event),
+            Ok(status) => report(
+                &last_warning,
+                format!("Hook script for {} failed with {}", event, status),
+            ),
+            Err(error) => report(
+                &last_warning,
+                format!("Error waiting for the hook script for {}: {}", event, error),
+            ),
+        });
+    }
+}
+
+/// Shows a desktop notification with `notify-send`, without waiting for it.
+pub fn notify(summary: &str, body: &str) {
+    let spawned = Command::new("notify-send")
+        .arg("--app-name=ratiosplit")
+        .arg(summary)
+        .arg(body)
+        .stdin(Stdio::null())
+        .spawn();
+    match spawned {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(error) => warn!("Error running notify-send: {}", error),
+    }
+}
+
+fn report(last_warning: &Mutex<Option<Instant>>, message: String) {
+    let mut last_warning = last_warning.lock().unwrap();
+    if last_warning.is_some_and(|at| at.elapsed() < FAILURE_WARN_INTERVAL) {
+        debug!("{}", message);
+    } else {
+        warn!("{}", message);
+        *last_warning = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use chrono::Local;
+
+    /// A hook script that appends its event and the window's class to `output`.
+    fn recording_hooks(dir: &Path, output: &Path, events: &[&str]) -> Hooks {
+        fs::create_dir_all(dir).unwrap();
+        let script = dir.join("hook.sh");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$RATIOSPLIT_EVENT $RATIOSPLIT_CLASS\" >> {:?}\n",
+                output
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        Hooks {
+            script: Some(script.to_string_lossy().into_owned()),
+            events: events.iter().map(|event| event.to_string()).collect(),
+            last_warning: Arc::default(),
+        }
+    }
+
+    fn action(commands: &[&str]) -> Action {
+        Action {
+            id: Some(1),
+            time: Local::now(),
+            event: "new",
+            window: 11,
+            class: Some("Firefox".to_string()),
+            title: None,
+            workspace: Some("1".to_string()),
+            output: None,
+            ratio: "0.5".to_string(),
+            decision: "split".to_string(),
+            commands: commands.iter().map(|command| command.to_string()).collect(),
+        }
+    }
+
+    /// Waits up to a few seconds for the hook script to have written `lines` lines to `output`.
+    fn wait_for_lines(output: &Path, lines: usize) {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            let written = fs::read_to_string(output).unwrap_or_default();
+            if written.lines().count() >= lines {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn scripts_run_only_for_their_events() {
+        let dir = env::temp_dir().join(format!("ratiosplit-hooks-{}", std::process::id()));
+        let output = dir.join("events");
+        let hooks = recording_hooks(&dir, &output, &["handled"]);
+
+        hooks.action(&action(&[]));
+        hooks.action(&action(&["split h"]));
+        wait_for_lines(&output, 1);
+        // Give a wrongly started script for `skipped` the chance to show up too.
+        thread::sleep(Duration::from_millis(100));
+        let written = fs::read_to_string(&output).unwrap_or_default();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written, "handled Firefox\n");
+    }
+
+    #[test]
+    fn failures_are_warned_about_once_a_minute() {
+        let last_warning = Mutex::new(None);
+        report(&last_warning, "first".to_string());
+        let first = last_warning.lock().unwrap().unwrap();
+
+        report(&last_warning, "second".to_string());
+        assert_eq!(*last_warning.lock().unwrap(), Some(first));
+
+        let long_ago = Instant::now() - FAILURE_WARN_INTERVAL * 2;
+        *last_warning.lock().unwrap() = Some(long_ago);
+        report(&last_warning, "third".to_string());
+        assert!(last_warning.lock().unwrap().unwrap() > long_ago);
+    }
+}
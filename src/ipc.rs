@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::process;
+
+use i3ipc::reply::{Node, NodeBorder, NodeLayout, NodeType, WindowProperty};
+use i3ipc::EstablishError;
+use serde_json::Value;
+
+use crate::marks::user_marks;
+
+const MAGIC: &[u8] = b"i3-ipc";
+const GET_TREE: u32 = 4;
+
+/// Tree fields the i3ipc crate doesn't expose, read from the raw tree reply.
+#[derive(Debug, Default, Clone)]
+pub struct NodeExtras {
+    pub marks: Vec<String>,
+    /// The pid of the process owning the window, which only sway reports.
+    pub pid: Option<u32>,
+    /// Whether the node is fullscreen, on its output or globally.
+    pub fullscreen: bool,
+}
+
+/// `NodeExtras` for every node in the tree, keyed by node id.
+pub type Extras = HashMap<i64, NodeExtras>;
+
+/// A connection of its own for GET_TREE, whose raw reply gives both the tree and the `Extras`
+/// i3ipc's parsed one drops, so that each look at the tree takes a single request.
+pub struct TreeConnection {
+    stream: UnixStream,
+}
+
+impl TreeConnection {
+    /// Connects to i3's socket, found the same way the i3ipc crate does.
+    pub fn connect() -> Result<TreeConnection, EstablishError> {
+        let path = socket_path().map_err(EstablishError::GetSocketPathError)?;
+        let stream = UnixStream::connect(path).map_err(EstablishError::SocketError)?;
+        Ok(TreeConnection { stream })
+    }
+
+    /// Fetches the tree, along with the extra fields for every node, including floating ones.
+    pub fn get_tree(&mut self) -> io::Result<(Node, Extras)> {
+        let tree = get_raw_tree(&mut self.stream)?;
+        let mut extras = Extras::new();
+        collect_extras(&tree, &mut extras);
+        Ok((build_node(&tree)?, extras))
+    }
+}
+
+/// Fetches the tree and its `Extras` over a fresh connection, for commands run without the
+/// daemon.
+pub fn get_tree() -> io::Result<(Node, Extras)> {
+    TreeConnection::connect()
+        .map_err(|error| io::Error::other(format!("{:?}", error)))?
+        .get_tree()
+}
+
+/// Builds the i3ipc node for a container of the raw tree, along with all of its children.
+fn build_node(value: &Value) -> io::Result<Node> {
+    let str_field = |key: &str| value.get(key).and_then(Value::as_str).unwrap_or_default();
+    let rect = |key: &str| {
+        let rect = value.get(key);
+        let field = |name: &str| {
+            rect.and_then(|rect| rect.get(name))
+                .and_then(Value::as_i64)
+                .unwrap_or_default() as i32
+        };
+        (field("x"), field("y"), field("width"), field("height"))
+    };
+    let children = |key: &str| -> io::Result<Vec<Node>> {
+        value
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|children| children.iter().map(build_node).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))
+    };
+
+    Ok(Node {
+        focus: value
+            .get("focus")
+            .and_then(Value::as_array)
+            .map(|ids| ids.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default(),
+        nodes: children("nodes")?,
+        floating_nodes: children("floating_nodes")?,
+        id: value
+            .get("id")
+            .and_then(Value::as_i64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "tree node without an id"))?,
+        name: value.get("name").and_then(Value::as_str).map(String::from),
+        nodetype: match str_field("type") {
+            "root" => NodeType::Root,
+            "output" => NodeType::Output,
+            "con" => NodeType::Con,
+            "floating_con" => NodeType::FloatingCon,
+            "workspace" => NodeType::Workspace,
+            "dockarea" => NodeType::DockArea,
+            _ => NodeType::Unknown,
+        },
+        border: match str_field("border") {
+            "normal" => NodeBorder::Normal,
+            "none" => NodeBorder::None,
+            "pixel" => NodeBorder::Pixel,
+            _ => NodeBorder::Unknown,
+        },
+        current_border_width: value
+            .get("current_border_width")
+            .and_then(Value::as_i64)
+            .unwrap_or_default() as i32,
+        layout: match str_field("layout") {
+            "splith" => NodeLayout::SplitH,
+            "splitv" => NodeLayout::SplitV,
+            "stacked" => NodeLayout::Stacked,
+            "tabbed" => NodeLayout::Tabbed,
+            "dockarea" => NodeLayout::DockArea,
+            "output" => NodeLayout::Output,
+            _ => NodeLayout::Unknown,
+        },
+        percent: value.get("percent").and_then(Value::as_f64),
+        rect: rect("rect"),
+        window_rect: rect("window_rect"),
+        deco_rect: rect("deco_rect"),
+        geometry: rect("geometry"),
+        window: value
+            .get("window")
+            .and_then(Value::as_i64)
+            .map(|window| window as i32),
+        window_properties: value
+            .get("window_properties")
+            .and_then(Value::as_object)
+            .map(|properties| {
+                properties
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let property = match key.as_str() {
+                            "class" => WindowProperty::Class,
+                            "instance" => WindowProperty::Instance,
+                            "window_role" => WindowProperty::WindowRole,
+                            "title" => WindowProperty::Title,
+                            "transient_for" => WindowProperty::TransientFor,
+                            _ => return None,
+                        };
+                        Some((property, value.as_str().unwrap_or_default().to_string()))
+                    })
+                    .collect()
+            }),
+        urgent: value
+            .get("urgent")
+            .and_then(Value::as_bool)
+            .unwrap_or_default(),
+        focused: value
+            .get("focused")
+            .and_then(Value::as_bool)
+            .unwrap_or_default(),
+    })
+}
+
+fn collect_extras(node: &Value, extras: &mut Extras) {
+    if let Some(id) = node.get("id").and_then(Value::as_i64) {
+        extras.insert(
+            id,
+            NodeExtras {
+                marks: node
+                    .get("marks")
+                    .and_then(Value::as_array)
+                    .map(|marks| user_marks(marks.iter().filter_map(Value::as_str)))
+                    .unwrap_or_default(),
+                pid: node
+                    .get("pid")
+                    .and_then(Value::as_u64)
+                    .and_then(|pid| u32::try_from(pid).ok()),
+                fullscreen: node
+                    .get("fullscreen_mode")
+                    .and_then(Value::as_u64)
+                    .is_some_and(|mode| mode != 0),
+            },
+        );
+    }
+
+    for key in &["nodes", "floating_nodes"] {
+        if let Some(children) = node.get(key).and_then(Value::as_array) {
+            for child in children {
+                collect_extras(child, extras);
+            }
+        }
+    }
+}
+
+fn get_raw_tree(stream: &mut UnixStream) -> io::Result<Value> {
+    let mut message = Vec::with_capacity(MAGIC.len() + 8);
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&0u32.to_le_bytes());
+    message.extend_from_slice(&GET_TREE.to_le_bytes());
+    stream.write_all(&message)?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..MAGIC.len()] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected magic string in i3 reply",
+        ));
+    }
+
+    let mut length = [0u8; 4];
+    length.copy_from_slice(&header[6..10]);
+    let mut payload = vec![0u8; u32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut payload)?;
+
+    serde_json::from_slice(&payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Finds i3's socket the same way the i3ipc crate does.
+fn socket_path() -> io::Result<String> {
+    if let Ok(path) = env::var("I3SOCK") {
+        return Ok(path);
+    }
+    if let Ok(path) = env::var("SWAYSOCK") {
+        return Ok(path);
+    }
+
+    let output = process::Command::new("i3")
+        .arg("--get-socketpath")
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string())
+    } else {
+        Err(io::Error::other("i3 --get-socketpath failed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_tree() -> Value {
+        serde_json::json!({
+            "id": 1,
+            "type": "root",
+            "name": "root",
+            "layout": "splith",
+            "rect": {"x": 0, "y": 0, "width": 1000, "height": 500},
+            "focus": [10],
+            "nodes": [{
+                "id": 10,
+                "type": "workspace",
+                "name": "1",
+                "layout": "splitv",
+                "percent": null,
+                "rect": {"x": 0, "y": 0, "width": 1000, "height": 500},
+                "nodes": [{
+                    "id": 100,
+                    "type": "con",
+                    "name": "Terminal",
+                    "layout": "splith",
+                    "percent": 0.5,
+                    "rect": {"x": 0, "y": 0, "width": 1000, "height": 250},
+                    "window": 4194307,
+                    "window_properties": {"class": "URxvt", "instance": "urxvt"},
+                    "marks": ["main", "_rs_managed_100"],
+                    "fullscreen_mode": 1,
+                    "focused": true
+                }],
+                "floating_nodes": [{
+                    "id": 200,
+                    "type": "floating_con",
+                    "layout": "splith",
+                    "rect": {"x": 10, "y": 10, "width": 300, "height": 200},
+                    "pid": 4321
+                }]
+            }]
+        })
+    }
+
+    #[test]
+    fn builds_the_tree_from_the_raw_reply() {
+        let tree = build_node(&raw_tree()).unwrap();
+        assert_eq!(tree.nodetype, NodeType::Root);
+        assert_eq!(tree.focus, vec![10]);
+
+        let workspace = &tree.nodes[0];
+        assert_eq!(workspace.nodetype, NodeType::Workspace);
+        assert_eq!(workspace.layout, NodeLayout::SplitV);
+        assert_eq!(workspace.percent, None);
+        assert_eq!(workspace.floating_nodes[0].nodetype, NodeType::FloatingCon);
+
+        let window = &workspace.nodes[0];
+        assert_eq!(window.rect, (0, 0, 1000, 250));
+        assert_eq!(window.percent, Some(0.5));
+        assert_eq!(window.window, Some(4194307));
+        assert!(window.focused);
+        assert_eq!(
+            window
+                .window_properties
+                .as_ref()
+                .and_then(|properties| properties.get(&WindowProperty::Class)),
+            Some(&"URxvt".to_string())
+        );
+    }
+
+    #[test]
+    fn collects_extras_from_the_same_reply() {
+        let mut extras = Extras::new();
+        collect_extras(&raw_tree(), &mut extras);
+
+        let window = &extras[&100];
+        assert_eq!(window.marks, vec!["main".to_string()]);
+        assert!(window.fullscreen);
+        assert_eq!(window.pid, None);
+
+        let floating = &extras[&200];
+        assert_eq!(floating.pid, Some(4321));
+        assert!(!floating.fullscreen);
+        assert!(!extras[&10].fullscreen);
+    }
+
+    #[test]
+    fn nodes_without_an_id_are_an_error() {
+        assert!(build_node(&serde_json::json!({"type": "con"})).is_err());
+    }
+}
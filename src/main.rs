@@ -1,226 +1,404 @@
 #[macro_use]
 extern crate log;
 
-use core::panic;
-use std::fs::OpenOptions;
-
-use i3ipc::{
-    event::{inner::WindowChange, Event, WindowEventInfo},
-    reply::{Node, NodeLayout, NodeType},
-    EstablishError, I3Connection, I3EventListener, Subscription,
-};
-use log::{trace, warn};
-use settings::{load_settings, Settings};
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+use std::{env, process};
+
+use daemon::ExitReason;
+use diagnostics::{summarize_tree, EventLogger};
+use log::LevelFilter;
+use settings::{default_settings, load_settings, load_settings_from_file, ConfigError, Settings};
 use simplelog::{CombinedLogger, SharedLogger, TermLogger, TerminalMode, WriteLogger};
 
+mod animate;
+mod backend;
+mod columns;
+mod command;
+mod control;
+mod daemon;
+mod diagnostics;
+#[cfg(test)]
+mod fake_i3;
+#[cfg(test)]
+mod fixtures;
+mod hooks;
+mod ipc;
+mod marks;
+mod planner;
+mod procinfo;
+mod selftest;
 mod settings;
+mod state;
+mod state_store;
+mod statusd;
+mod tree;
 
 fn main() {
-    let settings = load_settings();
-    setup_logger(&settings);
-
-    info!("Starting i3 ratiosplit, connecting to i3");
-
-    let (mut connection, mut listener) = match setup_i3_connection() {
-        Ok(t) => t,
-        Err(error) => {
-            error!("Error connecting to i3: {:?}", error);
-            return;
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // The path after --check-config-file is its value, not a subcommand.
+    let subcommand = args
+        .iter()
+        .enumerate()
+        .find(|&(i, a)| !a.starts_with("--") && (i == 0 || args[i - 1] != "--check-config-file"))
+        .map(|(_, a)| a);
+
+    match subcommand.map(String::as_str) {
+        None if args.iter().any(|a| a == "--config-json") => {
+            let (settings, problems) = load(&args);
+            for problem in &problems {
+                eprintln!("{}", problem);
+            }
+            match serde_json::to_string_pretty(&settings) {
+                Ok(json) => println!("{}", json),
+                Err(error) => {
+                    eprintln!("Error serializing settings: {}", error);
+                    process::exit(1);
+                }
+            }
         }
-    };
-
-    let events = [Subscription::Window];
-    info!("Subscribing to events: {:?}", events);
-    if let Err(error) = listener.subscribe(&events) {
-        error!("Error subscribing to events: {:?}", error);
-        return;
-    }
-
-    for event in listener.listen() {
-        if let Ok(Event::WindowEvent(event_info)) = event {
-            match event_info {
-                WindowEventInfo {
-                    change: WindowChange::New,
-                    container,
-                } => {
-                    info!("New window created {:?}", container.name);
-                    trace!("Container properties: {:?}", container);
-                    handle_child(&mut connection, container);
+        None if args.iter().any(|a| a == "--check-config-file") => {
+            let path = args
+                .iter()
+                .position(|a| a == "--check-config-file")
+                .and_then(|i| args.get(i + 1));
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    eprintln!("--check-config-file needs a path");
+                    process::exit(ExitReason::Config.code());
                 }
-                _ => {
-                    trace!(
-                        "Ignoring event {:?}: {:?}",
-                        event_info.change, event_info.container.name
+            };
+            match load_settings_from_file(path) {
+                Ok((_, warnings)) if warnings.is_empty() => println!("{} is a valid config", path),
+                Ok((_, warnings)) => {
+                    for warning in &warnings {
+                        eprintln!("{}: {}", path, warning);
+                    }
+                    println!(
+                        "{} is a valid config, with {} value(s) replaced by defaults",
+                        path,
+                        warnings.len()
                     );
                 }
+                Err(error) => {
+                    eprintln!("{}: {}", path, error);
+                    process::exit(ExitReason::Config.code());
+                }
+            }
+        }
+        None if args.iter().any(|a| a == "--self-test") => {
+            let (settings, problems) = load(&args);
+            setup_logger(&settings, foreground_log(&args));
+            report_config(&args, &problems);
+            if !selftest::run(&settings) {
+                process::exit(1);
+            }
+        }
+        None => {
+            let (mut settings, problems) = load(&args);
+            let watch = args.iter().any(|a| a == "--watch");
+            if args.iter().any(|a| a == "--dry-run") {
+                settings.suggest = true;
+            }
+            if watch {
+                // Decisions go to stdout instead, so only problems are logged, and not to the file.
+                let console = foreground_log(&args).unwrap_or(LevelFilter::Warn);
+                setup_console_logger(&settings, console);
+            } else {
+                setup_logger(&settings, foreground_log(&args));
+            }
+            report_config(&args, &problems);
+            let fresh = args.iter().any(|a| a == "--fresh");
+            if let Err(reason) = daemon::run(settings, fresh, watch) {
+                process::exit(reason.code());
+            }
+        }
+        Some("cleanup") => match marks::cleanup() {
+            Ok(removed) => println!("Removed {} debug mark(s)", removed),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        Some("statusd") => statusd::run(args.iter().any(|a| a == "--json")),
+        Some(verb @ "status")
+        | Some(verb @ "pause")
+        | Some(verb @ "resume")
+        | Some(verb @ "toggle")
+        | Some(verb @ "suggest")
+        | Some(verb @ "undo")
+        | Some(verb @ "promote")
+        | Some(verb @ "unlock") => run_client(verb),
+        Some("lock") => {
+            let percent = args
+                .iter()
+                .filter(|a| !a.starts_with("--"))
+                .nth(1)
+                .map(String::as_str)
+                .unwrap_or_default();
+            run_client(&format!("lock {}", percent));
+        }
+        Some("history") => {
+            let limit = args
+                .iter()
+                .position(|a| a == "--limit")
+                .and_then(|i| args.get(i + 1));
+            let line = match limit {
+                Some(limit) => format!("history {}", limit),
+                None => "history".to_string(),
+            };
+            if args.iter().any(|a| a == "--json") {
+                run_client_json(&line);
+            } else {
+                run_client(&line);
             }
-        } else {
-            error!("Unexpected event or error: {:?}", event);
-            return;
+        }
+        Some("msg") => {
+            let line = args
+                .iter()
+                .skip_while(|a| *a != "msg")
+                .skip(1)
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ");
+            run_client_msg(&line);
+        }
+        Some("tree") => {
+            let workspace = args
+                .iter()
+                .position(|a| a == "--workspace")
+                .and_then(|i| args.get(i + 1));
+            print_tree(
+                workspace.map(String::as_str),
+                args.iter().any(|a| a == "--json"),
+            );
+        }
+        Some("explain") if args.iter().any(|a| a == "--json") => run_client_json("explain"),
+        Some("explain") => run_client("explain"),
+        Some("rules") if args.iter().any(|a| a == "--json") => run_client_json("rules"),
+        Some("rules") => run_client("rules"),
+        Some("set-mode") => {
+            let mode = args
+                .iter()
+                .filter(|a| !a.starts_with("--"))
+                .nth(1)
+                .map(String::as_str)
+                .unwrap_or_default();
+            if args.iter().any(|a| a == "--apply") {
+                run_client(&format!("set-mode {} apply", mode));
+            } else {
+                run_client(&format!("set-mode {}", mode));
+            }
+        }
+        Some("cycle-ratio") if args.iter().any(|a| a == "--apply") => {
+            run_client("cycle-ratio apply")
+        }
+        Some("cycle-ratio") => run_client("cycle-ratio"),
+        Some(other) => {
+            eprintln!("Unknown command {:?}", other);
+            process::exit(ExitReason::Config.code());
         }
     }
 }
 
-fn setup_logger(settings: &Settings) {
-    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
-
-    if let Ok(file) = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(settings.log_file.as_str())
-    {
-        loggers.push(WriteLogger::new(
-            settings.log_file_level,
-            simplelog::Config::default(),
-            file,
-        ))
-    }
-
-    if let Some(console) = TermLogger::new(
-        settings.log_console_level,
-        simplelog::Config::default(),
-        TerminalMode::Mixed,
-    ) {
-        loggers.push(console);
+/// Sends a single control request to the running daemon and prints its response.
+fn run_client(line: &str) {
+    match control::request(line) {
+        Ok(response) if response.ok => println!("{}", response.message),
+        Ok(response) => {
+            eprintln!("{}", response.message);
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Could not reach the ratiosplit daemon: {}", error);
+            process::exit(1);
+        }
     }
-
-    CombinedLogger::init(loggers).unwrap();
-
-    info!("Using settings {:?}", settings);
-}
-
-fn setup_i3_connection() -> Result<(I3Connection, I3EventListener), EstablishError> {
-    info!("Main connection connecting");
-    let connection = I3Connection::connect()?;
-    info!("Listener connecting");
-    let listener = I3EventListener::connect()?;
-    Ok((connection, listener))
 }
 
-fn handle_child(connection: &mut I3Connection, new_node: Node) {
-    trace!("Retreiving current tree");
-
-    let tree = match connection.get_tree() {
-        Ok(t) => t,
+/// Like `run_client`, but prints the response's data as JSON rather than its message.
+fn run_client_json(line: &str) {
+    match control::request(line) {
+        Ok(response) if response.ok => {
+            let data = response.data.unwrap_or(serde_json::Value::Null);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&data).unwrap_or_default()
+            );
+        }
+        Ok(response) => {
+            eprintln!("{}", response.message);
+            process::exit(1);
+        }
         Err(error) => {
-            error!("Error retreiving the current i3 tree: {:?}", error);
-            panic!("Error retreiving the current i3 tree: {:?}", error);
+            eprintln!("Could not reach the ratiosplit daemon: {}", error);
+            process::exit(1);
         }
-    };
-
-    trace!("Retrieved tree.");
-
-    if let Some(parent) = find_parent(new_node.id, &tree) {
-        trace!("Found parent node for {:?}", new_node.name);
+    }
+}
 
-        // If the parent is not a container or is not a splitv/h, there's nothing to resize
-        if !matches!(parent, Node { nodetype: NodeType::Con, layout: NodeLayout::SplitH, .. } |
-                             Node { nodetype: NodeType::Con, layout: NodeLayout::SplitV, .. } |
-                             Node { nodetype: NodeType::Workspace, layout: NodeLayout::SplitH, .. } |
-                             Node { nodetype: NodeType::Workspace, layout: NodeLayout::SplitV, .. })
-        {
-            info!("Parent node is type {:?}, not resizing", parent.nodetype);
-            trace!("Parent properties: {:?}", parent);
+/// Prints the tree as the daemon sees it, or straight from i3, without the daemon's annotations,
+/// when the daemon isn't running.
+fn print_tree(workspace: Option<&str>, json: bool) {
+    let line = match workspace {
+        Some(workspace) => format!("tree {}", workspace),
+        None => "tree".to_string(),
+    };
+    match control::request(&line) {
+        Ok(response) if response.ok && json => {
+            let data = response.data.unwrap_or(serde_json::Value::Null);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&data).unwrap_or_default()
+            );
             return;
         }
-
-        // If there are not 2 children in this node, we can't resize one for golden mode,
-        // and would likely just annoy people if we did. Skip.
-        if parent.nodes.len() != 2 {
-            info!("Parent node has {} children, skipping", parent.nodes.len());
-            trace!("Parent properties: {:?}", parent);
+        Ok(response) if response.ok => {
+            println!("{}", response.message);
             return;
         }
+        Ok(response) => {
+            eprintln!("{}", response.message);
+            process::exit(1);
+        }
+        Err(_) => {}
+    }
 
-        trace!("Parent node is of known config, resizing");
-
-        // Finally, we want to resize the window, and set tiling to split the next window
-        // in the opposite direction that this was split to maintain the golden spiral.
-        // We actually set tiling first, on both windows, so that making a new window in either
-        // location will correctly maintain the golden spiral. We then want to move the current
-        // split location to 33% along the direction of the split.
-
-        let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    let summary = ipc::get_tree()
+        .map_err(|error| format!("Error retrieving the tree: {:?}", error))
+        .and_then(|(tree, extras)| summarize_tree(&tree, &extras, workspace, &|_| None));
+    match summary {
+        Ok(summary) if json => println!(
+            "{}",
+            serde_json::to_string_pretty(&summary).unwrap_or_default()
+        ),
+        Ok(summary) => println!("{}", summary),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
 
-        trace!(
-            "Resizing {}",
-            if resize_horizontal {
-                "horizontally"
+/// Sends any request line to the daemon, and prints its message followed by its data, if any.
+fn run_client_msg(line: &str) {
+    match control::request(line) {
+        Ok(response) => {
+            if response.ok {
+                println!("{}", response.message);
             } else {
-                "vertically"
+                eprintln!("{}", response.message);
             }
-        );
-
-        let split_command = format!(
-            "split {}",
-            if resize_horizontal {
-                "vertical"
-            } else {
-                "horizontal"
+            if let Some(data) = response.data {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&data).unwrap_or_default()
+                );
             }
-        );
+            if !response.ok {
+                process::exit(1);
+            }
+        }
+        Err(error) => {
+            eprintln!("Could not reach the ratiosplit daemon: {}", error);
+            process::exit(1);
+        }
+    }
+}
 
-        for child in &parent.nodes {
-            let focus_child = focus_id(child);
+fn no_config(args: &[String]) -> bool {
+    args.iter().any(|a| a == "--no-config")
+}
 
-            trace!("Running {}", focus_child);
-            if let Err(error) = connection.run_command(focus_child.as_str()) {
-                warn!("Error {:?} when focusing child {:?}", error, child);
-                return;
-            }
+/// Loads the settings from the config file, or uses the defaults with `--no-config`, along with
+/// any problems with the config.
+fn load(args: &[String]) -> (Settings, Vec<ConfigError>) {
+    if no_config(args) {
+        (default_settings(), Vec::new())
+    } else {
+        load_settings()
+    }
+}
 
-            trace!("Running {}", split_command);
-            if let Err(error) = connection.run_command(split_command.as_str()) {
-                warn!("Error {:?} when splitting child {:?}", error, child);
-                return;
-            }
+/// Logs where the settings came from, and the problems `load` found with them.
+fn report_config(args: &[String], problems: &[ConfigError]) {
+    if no_config(args) {
+        info!("--no-config given, not reading any config file");
+    }
+    for problem in problems {
+        if problem.is_fatal() {
+            error!("{}, using defaults", problem);
+        } else {
+            warn!("{}", problem);
         }
+    }
+}
 
-        trace!("Split children");
+/// The console log level given with `--foreground-log[=LEVEL]`, if any. A bare flag logs at debug.
+fn foreground_log(args: &[String]) -> Option<LevelFilter> {
+    let arg = args.iter().find(|a| a.starts_with("--foreground-log"))?;
+    match arg.strip_prefix("--foreground-log").unwrap_or_default() {
+        "" => Some(LevelFilter::Debug),
+        level => match level.strip_prefix('=').map(str::parse) {
+            Some(Ok(level)) => Some(level),
+            _ => {
+                eprintln!("Unknown log level in {:?}", arg);
+                process::exit(ExitReason::Config.code());
+            }
+        },
+    }
+}
 
-        let focus_command = format!("[id={}] focus", new_node.id);
-        let resize_command = format!(
-            "resize set {} 33 ppt",
-            if resize_horizontal { "width" } else { "height" }
-        );
+/// Sets up logging to the console alone, at the given level, for `--watch`.
+fn setup_console_logger(settings: &Settings, level: LevelFilter) {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+    if let Some(console) =
+        TermLogger::new(level, simplelog::Config::default(), TerminalMode::Stderr)
+    {
+        loggers.push(console);
+    }
 
-        trace!("Running {}", focus_command);
-        if let Err(error) = connection.run_command(focus_command.as_str()) {
-            warn!("Error {:?} when focusing node {:?}", error, new_node);
-            return;
-        }
+    let combined = CombinedLogger::new(loggers);
+    log::set_max_level(combined.level());
+    log::set_boxed_logger(Box::new(EventLogger::new(combined))).unwrap();
 
-        trace!("Running {}", resize_command);
-        if let Err(error) = connection.run_command(resize_command.as_str()) {
-            warn!("Error {:?} when resizing node {:?}", error, new_node);
-            return;
-        }
+    info!("Using settings {:?}", settings);
+}
 
-        info!("Resized {:?} successfully", new_node.name);
+/// Sets up logging to the log file and the console. `console_level` replaces the configured
+/// console level when given.
+fn setup_logger(settings: &Settings, console_level: Option<LevelFilter>) {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
 
-        fn focus_id(node: &Node) -> String {
-            format!("[id={}] focus", node.id)
-        }
-    } else {
-        info!("Could not find parent node for {:?}.", new_node.name);
-        trace!("Tree: {:?}", tree);
+    if let Some(dir) = Path::new(&settings.log_file).parent() {
+        // Logging isn't set up yet, and opening the file below fails the same way anyway.
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Ok(file) = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(settings.log_file.as_str())
+    {
+        loggers.push(WriteLogger::new(
+            settings.log_file_level,
+            simplelog::Config::default(),
+            file,
+        ))
     }
 
-    fn find_parent(child_id: i64, node: &Node) -> Option<&Node> {
-        // In order to find the child node, we get the tree and loop through all the children.
-        // There are a few possible failure conditions:
-        // 1. The node isn't in the tree
-        // 2. The node is a floating node (no need to dynamically resize these, so just don't check that field).
-        // 3. The given id is for the root node.
-
-        for child in &node.nodes {
-            if child.id == child_id {
-                return Some(node);
-            } else if let Some(found) = find_parent(child_id, child) {
-                return Some(found);
-            }
-        }
-
-        None
+    if let Some(console) = TermLogger::new(
+        console_level.unwrap_or(settings.log_console_level),
+        simplelog::Config::default(),
+        TerminalMode::Mixed,
+    ) {
+        loggers.push(console);
     }
+
+    let combined = CombinedLogger::new(loggers);
+    log::set_max_level(combined.level());
+    log::set_boxed_logger(Box::new(EventLogger::new(combined))).unwrap();
+
+    info!("Using settings {:?}", settings);
 }
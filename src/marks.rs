@@ -0,0 +1,130 @@
+use i3ipc::I3Connection;
+
+use crate::command::{failed_outcome, render_all, Command};
+
+/// The prefix of every mark ratiosplit leaves on containers with `debug_marks`, so they can be
+/// told apart from the user's own marks and swept up again.
+const DEBUG_MARK_PREFIX: &str = "_rs_";
+
+/// Whether the mark is one of ratiosplit's debug marks rather than the user's.
+pub fn is_debug_mark(mark: &str) -> bool {
+    mark.starts_with(DEBUG_MARK_PREFIX)
+}
+
+/// The user's own marks among `marks`, so that ratiosplit's debug marks are never mistaken for
+/// them, like for `anchor_mark`.
+pub fn user_marks<'a>(marks: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    marks
+        .into_iter()
+        .filter(|mark| !is_debug_mark(mark))
+        .map(String::from)
+        .collect()
+}
+
+/// Marks a window ratiosplit handled, with `debug_marks`. Marks are unique in i3, so the mark
+/// names the container, and it's added with `--add` so the window keeps any marks the user gave
+/// it. `--toggle` isn't used: handling the same window twice must not take the mark off again.
+pub fn managed_mark(con_id: i64) -> Command {
+    Command::Mark {
+        con_id,
+        mark: format!("{}managed_{}", DEBUG_MARK_PREFIX, con_id),
+    }
+}
+
+/// Commands removing each of the debug marks among `marks`, leaving the others alone.
+pub fn sweep_commands(marks: &[String]) -> Vec<Command> {
+    marks
+        .iter()
+        .filter(|mark| is_debug_mark(mark))
+        .map(|mark| Command::Unmark(mark.clone()))
+        .collect()
+}
+
+/// Removes every debug mark i3 knows of, found with GET_MARKS, returning how many were removed.
+/// The unmarks are sent together, and i3 still runs the rest when one of them fails, like for a
+/// window that closed meanwhile, so only the ones it refused are left out of the count.
+pub fn sweep(connection: &mut I3Connection) -> Result<usize, String> {
+    let marks = connection
+        .get_marks()
+        .map_err(|error| format!("Error retrieving marks: {:?}", error))?
+        .marks;
+    let commands = sweep_commands(&marks);
+    if commands.is_empty() {
+        return Ok(0);
+    }
+
+    let command = render_all(&commands).join("; ");
+    let reply = connection
+        .run_command(&command)
+        .map_err(|error| format!("Error running {:?}: {:?}", command, error))?;
+    let removed = reply.outcomes.iter().filter(|o| o.success).count();
+    if let Some(error) = failed_outcome(&reply.outcomes) {
+        warn!(
+            "i3 refused to remove {} of {} debug mark(s): {}",
+            commands.len() - removed,
+            commands.len(),
+            error
+        );
+    }
+    Ok(removed)
+}
+
+/// Removes the debug marks over a connection of its own, for `i3-ratiosplit cleanup`, which has
+/// to work whether or not the daemon is still running.
+pub fn cleanup() -> Result<usize, String> {
+    let mut connection =
+        I3Connection::connect().map_err(|error| format!("Could not connect to i3: {:?}", error))?;
+    sweep(&mut connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(marks: &[&str]) -> Vec<String> {
+        marks.iter().map(|mark| mark.to_string()).collect()
+    }
+
+    #[test]
+    fn managed_marks_name_the_container() {
+        let mark = managed_mark(42);
+        assert_eq!(
+            mark,
+            Command::Mark {
+                con_id: 42,
+                mark: "_rs_managed_42".to_string(),
+            }
+        );
+        assert_eq!(
+            mark.to_string(),
+            "[con_id=42] mark --add \"_rs_managed_42\""
+        );
+    }
+
+    #[test]
+    fn debug_marks_are_told_apart_from_the_users() {
+        assert!(is_debug_mark("_rs_managed_42"));
+        assert!(!is_debug_mark("main"));
+        assert!(!is_debug_mark("rs_managed"));
+    }
+
+    #[test]
+    fn sweep_unmarks_only_debug_marks() {
+        assert_eq!(
+            sweep_commands(&marks(&["main", "_rs_managed_1", "_rs_managed_2"])),
+            vec![
+                Command::Unmark("_rs_managed_1".to_string()),
+                Command::Unmark("_rs_managed_2".to_string()),
+            ]
+        );
+        assert!(sweep_commands(&marks(&["main"])).is_empty());
+    }
+
+    #[test]
+    fn debug_marks_are_not_the_users() {
+        assert_eq!(
+            user_marks(vec!["main", "_rs_managed_1", "anchor"]),
+            marks(&["main", "anchor"])
+        );
+    }
+}
@@ -0,0 +1,3326 @@
+use std::fmt;
+use std::time::Duration;
+
+use i3ipc::reply::{Node, NodeLayout, NodeType};
+
+use crate::columns::plan_columns;
+use crate::command::{render_template, Command};
+use crate::ipc::Extras;
+use crate::settings::{
+    CountAction, ManageTabbed, Mode, MultiChild, MultiOutput, NewWindowSide, OverflowLayout,
+    OverflowPolicy, ResizeMethod, ResizeUnit, Rounding, Settings, Sizing, SpiralSeed,
+    SplitDirection,
+};
+use crate::state::Remembered;
+use crate::tree::{
+    container_path, effective_percent, find_focused, find_node, find_output, find_parent,
+    find_workspace, is_docked, is_fullscreen, is_portrait, spans_outputs, split_direction,
+    tiled_leaves, tiled_windows, window_class, window_identity, workspace_number, workspaces,
+};
+
+/// How far a child's percent may be from the ratio before it's considered to have drifted.
+/// i3 rounds sizes to whole pixels, so exact matches are rare.
+pub const DRIFT_TOLERANCE: f64 = 0.02;
+
+/// How far a ratio in ppt may be below a whole number and still count as it.
+const PPT_EPSILON: f64 = 1e-6;
+
+/// How much of its parent, along each axis, a new window must cover to count as filling it.
+const FILL_TOLERANCE: f64 = 0.95;
+
+/// The bounds for ratios computed with `ratio = auto`.
+const AUTO_RATIO_MIN: f64 = 0.2;
+const AUTO_RATIO_MAX: f64 = 0.5;
+
+/// Why a new window was left alone. The planner decides most of these, the daemon the rest before
+/// it gets that far.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// ratiosplit is paused.
+    Paused,
+    /// The window's class is in `dropdown_classes`.
+    Dropdown,
+    /// The window's process is in `exclude_processes`.
+    ExcludedProcess(String),
+    /// The window matches this `exclude` entry.
+    Excluded(String),
+    /// The workspace was changed by hand this long ago, within `manual_cooldown`.
+    Cooldown(Duration),
+    /// The workspace is being restored with `append_layout`.
+    RestoredLayout,
+    /// The workspace isn't managed.
+    Unmanaged,
+    /// The window isn't in the tiling tree, like floating and scratchpad windows.
+    NoParent,
+    /// The window's container is tabbed or stacked rather than split.
+    NotSplit,
+    /// The window already fills its container, with `skip_if_fills_parent`.
+    FillsParent,
+    /// `count_policy` says to leave the window alone.
+    CountPolicy,
+    /// The window's container doesn't hold as many windows as the mode arranges.
+    WrongChildCount,
+    /// Every window in the container is anchored or locked.
+    Anchored,
+    /// The window's container spans more than one output, with `multi_output_container = skip`.
+    SpansOutputs,
+    /// The window is a bar or other dock window.
+    Dock,
+    /// With `pause_on_fullscreen`, the window's output is showing a fullscreen window.
+    Fullscreen,
+    /// With `skip_urgent`, the window has the urgency hint set.
+    Urgent,
+    /// `never_change_focus` is set, and the plan needs focus on another container.
+    WouldMoveFocus,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Paused => write!(f, "paused"),
+            SkipReason::Dropdown => write!(f, "dropdown"),
+            SkipReason::ExcludedProcess(process) => write!(f, "excluded process {}", process),
+            SkipReason::Excluded(matcher) => write!(f, "excluded by {}", matcher),
+            SkipReason::Cooldown(remaining) => {
+                write!(f, "cooldown, {:.1}s left", remaining.as_secs_f64())
+            }
+            SkipReason::RestoredLayout => write!(f, "restored layout"),
+            SkipReason::Unmanaged => write!(f, "unmanaged workspace"),
+            SkipReason::NoParent => write!(f, "no parent"),
+            SkipReason::NotSplit => write!(f, "not split"),
+            SkipReason::FillsParent => write!(f, "fills parent"),
+            SkipReason::CountPolicy => write!(f, "count_policy"),
+            SkipReason::WrongChildCount => write!(f, "wrong child count"),
+            SkipReason::Anchored => write!(f, "anchored"),
+            SkipReason::SpansOutputs => write!(f, "spans outputs"),
+            SkipReason::Dock => write!(f, "dock window"),
+            SkipReason::Fullscreen => write!(f, "output is fullscreen"),
+            SkipReason::Urgent => write!(f, "urgent"),
+            SkipReason::WouldMoveFocus => write!(f, "would move focus"),
+        }
+    }
+}
+
+/// What the planner decided to do for a new window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Act(Vec<Command>),
+    Skip(SkipReason),
+}
+
+impl Decision {
+    /// The commands to run, for callers that don't care why there are none.
+    pub fn commands(self) -> Option<Vec<Command>> {
+        match self {
+            Decision::Act(commands) => Some(commands),
+            Decision::Skip(_) => None,
+        }
+    }
+}
+
+/// Decides which i3 commands need to be run in response to `new_node` being created, or why the
+/// window should be left alone. `focused` is the container that actually has
+/// focus, which isn't always `new_node` by the time the event is handled; the commands leave
+/// focus there.
+pub fn plan_commands(
+    tree: &Node,
+    extras: &Extras,
+    remembered: &Remembered,
+    new_node: &Node,
+    focused: Option<i64>,
+    settings: &Settings,
+) -> Decision {
+    if is_docked(new_node.id, tree) {
+        return Decision::Skip(SkipReason::Dock);
+    }
+
+    let spanning = find_parent(new_node.id, tree).filter(|parent| spans_outputs(parent, tree));
+    if let Some(parent) = spanning {
+        if settings.multi_output_container == MultiOutput::Skip {
+            warn!(
+                "Container {} spans more than one output, skipping",
+                parent.id
+            );
+            return Decision::Skip(SkipReason::SpansOutputs);
+        }
+        info!(
+            "Container {} spans more than one output, resizing in ppt",
+            parent.id
+        );
+    }
+
+    let mut commands = match plan_layout(tree, extras, remembered, new_node, settings) {
+        Ok(commands) => commands,
+        Err(reason) => return Decision::Skip(reason),
+    };
+    if let Some(parent) = spanning {
+        commands = commands
+            .into_iter()
+            .map(|command| in_ppt(command, parent, settings))
+            .collect();
+    }
+
+    let moves_focus = commands.iter().any(|c| matches!(c, Command::Focus(_)));
+    if let Some(focused) = focused.filter(|&f| f != new_node.id && moves_focus) {
+        trace!(
+            "Focus moved to {} since {:?} opened",
+            focused,
+            new_node.name
+        );
+        commands.push(Command::Focus(focused));
+    }
+
+    if settings.never_change_focus {
+        let focused = find_focused(tree).map(|node| node.id);
+        match hold_focus(commands, focused) {
+            Ok(held) => commands = held,
+            Err(reason) => return Decision::Skip(reason),
+        }
+    }
+    Decision::Act(commands)
+}
+
+/// For `never_change_focus`: rewrites the commands so that none of them moves focus. Instead of
+/// focusing a container to split or resize it, the split or resize is pointed at the container
+/// with criteria, and the `focus` is dropped. Commands that move focus however they're run, like
+/// switching workspaces or moving the focused window away, fail the plan. Commands from the
+/// config are passed through as they are.
+pub fn hold_focus(
+    commands: Vec<Command>,
+    focused: Option<i64>,
+) -> Result<Vec<Command>, SkipReason> {
+    // The container the plan expects to have focus at each point.
+    let mut target = focused;
+    let mut held = Vec::with_capacity(commands.len());
+    for command in commands {
+        let command = match command {
+            Command::Focus(id) => {
+                target = Some(id);
+                continue;
+            }
+            Command::Split {
+                con_id: None,
+                direction,
+            } => Command::Split {
+                con_id: target,
+                direction,
+            },
+            Command::Layout {
+                con_id: None,
+                direction,
+            } => Command::Layout {
+                con_id: target,
+                direction,
+            },
+            Command::Resize { con_id: None, .. } | Command::ResizeBy { con_id: None, .. } => {
+                match target {
+                    Some(target) => targeting(command, target),
+                    None => command,
+                }
+            }
+            Command::MoveToWorkspace { con_id, .. } if Some(con_id) == focused => {
+                info!("{} would move focus, not running it", command);
+                return Err(SkipReason::WouldMoveFocus);
+            }
+            Command::Workspace(_) => {
+                info!("{} would move focus, not running it", command);
+                return Err(SkipReason::WouldMoveFocus);
+            }
+            command => command,
+        };
+        held.push(command);
+    }
+    Ok(held)
+}
+
+/// Decides how to lay out `new_node`'s container. Every plan ends with focus on `new_node`.
+fn plan_layout(
+    tree: &Node,
+    extras: &Extras,
+    remembered: &Remembered,
+    new_node: &Node,
+    settings: &Settings,
+) -> Result<Vec<Command>, SkipReason> {
+    if settings.mode == Mode::Columns {
+        return plan_columns(tree, new_node, settings).ok_or(SkipReason::NoParent);
+    }
+
+    let parent = find_split_parent(tree, new_node, settings)?;
+
+    if settings.overflow == OverflowPolicy::Layout
+        && settings.overflow_layout != OverflowLayout::None
+        && parent.nodes.len() > settings.overflow_threshold
+    {
+        info!(
+            "Parent node already held {} children, switching it to {:?}",
+            parent.nodes.len() - 1,
+            settings.overflow_layout
+        );
+        return Ok(vec![Command::Overflow {
+            con_id: new_node.id,
+            layout: settings.overflow_layout,
+        }]);
+    }
+
+    let current = find_node(new_node.id, tree).unwrap_or(new_node);
+    if settings.skip_if_fills_parent && fills_parent(current, parent) {
+        info!(
+            "{:?} already fills its parent, leaving it alone",
+            new_node.name
+        );
+        return Err(SkipReason::FillsParent);
+    }
+
+    let windows = find_workspace(new_node.id, tree).map_or(0, tiled_windows);
+    match settings.count_action(windows) {
+        Some(CountAction::None) => {
+            info!(
+                "count_policy leaves {:?} alone with {} windows",
+                new_node.name, windows
+            );
+            return Err(SkipReason::CountPolicy);
+        }
+        Some(CountAction::Equalize) if parent.nodes.len() > 1 => {
+            info!("count_policy equalizes with {} windows", windows);
+            return plan_equalize(parent, extras, new_node, settings).ok_or(SkipReason::Anchored);
+        }
+        _ => {}
+    }
+
+    if has_locked_child(parent, extras, settings) {
+        info!(
+            "{} has a locked child, sharing out the rest of it",
+            parent.id
+        );
+        return plan_equalize(parent, extras, new_node, settings).ok_or(SkipReason::Anchored);
+    }
+
+    match settings.mode {
+        Mode::Equalize if parent.nodes.len() > 1 => {
+            return plan_equalize(parent, extras, new_node, settings).ok_or(SkipReason::Anchored)
+        }
+        Mode::Dynamic if parent.nodes.len() > 2 => {
+            return plan_equalize(parent, extras, new_node, settings).ok_or(SkipReason::Anchored)
+        }
+        Mode::Master => {
+            return plan_master(parent, new_node, settings).ok_or(SkipReason::WrongChildCount)
+        }
+        _ => {}
+    }
+
+    if parent.nodes.len() > 2 && settings.multi_child == MultiChild::Equalize {
+        return plan_equalize(parent, extras, new_node, settings).ok_or(SkipReason::Anchored);
+    }
+
+    if !has_two_children(parent) {
+        return Err(SkipReason::WrongChildCount);
+    }
+
+    trace!("Parent node is of known config, resizing");
+
+    // Finally, we want to resize the window, and set tiling to split the next window
+    // in the opposite direction that this was split to maintain the golden spiral.
+    // We actually set tiling first, on both windows, so that making a new window in either
+    // location will correctly maintain the golden spiral. We then want to move the current
+    // split location to the configured ratio along the direction of the split. The ratio is
+    // always the new window's share, so it ends up as the larger pane when the ratio is above 0.5.
+
+    let mut commands = Vec::new();
+
+    // Windows with a configured split direction override the spiral for themselves: the parent
+    // is switched to that direction, and only the new window's own split is changed, so that
+    // the next level alternates from the forced direction.
+    // A window pinned to a side is forced in the direction that has that side.
+    let position = window_class(new_node).and_then(|class| settings.positions.get(class));
+    let forced = window_class(new_node)
+        .and_then(|class| settings.split_directions.get(class))
+        .copied()
+        .or_else(|| position.map(|p| p.direction()));
+    let resize_horizontal = match forced {
+        Some(direction) => {
+            info!(
+                "Forcing {:?} split for {:?}",
+                direction,
+                window_class(new_node)
+            );
+            let resize_horizontal = direction == SplitDirection::Horizontal;
+            if resize_horizontal != (parent.layout == NodeLayout::SplitH) {
+                commands.push(Command::Focus(new_node.id));
+                commands.push(Command::Layout {
+                    con_id: None,
+                    direction,
+                });
+            }
+            resize_horizontal
+        }
+        None if parent.nodetype == NodeType::Workspace
+            && settings.spiral_seed != SpiralSeed::Inherit =>
+        {
+            let resize_horizontal = settings.spiral_seed == SpiralSeed::Horizontal;
+            if resize_horizontal != (parent.layout == NodeLayout::SplitH) {
+                info!("Seeding the spiral {:?}", settings.spiral_seed);
+                commands.push(Command::Focus(new_node.id));
+                commands.push(Command::Layout {
+                    con_id: None,
+                    direction: if resize_horizontal {
+                        SplitDirection::Horizontal
+                    } else {
+                        SplitDirection::Vertical
+                    },
+                });
+            }
+            resize_horizontal
+        }
+        // i3 starts every workspace splitting horizontally, but on a portrait output the spiral
+        // should start by stacking windows vertically and alternate from there.
+        None if parent.nodetype == NodeType::Workspace
+            && parent.layout == NodeLayout::SplitH
+            && settings.portrait_aware
+            && is_portrait(parent.id, tree) =>
+        {
+            info!("Workspace is on a portrait output, starting the spiral vertically");
+            commands.push(Command::Focus(new_node.id));
+            commands.push(Command::Layout {
+                con_id: None,
+                direction: SplitDirection::Vertical,
+            });
+            false
+        }
+        None => parent.layout == NodeLayout::SplitH,
+    };
+
+    trace!(
+        "Resizing {}",
+        if resize_horizontal {
+            "horizontally"
+        } else {
+            "vertically"
+        }
+    );
+
+    let split_command = Command::Split {
+        con_id: None,
+        direction: if resize_horizontal {
+            SplitDirection::Vertical
+        } else {
+            SplitDirection::Horizontal
+        },
+    };
+
+    // Swapping comes before the splits, which then go to each window where it ends up, so the
+    // spiral continues from there. The new window keeps its ratio wherever it goes.
+    let (swap_with, reason) = match position {
+        Some(position) => {
+            let wanted = if position.is_first() {
+                parent.nodes.first()
+            } else {
+                parent.nodes.last()
+            };
+            (wanted, format!("its position {:?}", position))
+        }
+        None if settings.promote_new_to_master => {
+            (parent.nodes.first(), "promote_new_to_master".to_string())
+        }
+        None if settings.swap_new && parent.nodes.len() == 2 => (
+            parent.nodes.iter().find(|s| s.id != new_node.id),
+            "swap_new".to_string(),
+        ),
+        None => (
+            match settings.new_window_side {
+                NewWindowSide::Auto => None,
+                NewWindowSide::RightBottom => parent.nodes.last(),
+                NewWindowSide::LeftTop => parent.nodes.first(),
+            },
+            format!("new_window_side {:?}", settings.new_window_side),
+        ),
+    };
+    if let Some(other) = swap_with.filter(|w| w.id != new_node.id) {
+        info!(
+            "Swapping {:?} with {:?} for {}",
+            new_node.name, other.name, reason
+        );
+        commands.push(Command::Swap {
+            con_id: new_node.id,
+            with: other.id,
+        });
+    }
+
+    for child in &parent.nodes {
+        if forced.is_some() && child.id != new_node.id {
+            continue;
+        }
+        commands.push(Command::Focus(child.id));
+        commands.push(split_command.clone());
+    }
+
+    commands.push(Command::Focus(new_node.id));
+    let mut ratio = reference_ratio(tree, extras, parent, resize_horizontal, settings)
+        .or_else(|| remembered_ratio(tree, remembered, parent, settings))
+        .unwrap_or_else(|| {
+            let (ratio, setting) = ratio_with_setting(tree, parent, resize_horizontal, settings);
+            debug!("Using ratio {:.3} from {}", ratio, setting);
+            ratio
+        });
+    let rules = settings.matching_rules(&window_identity(new_node));
+    let ratio_rule = rules.iter().find_map(|rule| match rule.size {
+        Sizing::Ratio(ratio) => Some((rule, ratio)),
+        Sizing::Aspect(_) => None,
+    });
+    if let Some((rule, rule_ratio)) = ratio_rule {
+        info!(
+            "Using ratio {} for {:?} from {}",
+            rule_ratio, new_node.name, rule
+        );
+        ratio = rule_ratio;
+    }
+    // The new window ends up focused, so it starts out as the larger one.
+    if settings.mode == Mode::FocusMaster {
+        ratio = 1.0 - ratio;
+    }
+
+    // An aspect only applies when no more specific ratio rule came before it.
+    let aspect = rules.first().and_then(|rule| match rule.size {
+        Sizing::Aspect(aspect) => Some(aspect),
+        Sizing::Ratio(_) => None,
+    });
+    match aspect.and_then(|aspect| aspect_share(parent.rect, resize_horizontal, aspect)) {
+        Some(share) => {
+            info!(
+                "Sizing {:?} to its aspect, {:.2} of its parent",
+                new_node.name, share
+            );
+            commands.push(Command::Resize {
+                con_id: None,
+                horizontal: resize_horizontal,
+                amount: target_px(parent.rect, resize_horizontal, share),
+                unit: ResizeUnit::Px,
+            });
+        }
+        None => commands.extend(
+            resize_command(parent, current, resize_horizontal, ratio, settings)
+                .map(|resize| targeting(resize, new_node.id)),
+        ),
+    }
+
+    Ok(commands)
+}
+
+/// With `mode = remember`, the share of `parent` its last window had when it was last resized by
+/// hand. `None` in other modes, and for containers that haven't been.
+fn remembered_ratio(
+    tree: &Node,
+    remembered: &Remembered,
+    parent: &Node,
+    settings: &Settings,
+) -> Option<f64> {
+    if settings.mode != Mode::Remember {
+        return None;
+    }
+
+    let path = container_path(parent.id, tree)?;
+    match remembered.get(&path) {
+        Some(&ratio) => {
+            info!("Using the remembered ratio {:.2} for {}", ratio, path);
+            Some(ratio)
+        }
+        None => {
+            trace!("No remembered ratio for {}", path);
+            None
+        }
+    }
+}
+
+/// With `mode = match_reference`, the share of `parent` that makes a new window as large as the
+/// window marked `reference_mark` along the split axis. `None` in other modes, without a marked
+/// window, or when the reference wouldn't leave room for a sibling.
+fn reference_ratio(
+    tree: &Node,
+    extras: &Extras,
+    parent: &Node,
+    resize_horizontal: bool,
+    settings: &Settings,
+) -> Option<f64> {
+    if settings.mode != Mode::MatchReference {
+        return None;
+    }
+
+    let reference = extras
+        .iter()
+        .find(|(_, e)| e.marks.contains(&settings.reference_mark))
+        .and_then(|(&id, _)| find_node(id, tree));
+    let reference = match reference {
+        Some(reference) => reference,
+        None => {
+            trace!("No window marked {:?}", settings.reference_mark);
+            return None;
+        }
+    };
+
+    let ratio = if resize_horizontal {
+        f64::from(reference.rect.2) / f64::from(parent.rect.2)
+    } else {
+        f64::from(reference.rect.3) / f64::from(parent.rect.3)
+    };
+    if !(ratio > 0.0 && ratio < 1.0) {
+        info!(
+            "Reference {:?} doesn't fit in {:?}, using the ratio",
+            reference.name, parent.rect
+        );
+        return None;
+    }
+
+    info!(
+        "Sizing like {:?}, {:.2} of the parent",
+        reference.name, ratio
+    );
+    Some(ratio)
+}
+
+/// The share of a parent with the given rect that a child needs along the split axis to have the
+/// given width / height, keeping the parent's size along the other axis. `None` when that share
+/// doesn't fit in the parent.
+fn aspect_share(
+    parent_rect: (i32, i32, i32, i32),
+    resize_horizontal: bool,
+    aspect: f64,
+) -> Option<f64> {
+    let (width, height) = (f64::from(parent_rect.2), f64::from(parent_rect.3));
+    let share = if resize_horizontal {
+        height * aspect / width
+    } else {
+        width / aspect / height
+    };
+
+    if share.is_finite() && share > 0.0 && share < 1.0 {
+        Some(share)
+    } else {
+        info!(
+            "An aspect of {:.2} doesn't fit in {:?}",
+            aspect, parent_rect
+        );
+        None
+    }
+}
+
+/// How to switch a tabbed or stacked container to a split, and how to put it back if that
+/// doesn't work.
+#[derive(Debug)]
+pub struct Conversion {
+    pub convert: Command,
+    pub revert: Command,
+}
+
+/// Decides whether the tabbed or stacked container `new_node` landed in should be switched to a
+/// split layout, with `manage_tabbed = convert`. The container splits along its longer side.
+/// Both commands target the new window, since i3 changes the layout of the targeted container's
+/// parent; that also covers the implicit container i3 creates for tabbed workspaces.
+pub fn plan_convert(tree: &Node, new_node: &Node, settings: &Settings) -> Option<Conversion> {
+    if settings.manage_tabbed != ManageTabbed::Convert {
+        return None;
+    }
+
+    let parent = find_parent(new_node.id, tree)?;
+    let layout = match parent.layout {
+        NodeLayout::Tabbed => OverflowLayout::Tabbed,
+        NodeLayout::Stacked => OverflowLayout::Stacked,
+        _ => return None,
+    };
+
+    let workspace_name = find_workspace(new_node.id, tree).and_then(|w| w.name.as_deref());
+    if workspace_name.is_some_and(|name| !settings.is_workspace_managed(name)) {
+        return None;
+    }
+
+    let (_, _, width, height) = parent.rect;
+    let direction = if width >= height {
+        SplitDirection::Horizontal
+    } else {
+        SplitDirection::Vertical
+    };
+
+    info!(
+        "Converting {:?} container {} to a {:?} split",
+        parent.layout, parent.id, direction
+    );
+    Some(Conversion {
+        convert: Command::Layout {
+            con_id: Some(new_node.id),
+            direction,
+        },
+        revert: Command::Overflow {
+            con_id: new_node.id,
+            layout,
+        },
+    })
+}
+
+/// Windows in a tab group carrying this mark, or the group itself, keep new windows in the group
+/// with `escape_tabbed`.
+const NO_ESCAPE_MARK: &str = "no_escape";
+
+/// Decides whether `new_node`, which opened in a tabbed or stacked group, should be moved out of
+/// it with `escape_tabbed`, and returns the move that takes it a step towards the side of the
+/// group. Moves go along the group's grandparent's split, so it may take a few to get past the
+/// other windows in the group. Groups that are whole workspaces are left alone.
+pub fn plan_escape(
+    tree: &Node,
+    extras: &Extras,
+    new_node: &Node,
+    settings: &Settings,
+) -> Option<Command> {
+    if !settings.escape_tabbed {
+        return None;
+    }
+
+    let group = find_parent(new_node.id, tree)?;
+    if group.nodetype != NodeType::Con
+        || !matches!(group.layout, NodeLayout::Tabbed | NodeLayout::Stacked)
+    {
+        return None;
+    }
+
+    let workspace_name = find_workspace(new_node.id, tree).and_then(|w| w.name.as_deref());
+    if workspace_name.is_some_and(|name| !settings.is_workspace_managed(name)) {
+        return None;
+    }
+    if has_mark(group, extras, NO_ESCAPE_MARK) {
+        info!(
+            "Group {} is marked {}, leaving {:?} in it",
+            group.id, NO_ESCAPE_MARK, new_node.name
+        );
+        return None;
+    }
+
+    let direction = split_direction(find_parent(group.id, tree)?)?;
+    info!(
+        "Moving {:?} out of {:?} group {}",
+        new_node.name, group.layout, group.id
+    );
+    Some(Command::Move {
+        con_id: new_node.id,
+        direction,
+    })
+}
+
+/// Decides whether `new_node` should be moved off its workspace because the workspace already
+/// held `max_windows` tiled windows. Returns the number of the lowest-numbered empty workspace to
+/// move it to.
+pub fn plan_overflow_move(tree: &Node, new_node: &Node, settings: &Settings) -> Option<i32> {
+    if settings.overflow != OverflowPolicy::Move || settings.max_windows == 0 {
+        return None;
+    }
+
+    let workspace = find_workspace(new_node.id, tree)?;
+    let count = tiled_windows(workspace);
+    if count <= settings.max_windows {
+        return None;
+    }
+
+    let occupied: Vec<i32> = workspaces(tree)
+        .into_iter()
+        .filter(|w| !w.nodes.is_empty() || !w.floating_nodes.is_empty())
+        .filter_map(|w| w.name.as_deref().and_then(workspace_number))
+        .collect();
+    let destination = (1..).find(|number| !occupied.contains(number))?;
+
+    info!(
+        "Workspace {:?} already held {} windows, moving {:?} to workspace {}",
+        workspace.name,
+        count - 1,
+        new_node.name,
+        destination
+    );
+    Some(destination)
+}
+
+/// Decides how to repair a container after a window ratiosplit just handled left it, usually
+/// because a `for_window` rule floated or moved it. The windows that were split for it get their
+/// parent's direction back, and whatever children remain in the parent share it equally.
+pub fn plan_repair(tree: &Node, before: &Snapshot, settings: &Settings) -> Option<Vec<Command>> {
+    let parent = find_node(before.parent, tree).filter(|p| is_split(p))?;
+
+    let mut commands = restore_directions(tree, before);
+
+    if parent.nodes.len() > 1 {
+        let resize_horizontal = parent.layout == NodeLayout::SplitH;
+        let share = 1.0 / parent.nodes.len() as f64;
+        for child in &parent.nodes {
+            commands.push(Command::Focus(child.id));
+            commands.extend(
+                resize_command(parent, child, resize_horizontal, share, settings)
+                    .map(|resize| targeting(resize, child.id)),
+            );
+        }
+    }
+
+    if commands.is_empty() {
+        return None;
+    }
+
+    info!(
+        "Repairing container {} with {} children",
+        parent.id,
+        parent.nodes.len()
+    );
+    Some(commands)
+}
+
+/// A container's layout and its children's sizes as they were before ratiosplit changed them.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub parent: i64,
+    pub direction: SplitDirection,
+    /// Each child's share of the parent, in order.
+    pub percents: Vec<(i64, f64)>,
+    /// Containers that were split, and so need their parent's direction back.
+    pub split: Vec<i64>,
+}
+
+/// Records the given split container's current state, before `split` are split.
+pub fn snapshot(parent: &Node, split: Vec<i64>) -> Option<Snapshot> {
+    Some(Snapshot {
+        parent: parent.id,
+        direction: split_direction(parent)?,
+        percents: parent
+            .nodes
+            .iter()
+            .map(|child| (child.id, effective_percent(child, parent)))
+            .collect(),
+        split,
+    })
+}
+
+/// Decides which commands put a container back the way the snapshot recorded it: split
+/// containers get the parent's direction back, and children return to their recorded sizes.
+/// Returns `None` when the container or any of its recorded children are gone, since the sizes
+/// no longer mean anything then.
+pub fn plan_restore(tree: &Node, snapshot: &Snapshot, settings: &Settings) -> Option<Vec<Command>> {
+    let parent = find_node(snapshot.parent, tree).filter(|p| is_split(p))?;
+    let children: Vec<i64> = parent.nodes.iter().map(|child| child.id).collect();
+    if snapshot
+        .percents
+        .iter()
+        .any(|(id, _)| !children.contains(id))
+    {
+        info!("Children of {} have changed, cannot restore it", parent.id);
+        return None;
+    }
+
+    let mut commands = restore_directions(tree, snapshot);
+
+    let resize_horizontal = snapshot.direction == SplitDirection::Horizontal;
+    // Resizing a child takes space from its neighbours, so the last one ends up with whatever is
+    // left, which is its recorded size once all the others have theirs.
+    for &(id, percent) in snapshot.percents.iter().rev().skip(1).rev() {
+        if let Some(child) = parent.nodes.iter().find(|child| child.id == id) {
+            commands.push(Command::Focus(id));
+            commands.extend(resize_command(
+                parent,
+                child,
+                resize_horizontal,
+                percent,
+                settings,
+            ));
+        }
+    }
+
+    if let Some(focused) = find_focused(tree) {
+        commands.push(Command::Focus(focused.id));
+    }
+
+    Some(commands)
+}
+
+/// Gives the containers split for a window, that still exist, their parent's direction back.
+fn restore_directions(tree: &Node, snapshot: &Snapshot) -> Vec<Command> {
+    snapshot
+        .split
+        .iter()
+        .filter(|&&id| find_node(id, tree).is_some())
+        .map(|&id| Command::Layout {
+            con_id: Some(id),
+            direction: snapshot.direction,
+        })
+        .collect()
+}
+
+/// How a new window's size compares to what it was resized to.
+#[derive(Debug)]
+pub enum Verification {
+    Matches,
+    /// The window or its container has closed, or moved elsewhere.
+    Gone,
+    /// The window is off by more than the drift tolerance; `retry` resizes it again in pixels.
+    Mismatch {
+        actual: f64,
+        retry: Command,
+    },
+}
+
+/// Checks whether the child of `parent_id` holding `node_id` takes up `ratio` of it along the
+/// given axis. The window itself may have been wrapped in a new container by a split, so its
+/// share is measured through whichever child of the parent contains it.
+pub fn verify_resize(
+    tree: &Node,
+    parent_id: i64,
+    node_id: i64,
+    resize_horizontal: bool,
+    ratio: f64,
+) -> Verification {
+    let parent = match find_node(parent_id, tree) {
+        Some(p) => p,
+        None => return Verification::Gone,
+    };
+    let child = match parent
+        .nodes
+        .iter()
+        .find(|child| find_node(node_id, child).is_some())
+    {
+        Some(c) => c,
+        None => return Verification::Gone,
+    };
+
+    let actual = effective_percent(child, parent);
+    if (actual - ratio).abs() <= DRIFT_TOLERANCE {
+        return Verification::Matches;
+    }
+
+    Verification::Mismatch {
+        actual,
+        retry: Command::Resize {
+            con_id: Some(node_id),
+            horizontal: resize_horizontal,
+            amount: target_px(parent.rect, resize_horizontal, ratio),
+            unit: ResizeUnit::Px,
+        },
+    }
+}
+
+/// Decides whether a container that was switched to `overflow_layout` should go back to the split
+/// direction it had before, now that windows have closed. Containers the user has since changed
+/// to another layout are left alone.
+pub fn plan_overflow_restore(
+    container: &Node,
+    direction: SplitDirection,
+    settings: &Settings,
+) -> Option<Vec<Command>> {
+    if !settings.overflow_restore
+        || !matches!(container.layout, NodeLayout::Stacked | NodeLayout::Tabbed)
+        || container.nodes.len() >= settings.overflow_threshold
+    {
+        return None;
+    }
+
+    info!(
+        "Container {} is down to {} children, restoring {:?} split",
+        container.id,
+        container.nodes.len(),
+        direction
+    );
+    Some(vec![Command::Layout {
+        con_id: Some(container.nodes.first()?.id),
+        direction,
+    }])
+}
+
+/// Decides which commands restore the configured ratio between `node` and its sibling, without
+/// changing any split directions. The sibling closest to the ratio is treated as the managed
+/// pane, so this works regardless of which of the two was created last, and regardless of
+/// whether the ratio makes the managed pane the smaller or the larger one.
+pub fn plan_rebalance(tree: &Node, node: &Node, settings: &Settings) -> Option<Vec<Command>> {
+    let parent = find_managed_parent(tree, node, settings)?;
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+
+    let ratio = effective_ratio(tree, parent, resize_horizontal, settings);
+    let managed = managed_child(parent, ratio)?;
+
+    trace!("Rebalancing {:?} in parent {}", managed.name, parent.id);
+
+    let mut commands = vec![Command::Focus(managed.id)];
+    commands.extend(resize_command(
+        parent,
+        managed,
+        resize_horizontal,
+        ratio,
+        settings,
+    ));
+    commands.push(Command::Focus(node.id));
+    Some(commands)
+}
+
+/// Decides how to resize `node`, which has just been focused, so that it takes up `1 - ratio` of
+/// its container, with `mode = focus_master`. The resize targets the node directly, so it can't
+/// change focus and set off another focus event; relative resizes have no criteria, and rely on
+/// the node still being focused. Returns `None` when it's already at that size.
+pub fn plan_focus_master(tree: &Node, node: &Node, settings: &Settings) -> Option<Command> {
+    let parent = find_managed_parent(tree, node, settings)?;
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    let ratio = 1.0 - effective_ratio(tree, parent, resize_horizontal, settings);
+
+    if (effective_percent(node, parent) - ratio).abs() <= DRIFT_TOLERANCE {
+        trace!("{:?} is already the larger window", node.name);
+        return None;
+    }
+
+    resize_command(parent, node, resize_horizontal, ratio, settings)
+        .map(|resize| targeting(resize, node.id))
+}
+
+/// Decides how to swap `node` with the largest tiled window on its workspace, making it the
+/// master. The windows trade places but not sizes, so each parent keeps its ratio. Returns `None`
+/// when `node` already is, or holds, the largest window.
+pub fn plan_promote(tree: &Node, node: &Node) -> Option<Command> {
+    let workspace = find_workspace(node.id, tree)?;
+    let area = |n: &Node| i64::from(n.rect.2) * i64::from(n.rect.3);
+    let largest = tiled_leaves(workspace)
+        .into_iter()
+        .max_by_key(|w| area(w))?;
+
+    if find_node(largest.id, node).is_some() || area(largest) <= area(node) {
+        trace!("{:?} is already the largest window", node.name);
+        return None;
+    }
+
+    info!("Promoting {:?} over {:?}", node.name, largest.name);
+    Some(Command::Swap {
+        con_id: node.id,
+        with: largest.id,
+    })
+}
+
+/// Decides how to resize `node`, a window handled a moment ago in `parent_id`, now that its title
+/// changed to one with a `[title_ratios]` ratio. The window may have been wrapped in a container
+/// of its own by a split since, in which case that container is resized. Returns `None` when the
+/// title has no ratio, or the window is already at it.
+pub fn plan_title_ratio(
+    tree: &Node,
+    parent_id: i64,
+    node: &Node,
+    settings: &Settings,
+) -> Option<Command> {
+    let ratio = settings.title_ratio(node.name.as_deref()?)?;
+    let parent = find_node(parent_id, tree).filter(|p| is_split(p) && has_two_children(p))?;
+    let child = parent
+        .nodes
+        .iter()
+        .find(|child| find_node(node.id, child).is_some())?;
+
+    if (effective_percent(child, parent) - ratio).abs() <= DRIFT_TOLERANCE {
+        trace!("{:?} is already at {:.2}", node.name, ratio);
+        return None;
+    }
+
+    info!("Title of {:?} gives it ratio {}", node.name, ratio);
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    resize_command(parent, child, resize_horizontal, ratio, settings)
+        .map(|resize| targeting(resize, child.id))
+}
+
+/// Decides how to resize the sibling of `target` instead, after i3 refused `resize` for it: to
+/// whatever of their parent `resize` would have left it. Only works for two-child parents.
+pub fn plan_sibling_resize(tree: &Node, target: i64, resize: &Command) -> Option<Vec<Command>> {
+    let parent = find_parent(target, tree).filter(|p| p.nodes.len() == 2)?;
+    let sibling = parent.nodes.iter().find(|child| child.id != target)?;
+
+    let commands = match *resize {
+        Command::Resize {
+            horizontal,
+            amount,
+            unit,
+            ..
+        } => {
+            let total = match unit {
+                ResizeUnit::Ppt => 100,
+                ResizeUnit::Px if horizontal => parent.rect.2,
+                ResizeUnit::Px => parent.rect.3,
+            };
+            vec![Command::Resize {
+                con_id: Some(sibling.id),
+                horizontal,
+                amount: total - amount,
+                unit,
+            }]
+        }
+        Command::ResizeBy {
+            horizontal,
+            ppt,
+            px,
+            ..
+        } => vec![Command::ResizeBy {
+            con_id: Some(sibling.id),
+            horizontal,
+            ppt: -ppt,
+            px: -px,
+        }],
+        _ => return None,
+    };
+    Some(commands)
+}
+
+/// Renders a hook, `pre_split_command` or `post_resize_command`, for `node`.
+pub fn plan_hook(template: Option<&str>, tree: &Node, node: &Node) -> Vec<Command> {
+    let template = match template {
+        Some(template) => template,
+        None => return Vec::new(),
+    };
+
+    render_template(template, |name| match name {
+        "con_id" => Some(node.id.to_string()),
+        "class" => window_class(node).map(String::from),
+        "workspace" => find_workspace(node.id, tree).and_then(|w| w.name.clone()),
+        _ => None,
+    })
+}
+
+/// Decides which commands share a multi-child parent's space equally among its children. Children
+/// carrying the `anchor_mark` (or containing a window that does) keep their current size, and the
+/// rest share whatever space is left.
+fn plan_equalize(
+    parent: &Node,
+    extras: &Extras,
+    node: &Node,
+    settings: &Settings,
+) -> Option<Vec<Command>> {
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    let mut pinned = Vec::new();
+    let mut free = Vec::new();
+    for child in &parent.nodes {
+        match locked_share(child, extras, settings) {
+            Some(share) => pinned.push((child, share)),
+            None if is_anchored(child, extras, settings) => {
+                pinned.push((child, effective_percent(child, parent)))
+            }
+            None => free.push(child),
+        }
+    }
+
+    if free.is_empty() {
+        info!(
+            "Every child of {} is anchored or locked, nothing to equalize",
+            parent.id
+        );
+        return None;
+    }
+
+    let pinned_total: f64 = pinned.iter().map(|(_, share)| share).sum();
+    let share = (1.0 - pinned_total).max(0.0) / free.len() as f64;
+
+    trace!(
+        "Equalizing {} children of {} at {:.3}, {} anchored or locked at {:.3} total",
+        free.len(),
+        parent.id,
+        share,
+        pinned.len(),
+        pinned_total
+    );
+
+    // Resizing a child takes space from its neighbours, so the anchors are put back last.
+    let mut sized: Vec<(&Node, f64)> = free.into_iter().map(|child| (child, share)).collect();
+    sized.extend(pinned);
+    if settings.resize_method == ResizeMethod::Set && settings.resize_unit == ResizeUnit::Ppt {
+        let shares: Vec<f64> = sized.iter().map(|&(_, share)| share).collect();
+        for ((_, share), ppt) in sized
+            .iter_mut()
+            .zip(shares_to_ppt(&shares, settings.rounding))
+        {
+            *share = f64::from(ppt) / 100.0;
+        }
+    }
+
+    // Children are resized by id as well as focused: focusing a tabbed or stacked group can leave
+    // one of its tabs focused, and resizing that would size the group by the wrong container.
+    let mut commands = Vec::new();
+    for (child, share) in sized {
+        commands.push(Command::Focus(child.id));
+        commands.extend(
+            resize_command(parent, child, resize_horizontal, share, settings)
+                .map(|resize| targeting(resize, child.id)),
+        );
+    }
+
+    commands.push(Command::Focus(node.id));
+    Some(commands)
+}
+
+/// Decides which commands give the first child of `parent` the master pane, `1 - ratio` of it,
+/// with the other children sharing the rest equally. Split directions are left alone.
+fn plan_master(parent: &Node, node: &Node, settings: &Settings) -> Option<Vec<Command>> {
+    let (master, stack) = parent.nodes.split_first()?;
+    if stack.is_empty() {
+        return None;
+    }
+
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    let ratio = if resize_horizontal {
+        settings.ratio
+    } else {
+        settings.ratio_vertical
+    };
+    let share = ratio / stack.len() as f64;
+
+    trace!(
+        "Giving master {} {:.3} of {}, and {} others {:.3} each",
+        master.id,
+        1.0 - ratio,
+        parent.id,
+        stack.len(),
+        share
+    );
+
+    let mut commands = Vec::new();
+    // The last stack child ends up with whatever is left, which is its share once the others
+    // have theirs.
+    for child in stack.iter().rev().skip(1) {
+        commands.push(Command::Focus(child.id));
+        commands.extend(resize_command(
+            parent,
+            child,
+            resize_horizontal,
+            share,
+            settings,
+        ));
+    }
+    commands.push(Command::Focus(master.id));
+    commands.extend(resize_command(
+        parent,
+        master,
+        resize_horizontal,
+        1.0 - ratio,
+        settings,
+    ));
+    commands.push(Command::Focus(node.id));
+    Some(commands)
+}
+
+/// The share of its parent a container is locked at with a `ratio_lock:<percent>` mark, with
+/// `ratio_locks`. Percents outside 1 to 99 are ignored, with a warning.
+fn locked_share(node: &Node, extras: &Extras, settings: &Settings) -> Option<f64> {
+    if !settings.ratio_locks {
+        return None;
+    }
+    let mark = extras
+        .get(&node.id)?
+        .marks
+        .iter()
+        .find(|mark| mark.starts_with(RATIO_LOCK_PREFIX))?;
+    match mark[RATIO_LOCK_PREFIX.len()..].parse::<f64>() {
+        Ok(percent) if (1.0..=99.0).contains(&percent) => Some(percent / 100.0),
+        _ => {
+            warn!(
+                "Ignoring {:?}, locks must be between 1 and 99 percent",
+                mark
+            );
+            None
+        }
+    }
+}
+
+fn has_locked_child(parent: &Node, extras: &Extras, settings: &Settings) -> bool {
+    parent
+        .nodes
+        .iter()
+        .any(|child| locked_share(child, extras, settings).is_some())
+}
+
+/// Puts the locked containers on the workspace that have drifted back to their locked shares,
+/// sharing out the rest of their parents equally among their siblings. Ends with focus on
+/// `focused`.
+pub fn plan_locks(
+    workspace: &Node,
+    extras: &Extras,
+    focused: &Node,
+    settings: &Settings,
+) -> Vec<Command> {
+    let mut commands = Vec::new();
+    plan_container(workspace, extras, focused, settings, &mut commands);
+    return commands;
+
+    fn plan_container(
+        node: &Node,
+        extras: &Extras,
+        focused: &Node,
+        settings: &Settings,
+        commands: &mut Vec<Command>,
+    ) {
+        let drifted = is_split(node)
+            && node.nodes.iter().any(|child| {
+                locked_share(child, extras, settings).is_some_and(|share| {
+                    (effective_percent(child, node) - share).abs() > DRIFT_TOLERANCE
+                })
+            });
+        if drifted {
+            info!("Putting the locked children of {} back", node.id);
+            commands.extend(plan_equalize(node, extras, focused, settings).unwrap_or_default());
+        }
+
+        for child in &node.nodes {
+            plan_container(child, extras, focused, settings, commands);
+        }
+    }
+}
+
+/// Whether the node, or any window inside it, carries the configured anchor mark.
+fn is_anchored(node: &Node, extras: &Extras, settings: &Settings) -> bool {
+    match &settings.anchor_mark {
+        Some(anchor_mark) => has_mark(node, extras, anchor_mark),
+        None => false,
+    }
+}
+
+/// Whether the node, or any node under it, carries the given mark.
+fn has_mark(node: &Node, extras: &Extras, mark: &str) -> bool {
+    extras
+        .get(&node.id)
+        .is_some_and(|e| e.marks.iter().any(|m| m == mark))
+        || node.nodes.iter().any(|child| has_mark(child, extras, mark))
+}
+
+/// The prefix of the marks that lock a container at a share of its parent, like `ratio_lock:40`.
+pub const RATIO_LOCK_PREFIX: &str = "ratio_lock:";
+
+/// A difference between a container's actual layout and what the planner would have produced,
+/// along with the commands that would fix it.
+#[derive(Debug)]
+pub struct Adjustment {
+    pub container: i64,
+    pub description: String,
+    pub commands: Vec<Command>,
+}
+
+/// Walks a workspace and compares every two-child split container against the layout the
+/// planner produces: the managed child at the configured ratio, and nested split containers
+/// alternating direction from their parent. Returns what would need to change.
+pub fn plan_workspace(
+    tree: &Node,
+    extras: &Extras,
+    workspace: &Node,
+    settings: &Settings,
+) -> Vec<Adjustment> {
+    let mut adjustments = Vec::new();
+    // Drift is measured against the spiral, which the other modes don't follow.
+    if !matches!(settings.mode, Mode::Spiral | Mode::Dynamic) {
+        return adjustments;
+    }
+    plan_container(tree, extras, workspace, settings, &mut adjustments);
+    return adjustments;
+
+    fn plan_container(
+        tree: &Node,
+        extras: &Extras,
+        node: &Node,
+        settings: &Settings,
+        adjustments: &mut Vec<Adjustment>,
+    ) {
+        // Containers with a locked child are sized by `plan_locks` instead.
+        if is_split(node) && node.nodes.len() == 2 && !has_locked_child(node, extras, settings) {
+            let resize_horizontal = node.layout == NodeLayout::SplitH;
+            let ratio = effective_ratio(tree, node, resize_horizontal, settings);
+
+            if let Some(managed) = managed_child(node, ratio) {
+                let percent = effective_percent(managed, node);
+                if (percent - ratio).abs() > DRIFT_TOLERANCE {
+                    adjustments.push(Adjustment {
+                        container: node.id,
+                        description: format!(
+                            "child {} is at {:.2}, expected {:.2}",
+                            managed.id, percent, ratio
+                        ),
+                        commands: std::iter::once(Command::Focus(managed.id))
+                            .chain(resize_command(
+                                node,
+                                managed,
+                                resize_horizontal,
+                                ratio,
+                                settings,
+                            ))
+                            .collect(),
+                    });
+                }
+            }
+
+            for child in &node.nodes {
+                let grandchild = match child.nodes.first() {
+                    Some(g) => g,
+                    None => continue,
+                };
+                if is_split(child) && child.layout == node.layout {
+                    adjustments.push(Adjustment {
+                        container: child.id,
+                        description: format!(
+                            "splits {:?} like its parent {}",
+                            child.layout, node.id
+                        ),
+                        commands: vec![Command::Layout {
+                            con_id: Some(grandchild.id),
+                            direction: if resize_horizontal {
+                                SplitDirection::Vertical
+                            } else {
+                                SplitDirection::Horizontal
+                            },
+                        }],
+                    });
+                }
+            }
+        }
+
+        for child in &node.nodes {
+            plan_container(tree, extras, child, settings, adjustments);
+        }
+    }
+}
+
+/// Decides whether a window leaving fullscreen should have its container's ratio re-applied.
+/// Entering fullscreen never needs any work.
+pub fn plan_fullscreen_change(
+    tree: &Node,
+    extras: &Extras,
+    node: &Node,
+    settings: &Settings,
+) -> Option<Vec<Command>> {
+    if !settings.rebalance_on_fullscreen_exit {
+        return None;
+    }
+
+    if is_fullscreen(node.id, extras) {
+        trace!("{:?} entered fullscreen, nothing to do", node.name);
+        return None;
+    }
+
+    info!("{:?} left fullscreen, rebalancing", node.name);
+    plan_rebalance(tree, node, settings)
+}
+
+/// The ratio to apply when resizing a child of `parent` along the given axis. Resizes along an
+/// output's long axis use `ratio`, unless it is `auto`, in which case it's derived from the shape
+/// of the output. Resizes along the short axis use `ratio_vertical`. On landscape outputs the long
+/// axis is horizontal; on portrait outputs it's vertical, unless `portrait_aware` is off.
+pub fn effective_ratio(
+    tree: &Node,
+    parent: &Node,
+    resize_horizontal: bool,
+    settings: &Settings,
+) -> f64 {
+    ratio_with_setting(tree, parent, resize_horizontal, settings).0
+}
+
+/// `effective_ratio`, along with the setting it came from.
+fn ratio_with_setting(
+    tree: &Node,
+    parent: &Node,
+    resize_horizontal: bool,
+    settings: &Settings,
+) -> (f64, &'static str) {
+    let output = find_output(parent.id, tree);
+    let portrait = settings.portrait_aware && output.is_some_and(|o| o.rect.3 > o.rect.2);
+
+    if resize_horizontal == portrait {
+        return (settings.ratio_vertical, "ratio_vertical");
+    }
+
+    if !settings.auto_ratio {
+        return (settings.ratio, "ratio");
+    }
+
+    match output {
+        Some(Node {
+            rect: (x, y, width, height),
+            ..
+        }) if portrait => (
+            auto_ratio((*x, *y, *height, *width), settings.target_aspect),
+            "ratio = auto",
+        ),
+        Some(output) => (
+            auto_ratio(output.rect, settings.target_aspect),
+            "ratio = auto",
+        ),
+        None => (settings.ratio, "ratio"),
+    }
+}
+
+/// Computes the horizontal ratio that leaves the larger pane with the given aspect ratio
+/// (width / height) on an output of the given size. Clamped so that the new window is never
+/// uselessly thin, and never becomes the larger pane.
+fn auto_ratio(output_rect: (i32, i32, i32, i32), target_aspect: f64) -> f64 {
+    let (_, _, width, height) = output_rect;
+    if width <= 0 || height <= 0 {
+        return AUTO_RATIO_MAX;
+    }
+
+    let larger_width = f64::from(height) * target_aspect;
+    (1.0 - larger_width / f64::from(width)).clamp(AUTO_RATIO_MIN, AUTO_RATIO_MAX)
+}
+
+/// Whether the node covers nearly all of its parent, leaving its siblings effectively invisible.
+/// Apps that open at the size of the output without going fullscreen end up like this.
+fn fills_parent(node: &Node, parent: &Node) -> bool {
+    let covers = |size: i32, parent_size: i32| {
+        parent_size > 0 && f64::from(size) >= f64::from(parent_size) * FILL_TOLERANCE
+    };
+    covers(node.rect.2, parent.rect.2) && covers(node.rect.3, parent.rect.3)
+}
+
+/// The child of a two-child parent that holds the configured ratio: whichever is closest to it.
+fn managed_child(parent: &Node, ratio: f64) -> Option<&Node> {
+    parent.nodes.iter().min_by(|a, b| {
+        let distance = |n: &Node| (effective_percent(n, parent) - ratio).abs();
+        distance(a).partial_cmp(&distance(b)).unwrap()
+    })
+}
+
+fn is_split(node: &Node) -> bool {
+    matches!(node.nodetype, NodeType::Con | NodeType::Workspace)
+        && matches!(node.layout, NodeLayout::SplitH | NodeLayout::SplitV)
+}
+
+/// Finds the parent of `node`, provided that it is a split container with exactly two children
+/// on a managed workspace; the only configuration ratiosplit knows how to resize.
+fn find_managed_parent<'a>(tree: &'a Node, node: &Node, settings: &Settings) -> Option<&'a Node> {
+    find_split_parent(tree, node, settings)
+        .ok()
+        .filter(|parent| has_two_children(parent))
+}
+
+/// Finds the parent of `node`, provided that it is a split container on a managed workspace.
+fn find_split_parent<'a>(
+    tree: &'a Node,
+    node: &Node,
+    settings: &Settings,
+) -> Result<&'a Node, SkipReason> {
+    let workspace_name = find_workspace(node.id, tree).and_then(|w| w.name.as_deref());
+    match workspace_name {
+        Some(name) if !settings.is_workspace_managed(name) => {
+            info!("Workspace {:?} is not managed, skipping", name);
+            return Err(SkipReason::Unmanaged);
+        }
+        Some(_) => {}
+        None => trace!("Could not find workspace for {:?}", node.name),
+    }
+
+    let parent = match find_parent(node.id, tree) {
+        Some(p) => p,
+        None => {
+            info!("Could not find parent node for {:?}.", node.name);
+            trace!("Tree: {:?}", tree);
+            return Err(SkipReason::NoParent);
+        }
+    };
+
+    trace!("Found parent node for {:?}", node.name);
+
+    // If the parent is not a container or is not a splitv/h, there's nothing to resize
+    if !matches!(
+        parent,
+        Node {
+            nodetype: NodeType::Con,
+            layout: NodeLayout::SplitH,
+            ..
+        } | Node {
+            nodetype: NodeType::Con,
+            layout: NodeLayout::SplitV,
+            ..
+        } | Node {
+            nodetype: NodeType::Workspace,
+            layout: NodeLayout::SplitH,
+            ..
+        } | Node {
+            nodetype: NodeType::Workspace,
+            layout: NodeLayout::SplitV,
+            ..
+        }
+    ) {
+        info!("Parent node is type {:?}, not resizing", parent.nodetype);
+        trace!("Parent properties: {:?}", parent);
+        return Err(SkipReason::NotSplit);
+    }
+
+    Ok(parent)
+}
+
+fn has_two_children(parent: &Node) -> bool {
+    // If there are not 2 children in this node, we can't resize one for golden mode,
+    // and would likely just annoy people if we did. Skip.
+    if parent.nodes.len() != 2 {
+        info!("Parent node has {} children, skipping", parent.nodes.len());
+        trace!("Parent properties: {:?}", parent);
+        return false;
+    }
+
+    true
+}
+
+/// The command that resizes `child` to take `ratio` of `parent` along the given axis. With
+/// `resize_method = relative` this grows or shrinks the child by the difference from its current
+/// size, and returns `None` when there is no difference.
+fn resize_command(
+    parent: &Node,
+    child: &Node,
+    resize_horizontal: bool,
+    ratio: f64,
+    settings: &Settings,
+) -> Option<Command> {
+    if settings.resize_method == ResizeMethod::Relative {
+        let (ppt, px) = resize_delta(
+            parent.rect,
+            effective_percent(child, parent),
+            resize_horizontal,
+            ratio,
+        );
+        if ppt == 0 && px == 0 {
+            trace!("{} is already at {:.2}, not resizing", child.id, ratio);
+            return None;
+        }
+
+        return Some(Command::ResizeBy {
+            con_id: None,
+            horizontal: resize_horizontal,
+            ppt,
+            px,
+        });
+    }
+
+    let amount = match settings.resize_unit {
+        ResizeUnit::Ppt => ratio_to_ppt(ratio, settings.rounding),
+        ResizeUnit::Px => target_px(parent.rect, resize_horizontal, ratio),
+    };
+
+    Some(Command::Resize {
+        con_id: None,
+        horizontal: resize_horizontal,
+        amount,
+        unit: settings.resize_unit,
+    })
+}
+
+/// Converts a ratio to the whole ppt i3 resizes by, rounded as configured. The result is kept
+/// within 1 to 99, since i3 refuses 0 and 100 would leave nothing of the sibling.
+pub fn ratio_to_ppt(ratio: f64, rounding: Rounding) -> i32 {
+    // Ratios like 0.615 are a hair under what they say in binary, so nudge them before rounding
+    // to get the ppt that was written.
+    let ppt = ratio * 100.0;
+    let rounded = match rounding {
+        Rounding::Nearest => (ppt + 0.5 + PPT_EPSILON).floor(),
+        Rounding::Floor => (ppt + PPT_EPSILON).floor(),
+        Rounding::Ceil => (ppt - PPT_EPSILON).ceil(),
+    };
+
+    if !(1.0..=99.0).contains(&rounded) {
+        let clamped = rounded.clamp(1.0, 99.0);
+        warn!(
+            "A ratio of {} makes {} ppt, which i3 can't do, using {} ppt",
+            ratio, rounded, clamped
+        );
+        return clamped as i32;
+    }
+    rounded as i32
+}
+
+/// Rounds the shares of one container's children to whole ppt as configured, then moves the ones
+/// rounding moved furthest by a ppt each, so that together they still add up to what the shares
+/// did. Three thirds become 33, 33 and 34 with any `rounding`, rather than 99 or 102 in total.
+fn shares_to_ppt(shares: &[f64], rounding: Rounding) -> Vec<i32> {
+    let mut ppts: Vec<i32> = shares
+        .iter()
+        .map(|&share| ratio_to_ppt(share, rounding))
+        .collect();
+    if ppts.is_empty() {
+        return ppts;
+    }
+
+    let target = (shares.iter().sum::<f64>() * 100.0).round() as i32;
+    let mut diff = target - ppts.iter().sum::<i32>();
+    // How far rounding moved each share down, so the most rounded down get a ppt first.
+    let mut order: Vec<usize> = (0..ppts.len()).collect();
+    order.sort_by(|&a, &b| {
+        let error = |i: usize| shares[i] * 100.0 - f64::from(ppts[i]);
+        error(b).total_cmp(&error(a))
+    });
+    if diff < 0 {
+        order.reverse();
+    }
+    // Each share stays within 1 to 99 like a single ratio, so a share already at the bound is
+    // passed over; when every share is, whatever is left over is left over.
+    while diff != 0 {
+        let step = diff.signum();
+        let mut moved = false;
+        for &i in &order {
+            if diff == 0 {
+                break;
+            }
+            if !(1..=99).contains(&(ppts[i] + step)) {
+                continue;
+            }
+            ppts[i] += step;
+            diff -= step;
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+    ppts
+}
+
+/// Converts a `resize set` in px to ppt of the given parent.
+fn in_ppt(command: Command, parent: &Node, settings: &Settings) -> Command {
+    match command {
+        Command::Resize {
+            con_id,
+            horizontal,
+            amount,
+            unit: ResizeUnit::Px,
+        } => {
+            let total = if horizontal {
+                parent.rect.2
+            } else {
+                parent.rect.3
+            };
+            Command::Resize {
+                con_id,
+                horizontal,
+                amount: ratio_to_ppt(f64::from(amount) / f64::from(total), settings.rounding),
+                unit: ResizeUnit::Ppt,
+            }
+        }
+        other => other,
+    }
+}
+
+/// Points a resize at the container with the given id rather than the focused one.
+fn targeting(resize: Command, con_id: i64) -> Command {
+    match resize {
+        Command::Resize {
+            horizontal,
+            amount,
+            unit,
+            ..
+        } => Command::Resize {
+            con_id: Some(con_id),
+            horizontal,
+            amount,
+            unit,
+        },
+        Command::ResizeBy {
+            horizontal,
+            ppt,
+            px,
+            ..
+        } => Command::ResizeBy {
+            con_id: Some(con_id),
+            horizontal,
+            ppt,
+            px,
+        },
+        other => other,
+    }
+}
+
+/// The signed change, in ppt and in pixels, that takes a child from `percent` of the given
+/// parent rect to `ratio` of it.
+fn resize_delta(
+    parent_rect: (i32, i32, i32, i32),
+    percent: f64,
+    resize_horizontal: bool,
+    ratio: f64,
+) -> (i32, i32) {
+    let ppt = ((ratio - percent) * 100.0).round() as i32;
+    let px = target_px(parent_rect, resize_horizontal, ratio)
+        - target_px(parent_rect, resize_horizontal, percent);
+    (ppt, px)
+}
+
+/// Splits a resize of `node` into `settings.animate_steps` resizes, stepping evenly from its
+/// current size to the one `resize` sets. Each step targets the node directly, so the steps can
+/// run later without depending on focus. Returns `None` when there is nothing to animate.
+pub fn plan_animation(
+    tree: &Node,
+    node: &Node,
+    resize: &Command,
+    settings: &Settings,
+) -> Option<Vec<Command>> {
+    let (horizontal, target, unit) = match resize {
+        Command::Resize {
+            horizontal,
+            amount,
+            unit,
+            ..
+        } => (*horizontal, *amount, *unit),
+        _ => return None,
+    };
+
+    let node = find_node(node.id, tree)?;
+    let parent = find_parent(node.id, tree)?;
+    let current = match unit {
+        ResizeUnit::Ppt => (effective_percent(node, parent) * 100.0).round() as i32,
+        ResizeUnit::Px if horizontal => node.rect.2,
+        ResizeUnit::Px => node.rect.3,
+    };
+    if settings.animate_steps < 2 || current == target {
+        return None;
+    }
+
+    let steps = settings.animate_steps;
+    Some(
+        (1..=steps)
+            .map(|step| Command::Resize {
+                con_id: Some(node.id),
+                horizontal,
+                amount: current
+                    + ((target - current) as f64 * f64::from(step) / f64::from(steps)).round()
+                        as i32,
+                unit,
+            })
+            .collect(),
+    )
+}
+
+/// Computes the pixel size for a child taking `ratio` of the given parent rect. Rects in the
+/// tree are in the same coordinate space that `resize set ... px` expects: physical pixels on
+/// i3, which has no per-output scaling, and logical pixels on sway, where each output's scale
+/// is already applied to its rects. Using the parent's own rect, rather than anything derived
+/// from the output's mode, keeps the result correct on mixed-DPI setups.
+pub fn target_px(parent_rect: (i32, i32, i32, i32), resize_horizontal: bool, ratio: f64) -> i32 {
+    let parent_size = if resize_horizontal {
+        parent_rect.2
+    } else {
+        parent_rect.3
+    };
+    (f64::from(parent_size) * ratio).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i3ipc::reply::WindowProperty;
+
+    use crate::fixtures::{dock_bar, focus, output, root, split, tree, window, workspace};
+    use crate::settings::{default_settings, parse_settings_from_str, Format, Position};
+
+    fn never_change_focus(mode: Mode) -> Settings {
+        let mut settings = default_settings();
+        settings.never_change_focus = true;
+        settings.mode = mode;
+        settings
+    }
+
+    fn plan(tree: &Node, new_id: i64, settings: &Settings) -> Decision {
+        let new_node = find_node(new_id, tree).unwrap().clone();
+        plan_commands(
+            tree,
+            &Extras::new(),
+            &Remembered::new(),
+            &new_node,
+            Some(new_id),
+            settings,
+        )
+    }
+
+    fn assert_no_focus(decision: Decision) -> Vec<Command> {
+        let commands = decision.commands().expect("the window should be handled");
+        assert!(
+            !commands.iter().any(|c| matches!(c, Command::Focus(_))),
+            "{:?}",
+            commands
+        );
+        assert!(
+            !commands.iter().any(|c| c.to_string().contains("focus")),
+            "{:?}",
+            commands
+        );
+        commands
+    }
+
+    #[test]
+    fn never_change_focus_spiral_emits_no_focus() {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Old", (0, 0, 500, 500)),
+                window(101, "New", (500, 0, 500, 500)),
+            ],
+        );
+        focus(&mut tree, 101);
+
+        let commands = assert_no_focus(plan(&tree, 101, &never_change_focus(Mode::Spiral)));
+        assert!(commands.contains(&Command::Split {
+            con_id: Some(100),
+            direction: SplitDirection::Vertical,
+        }));
+        assert!(commands.contains(&Command::Split {
+            con_id: Some(101),
+            direction: SplitDirection::Vertical,
+        }));
+        assert!(commands.iter().any(|c| matches!(
+            c,
+            Command::Resize {
+                con_id: Some(101),
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn never_change_focus_equalize_emits_no_focus() {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![split(
+                20,
+                NodeLayout::SplitV,
+                (0, 0, 1000, 500),
+                vec![
+                    window(100, "A", (0, 0, 1000, 100)),
+                    window(101, "B", (0, 100, 1000, 100)),
+                    window(102, "C", (0, 200, 1000, 300)),
+                ],
+            )],
+        );
+        focus(&mut tree, 102);
+
+        let commands = assert_no_focus(plan(&tree, 102, &never_change_focus(Mode::Equalize)));
+        assert!(commands.iter().all(|c| match c {
+            Command::Resize { con_id, .. } | Command::ResizeBy { con_id, .. } => con_id.is_some(),
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn hold_focus_targets_commands_that_relied_on_focus() {
+        let commands = vec![
+            Command::Focus(7),
+            Command::Split {
+                con_id: None,
+                direction: SplitDirection::Horizontal,
+            },
+            Command::Resize {
+                con_id: None,
+                horizontal: true,
+                amount: 40,
+                unit: ResizeUnit::Ppt,
+            },
+            Command::Focus(8),
+        ];
+        assert_eq!(
+            hold_focus(commands, Some(8)),
+            Ok(vec![
+                Command::Split {
+                    con_id: Some(7),
+                    direction: SplitDirection::Horizontal,
+                },
+                Command::Resize {
+                    con_id: Some(7),
+                    horizontal: true,
+                    amount: 40,
+                    unit: ResizeUnit::Ppt,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn hold_focus_skips_what_always_moves_focus() {
+        assert_eq!(
+            hold_focus(vec![Command::Workspace("2".to_string())], Some(1)),
+            Err(SkipReason::WouldMoveFocus)
+        );
+        assert_eq!(
+            hold_focus(
+                vec![Command::MoveToWorkspace {
+                    con_id: 1,
+                    number: 2
+                }],
+                Some(1)
+            ),
+            Err(SkipReason::WouldMoveFocus)
+        );
+        let other = Command::MoveToWorkspace {
+            con_id: 3,
+            number: 2,
+        };
+        assert_eq!(hold_focus(vec![other.clone()], Some(1)), Ok(vec![other]));
+    }
+
+    #[test]
+    fn ratio_to_ppt_rounds_as_configured() {
+        assert_eq!(ratio_to_ppt(0.615, Rounding::Nearest), 62);
+        assert_eq!(ratio_to_ppt(0.615, Rounding::Floor), 61);
+        assert_eq!(ratio_to_ppt(0.611, Rounding::Ceil), 62);
+        assert_eq!(ratio_to_ppt(0.62, Rounding::Ceil), 62);
+        assert_eq!(ratio_to_ppt(0.0, Rounding::Nearest), 1);
+        assert_eq!(ratio_to_ppt(1.0, Rounding::Nearest), 99);
+    }
+
+    #[test]
+    fn thirds_add_up_to_100_with_any_rounding() {
+        let thirds = [1.0 / 3.0; 3];
+        for rounding in [Rounding::Nearest, Rounding::Floor, Rounding::Ceil] {
+            let mut ppts = shares_to_ppt(&thirds, rounding);
+            assert_eq!(ppts.iter().sum::<i32>(), 100, "{:?}", rounding);
+            ppts.sort_unstable();
+            assert_eq!(ppts, vec![33, 33, 34], "{:?}", rounding);
+        }
+    }
+
+    #[test]
+    fn shares_to_ppt_keeps_each_share_within_bounds() {
+        // Locked ratios adding up to more than the whole container.
+        let ppts = shares_to_ppt(&[1.2, 0.5], Rounding::Nearest);
+        assert_eq!(ppts, vec![99, 71]);
+
+        // Nothing can move, and the loop still ends.
+        assert_eq!(shares_to_ppt(&[1.0, 1.0], Rounding::Nearest), vec![99, 99]);
+        assert_eq!(shares_to_ppt(&[0.0, 0.0], Rounding::Nearest), vec![1, 1]);
+    }
+
+    fn marked(marks: &[(i64, &str)]) -> Extras {
+        let mut extras = Extras::new();
+        for &(id, mark) in marks {
+            extras.entry(id).or_default().marks.push(mark.to_string());
+        }
+        extras
+    }
+
+    fn ratio_locks(on: bool) -> Settings {
+        let mut settings = default_settings();
+        settings.ratio_locks = on;
+        settings
+    }
+
+    #[test]
+    fn locks_are_read_from_marks() {
+        let node = window(100, "A", (0, 0, 500, 500));
+        let settings = ratio_locks(true);
+        let share = |mark| locked_share(&node, &marked(&[(100, mark)]), &settings);
+        assert_eq!(share("ratio_lock:40"), Some(0.4));
+        assert_eq!(share("ratio_lock:0"), None);
+        assert_eq!(share("ratio_lock:100"), None);
+        assert_eq!(share("ratio_lock:lots"), None);
+        assert_eq!(share("other"), None);
+        assert_eq!(
+            locked_share(
+                &node,
+                &marked(&[(100, "ratio_lock:40")]),
+                &ratio_locks(false)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn drifted_locks_are_put_back() {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 500, 500)),
+                window(101, "B", (500, 0, 500, 500)),
+            ],
+        );
+        focus(&mut tree, 101);
+        let workspace = find_workspace(100, &tree).unwrap();
+        let focused = find_node(101, &tree).unwrap();
+
+        let commands = plan_locks(
+            workspace,
+            &marked(&[(100, "ratio_lock:30")]),
+            focused,
+            &ratio_locks(true),
+        );
+        assert!(
+            commands.contains(&Command::Resize {
+                con_id: Some(100),
+                horizontal: true,
+                amount: 30,
+                unit: ResizeUnit::Ppt,
+            }),
+            "{:?}",
+            commands
+        );
+
+        // At its share already.
+        let commands = plan_locks(
+            workspace,
+            &marked(&[(100, "ratio_lock:50")]),
+            focused,
+            &ratio_locks(true),
+        );
+        assert!(commands.is_empty(), "{:?}", commands);
+    }
+
+    #[test]
+    fn px_sizes_come_from_the_scaled_rects_sway_reports() {
+        // sway reports a 2560x1600 panel at scale 2 as 1280x800, in the logical pixels its
+        // `resize set ... px` takes, next to an unscaled 1920x1080 monitor.
+        let scaled = workspace(
+            10,
+            "1",
+            NodeLayout::SplitH,
+            (0, 0, 1280, 800),
+            vec![
+                window(100, "A", (0, 0, 640, 800)),
+                window(101, "B", (640, 0, 640, 800)),
+            ],
+        );
+        let unscaled = workspace(
+            11,
+            "2",
+            NodeLayout::SplitH,
+            (1280, 0, 1920, 1080),
+            vec![
+                window(102, "C", (1280, 0, 960, 1080)),
+                window(103, "D", (2240, 0, 960, 1080)),
+            ],
+        );
+        let tree = root(vec![
+            output(2, "eDP-1", scaled),
+            output(3, "HDMI-A-1", unscaled),
+        ]);
+        let mut settings = default_settings();
+        settings.resize_unit = ResizeUnit::Px;
+
+        let resize = |id, amount| Command::Resize {
+            con_id: Some(id),
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Px,
+        };
+        let commands = plan(&tree, 101, &settings).commands().unwrap();
+        assert_eq!(commands.last(), Some(&resize(101, 422)), "{:?}", commands);
+        let commands = plan(&tree, 103, &settings).commands().unwrap();
+        assert_eq!(commands.last(), Some(&resize(103, 634)), "{:?}", commands);
+    }
+
+    #[test]
+    fn auto_ratio_leaves_the_larger_pane_at_the_target_aspect() {
+        // 1440 * 1.6 = 2304 of 3440 for the larger pane leaves the new window 0.33.
+        assert!((auto_ratio((0, 0, 3440, 1440), 1.6) - 0.330).abs() < 0.001);
+        // 16:9 is too narrow for a 1.6 pane beside another, so the new window gets the least.
+        assert_eq!(auto_ratio((0, 0, 1920, 1080), 1.6), AUTO_RATIO_MIN);
+        // Super-ultrawide would give the new window the larger pane.
+        assert_eq!(auto_ratio((0, 0, 5120, 1440), 1.6), AUTO_RATIO_MAX);
+        assert_eq!(auto_ratio((0, 0, 0, 0), 1.6), AUTO_RATIO_MAX);
+    }
+
+    #[test]
+    fn auto_ratio_uses_the_long_axis_of_portrait_outputs() {
+        let tree = root(vec![output(
+            2,
+            "DP-1",
+            workspace(10, "1", NodeLayout::SplitV, (0, 0, 1440, 3440), Vec::new()),
+        )]);
+        let workspace = find_workspace(10, &tree).unwrap();
+        let mut settings = default_settings();
+        settings.auto_ratio = true;
+
+        let along_height = effective_ratio(&tree, workspace, false, &settings);
+        assert!((along_height - 0.330).abs() < 0.001, "{}", along_height);
+        assert_eq!(
+            effective_ratio(&tree, workspace, true, &settings),
+            settings.ratio_vertical
+        );
+    }
+
+    #[test]
+    fn resize_delta_grows_and_shrinks_to_the_ratio() {
+        let rect = (0, 0, 1000, 500);
+        assert_eq!(resize_delta(rect, 0.25, true, 0.4), (15, 150));
+        assert_eq!(resize_delta(rect, 0.5, true, 0.33), (-17, -170));
+        assert_eq!(resize_delta(rect, 0.5, false, 0.33), (-17, -85));
+        assert_eq!(resize_delta(rect, 0.33, true, 0.33), (0, 0));
+    }
+
+    #[test]
+    fn relative_resizes_are_skipped_at_the_ratio() {
+        let parent = split(
+            20,
+            NodeLayout::SplitH,
+            (0, 0, 1000, 500),
+            vec![
+                window(100, "A", (0, 0, 500, 500)),
+                window(101, "B", (500, 0, 500, 500)),
+            ],
+        );
+        let mut settings = default_settings();
+        settings.resize_method = ResizeMethod::Relative;
+
+        let child = &parent.nodes[1];
+        assert_eq!(
+            resize_command(&parent, child, true, 0.33, &settings),
+            Some(Command::ResizeBy {
+                con_id: None,
+                horizontal: true,
+                ppt: -17,
+                px: -170,
+            })
+        );
+        assert_eq!(resize_command(&parent, child, true, 0.5, &settings), None);
+    }
+
+    #[test]
+    fn aspect_shares_must_fit_in_the_parent() {
+        let share = aspect_share((0, 0, 1000, 500), true, 16.0 / 9.0).unwrap();
+        assert!((share - 0.889).abs() < 0.001, "{}", share);
+        let share = aspect_share((0, 0, 1000, 1000), false, 16.0 / 9.0).unwrap();
+        assert!((share - 0.5625).abs() < 0.001, "{}", share);
+
+        // As wide as the parent, or wider, leaves the sibling nothing.
+        assert_eq!(aspect_share((0, 0, 1000, 500), true, 2.0), None);
+        assert_eq!(aspect_share((0, 0, 1000, 1000), true, 16.0 / 9.0), None);
+        assert_eq!(aspect_share((0, 0, 1000, 0), true, 16.0 / 9.0), None);
+        assert_eq!(aspect_share((0, 0, 0, 0), false, 16.0 / 9.0), None);
+    }
+
+    #[test]
+    fn count_policy_picks_the_action_for_the_number_of_windows() {
+        let mut settings = default_settings();
+        settings.count_policy = vec!["1:none", "2:ratio", "3+:equalize"]
+            .into_iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        let windows = |count: i64| -> Node {
+            let width = 900 / count as i32;
+            tree(
+                "1",
+                NodeLayout::SplitH,
+                (0..count)
+                    .map(|i| window(100 + i, "A", (i as i32 * width, 0, width, 500)))
+                    .collect(),
+            )
+        };
+        let resizes = |decision: Decision| -> Vec<(Option<i64>, i32)> {
+            decision
+                .commands()
+                .unwrap()
+                .into_iter()
+                .filter_map(|command| match command {
+                    Command::Resize { con_id, amount, .. } => Some((con_id, amount)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // A lone window keeps the whole workspace.
+        assert_eq!(
+            plan(&windows(1), 100, &settings),
+            Decision::Skip(SkipReason::CountPolicy)
+        );
+        assert_eq!(
+            resizes(plan(&windows(2), 101, &settings)),
+            vec![(Some(101), 33)]
+        );
+        let mut shares: Vec<i32> = resizes(plan(&windows(3), 102, &settings))
+            .into_iter()
+            .map(|(_, amount)| amount)
+            .collect();
+        shares.sort_unstable();
+        assert_eq!(shares, vec![33, 33, 34]);
+        assert_eq!(
+            resizes(plan(&windows(5), 104, &settings)).len(),
+            5,
+            "3+ covers any count above"
+        );
+    }
+
+    #[test]
+    fn containers_spanning_outputs_follow_multi_output_container() {
+        // Container 20 straddles the edge between the two outputs.
+        let spanning = split(
+            20,
+            NodeLayout::SplitH,
+            (500, 0, 1000, 500),
+            vec![
+                window(100, "A", (500, 0, 500, 500)),
+                window(101, "B", (1000, 0, 500, 500)),
+            ],
+        );
+        let left = workspace(
+            10,
+            "1",
+            NodeLayout::SplitH,
+            (0, 0, 1000, 500),
+            vec![spanning],
+        );
+        let right = workspace(
+            11,
+            "2",
+            NodeLayout::SplitH,
+            (1000, 0, 1000, 500),
+            Vec::new(),
+        );
+        let tree = root(vec![output(2, "DP-1", left), output(3, "DP-2", right)]);
+        let mut settings = default_settings();
+        settings.resize_unit = ResizeUnit::Px;
+
+        settings.multi_output_container = MultiOutput::Skip;
+        assert_eq!(
+            plan(&tree, 101, &settings),
+            Decision::Skip(SkipReason::SpansOutputs)
+        );
+
+        // Pixels on one output aren't pixels on the other, so the resize is made relative.
+        settings.multi_output_container = MultiOutput::Dominant;
+        let commands = plan(&tree, 101, &settings).commands().unwrap();
+        assert_eq!(
+            commands.last(),
+            Some(&Command::Resize {
+                con_id: Some(101),
+                horizontal: true,
+                amount: 33,
+                unit: ResizeUnit::Ppt,
+            }),
+            "{:?}",
+            commands
+        );
+    }
+
+    /// Window 101 opening beside window 100 on a workspace with the given layout.
+    fn opened_beside(layout: NodeLayout) -> Node {
+        let (first, second) = match layout {
+            NodeLayout::SplitV => ((0, 0, 1000, 250), (0, 250, 1000, 250)),
+            _ => ((0, 0, 500, 500), (500, 0, 500, 500)),
+        };
+        tree(
+            "1",
+            layout,
+            vec![window(100, "A", first), window(101, "B", second)],
+        )
+    }
+
+    /// The direction of the final resize in a plan, and the layouts it switches to.
+    fn resize_and_layouts(decision: Decision) -> (bool, Vec<SplitDirection>) {
+        let commands = decision.commands().unwrap();
+        let layouts = commands
+            .iter()
+            .filter_map(|command| match command {
+                Command::Layout { direction, .. } => Some(*direction),
+                _ => None,
+            })
+            .collect();
+        let horizontal = commands.iter().rev().find_map(Command::resize_horizontal);
+        (horizontal.unwrap(), layouts)
+    }
+
+    #[test]
+    fn spiral_seed_sets_the_direction_of_the_first_split() {
+        let mut settings = default_settings();
+        let horizontal = opened_beside(NodeLayout::SplitH);
+        let vertical = opened_beside(NodeLayout::SplitV);
+
+        settings.spiral_seed = SpiralSeed::Inherit;
+        assert_eq!(
+            resize_and_layouts(plan(&horizontal, 101, &settings)),
+            (true, vec![])
+        );
+        assert_eq!(
+            resize_and_layouts(plan(&vertical, 101, &settings)),
+            (false, vec![])
+        );
+
+        settings.spiral_seed = SpiralSeed::Horizontal;
+        assert_eq!(
+            resize_and_layouts(plan(&horizontal, 101, &settings)),
+            (true, vec![])
+        );
+        assert_eq!(
+            resize_and_layouts(plan(&vertical, 101, &settings)),
+            (true, vec![SplitDirection::Horizontal])
+        );
+
+        settings.spiral_seed = SpiralSeed::Vertical;
+        assert_eq!(
+            resize_and_layouts(plan(&horizontal, 101, &settings)),
+            (false, vec![SplitDirection::Vertical])
+        );
+        assert_eq!(
+            resize_and_layouts(plan(&vertical, 101, &settings)),
+            (false, vec![])
+        );
+    }
+
+    #[test]
+    fn spiral_seed_leaves_nested_containers_alone() {
+        let tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![split(
+                20,
+                NodeLayout::SplitH,
+                (0, 0, 1000, 500),
+                vec![
+                    window(100, "A", (0, 0, 500, 500)),
+                    window(101, "B", (500, 0, 500, 500)),
+                ],
+            )],
+        );
+        let mut settings = default_settings();
+        settings.spiral_seed = SpiralSeed::Vertical;
+        assert_eq!(
+            resize_and_layouts(plan(&tree, 101, &settings)),
+            (true, vec![])
+        );
+    }
+
+    #[test]
+    fn portrait_outputs_start_the_spiral_vertically() {
+        // i3 starts every workspace split horizontally, whatever the output's shape.
+        let portrait = root(vec![output(
+            2,
+            "DP-1",
+            workspace(
+                10,
+                "1",
+                NodeLayout::SplitH,
+                (0, 0, 1080, 1920),
+                vec![
+                    window(100, "A", (0, 0, 540, 1920)),
+                    window(101, "B", (540, 0, 540, 1920)),
+                ],
+            ),
+        )]);
+        let mut settings = default_settings();
+
+        let commands = plan(&portrait, 101, &settings).commands().unwrap();
+        assert_eq!(
+            commands[..2],
+            [
+                Command::Focus(101),
+                Command::Layout {
+                    con_id: None,
+                    direction: SplitDirection::Vertical,
+                },
+            ]
+        );
+        assert_eq!(
+            resize_and_layouts(plan(&portrait, 101, &settings)),
+            (false, vec![SplitDirection::Vertical])
+        );
+
+        settings.portrait_aware = false;
+        assert_eq!(
+            resize_and_layouts(plan(&portrait, 101, &settings)),
+            (true, vec![])
+        );
+    }
+
+    fn swaps(decision: Decision) -> Vec<Command> {
+        decision
+            .commands()
+            .unwrap()
+            .into_iter()
+            .filter(|command| matches!(command, Command::Swap { .. }))
+            .collect()
+    }
+
+    #[test]
+    fn new_window_side_swaps_the_new_window_onto_its_side() {
+        let tree = opened_beside(NodeLayout::SplitH);
+        let swap = |con_id, with| vec![Command::Swap { con_id, with }];
+        let mut settings = default_settings();
+
+        settings.new_window_side = NewWindowSide::Auto;
+        assert_eq!(swaps(plan(&tree, 101, &settings)), vec![]);
+        assert_eq!(swaps(plan(&tree, 100, &settings)), vec![]);
+
+        settings.new_window_side = NewWindowSide::LeftTop;
+        assert_eq!(swaps(plan(&tree, 101, &settings)), swap(101, 100));
+        assert_eq!(swaps(plan(&tree, 100, &settings)), vec![]);
+
+        settings.new_window_side = NewWindowSide::RightBottom;
+        assert_eq!(swaps(plan(&tree, 101, &settings)), vec![]);
+        assert_eq!(swaps(plan(&tree, 100, &settings)), swap(100, 101));
+    }
+
+    #[test]
+    fn titles_matching_two_patterns_get_the_first_ones_ratio() {
+        let (settings, _) = parse_settings_from_str(
+            "[main]\n[title_ratios]\nZoom = 0.7\nMeeting$ = 0.25\n",
+            Format::Ini,
+        )
+        .unwrap();
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        let node = find_node(101, &tree).unwrap().clone();
+        assert_eq!(plan_title_ratio(&tree, 10, &node, &settings), None);
+
+        let mut titled = node;
+        titled.name = Some("Zoom Meeting".to_string());
+        assert_eq!(
+            plan_title_ratio(&tree, 10, &titled, &settings),
+            Some(Command::Resize {
+                con_id: Some(101),
+                horizontal: true,
+                amount: 70,
+                unit: ResizeUnit::Ppt,
+            })
+        );
+
+        // Already at the ratio.
+        tree.nodes[0].nodes[0].nodes[0].percent = Some(0.3);
+        tree.nodes[0].nodes[0].nodes[1].percent = Some(0.7);
+        assert_eq!(plan_title_ratio(&tree, 10, &titled, &settings), None);
+    }
+
+    #[test]
+    fn role_ratios_size_windows_with_that_role() {
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        let new = &mut tree.nodes[0].nodes[0].nodes[1];
+        new.window_properties
+            .as_mut()
+            .unwrap()
+            .insert(WindowProperty::WindowRole, "pop-up".to_string());
+        let mut settings = default_settings();
+        settings.role_ratios.insert("pop-up".to_string(), 0.6);
+
+        let resize = |id, amount| Command::Resize {
+            con_id: Some(id),
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Ppt,
+        };
+        let commands = plan(&tree, 101, &settings).commands().unwrap();
+        assert_eq!(commands.last(), Some(&resize(101, 60)));
+        // Window 100 has no role, and gets the ratio.
+        let commands = plan(&tree, 100, &settings).commands().unwrap();
+        assert_eq!(commands.last(), Some(&resize(100, 33)));
+    }
+
+    #[test]
+    fn role_rules_beat_instance_class_and_title_rules() {
+        let (mut settings, _) = parse_settings_from_str(
+            "[main]\n[role_ratios]\npop-up = 0.6\n[instance_ratios]\nb = 0.55\n\
+             [aspect]\nB = 1:1\n[title_ratios]\nwindow$ = 0.7\n",
+            Format::Ini,
+        )
+        .unwrap();
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        tree.nodes[0].nodes[0].nodes[1]
+            .window_properties
+            .as_mut()
+            .unwrap()
+            .insert(WindowProperty::WindowRole, "pop-up".to_string());
+        let resize = |amount| Command::Resize {
+            con_id: Some(101),
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Ppt,
+        };
+        let last = |settings: &Settings| {
+            let commands = plan(&tree, 101, settings).commands().unwrap();
+            commands.last().cloned()
+        };
+
+        assert_eq!(last(&settings), Some(resize(60)));
+        settings.role_ratios.clear();
+        assert_eq!(last(&settings), Some(resize(55)));
+        settings.instance_ratios.clear();
+        // A square in the 1000x500 workspace, sized on the focused new window.
+        let square = Command::Resize {
+            con_id: None,
+            horizontal: true,
+            amount: 500,
+            unit: ResizeUnit::Px,
+        };
+        assert_eq!(last(&settings), Some(square));
+        settings.aspects.clear();
+        assert_eq!(last(&settings), Some(resize(70)));
+    }
+
+    /// Window 100 beside a tabbed group of windows 101 and 102, in a workspace split along
+    /// `layout`. The group is taller than wide in a horizontal split, and wider than tall in a
+    /// vertical one.
+    fn beside_tabbed_group(layout: NodeLayout) -> Node {
+        let (first, group) = match layout {
+            NodeLayout::SplitH => ((0, 0, 600, 500), (600, 0, 400, 500)),
+            _ => ((0, 0, 1000, 250), (0, 250, 1000, 250)),
+        };
+        let (x, y, width, height) = group;
+        let tab = (x, y + 20, width, height - 20);
+        tree(
+            "1",
+            layout,
+            vec![
+                window(100, "A", first),
+                split(
+                    200,
+                    NodeLayout::Tabbed,
+                    group,
+                    vec![window(101, "B", tab), window(102, "C", tab)],
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn escaping_a_tabbed_group_moves_along_the_split_around_it() {
+        let mut settings = default_settings();
+        settings.escape_tabbed = true;
+        for &(ref layout, direction) in &[
+            (NodeLayout::SplitH, SplitDirection::Horizontal),
+            (NodeLayout::SplitV, SplitDirection::Vertical),
+        ] {
+            let tree = beside_tabbed_group(layout.clone());
+            let new = find_node(102, &tree).unwrap();
+            assert_eq!(
+                plan_escape(&tree, &Extras::new(), new, &settings),
+                Some(Command::Move {
+                    con_id: 102,
+                    direction,
+                })
+            );
+            assert_eq!(
+                plan_escape(&tree, &marked(&[(200, "no_escape")]), new, &settings),
+                None
+            );
+        }
+
+        // Window 100 isn't in a group.
+        let tree = beside_tabbed_group(NodeLayout::SplitH);
+        let window = find_node(100, &tree).unwrap();
+        assert_eq!(plan_escape(&tree, &Extras::new(), window, &settings), None);
+    }
+
+    #[test]
+    fn converting_a_tabbed_group_splits_along_its_longer_side() {
+        let mut settings = default_settings();
+        settings.manage_tabbed = ManageTabbed::Convert;
+        for &(ref layout, direction) in &[
+            (NodeLayout::SplitH, SplitDirection::Vertical),
+            (NodeLayout::SplitV, SplitDirection::Horizontal),
+        ] {
+            let tree = beside_tabbed_group(layout.clone());
+            let new = find_node(102, &tree).unwrap();
+            let conversion = plan_convert(&tree, new, &settings).unwrap();
+            assert_eq!(
+                conversion.convert,
+                Command::Layout {
+                    con_id: Some(102),
+                    direction,
+                }
+            );
+            assert_eq!(
+                conversion.revert,
+                Command::Overflow {
+                    con_id: 102,
+                    layout: OverflowLayout::Tabbed,
+                }
+            );
+        }
+
+        let mut tree = beside_tabbed_group(NodeLayout::SplitH);
+        tree.nodes[0].nodes[0].nodes[1].layout = NodeLayout::Stacked;
+        let new = find_node(102, &tree).unwrap();
+        let conversion = plan_convert(&tree, new, &settings).unwrap();
+        assert_eq!(
+            conversion.revert,
+            Command::Overflow {
+                con_id: 102,
+                layout: OverflowLayout::Stacked,
+            }
+        );
+        let window = find_node(100, &tree).unwrap();
+        assert!(plan_convert(&tree, window, &settings).is_none());
+    }
+
+    #[test]
+    fn converting_targets_the_window_in_an_implicit_tabbed_container() {
+        let mut settings = default_settings();
+        settings.manage_tabbed = ManageTabbed::Convert;
+        let tabs = || {
+            let tab = (0, 20, 1000, 480);
+            vec![window(101, "B", tab), window(102, "C", tab)]
+        };
+        let convert = Command::Layout {
+            con_id: Some(102),
+            direction: SplitDirection::Horizontal,
+        };
+
+        // With workspace_layout tabbed, i3 wraps the windows in a tabbed container of its own.
+        let implicit = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![split(300, NodeLayout::Tabbed, (0, 0, 1000, 500), tabs())],
+        );
+        let new = find_node(102, &implicit).unwrap();
+        let conversion = plan_convert(&implicit, new, &settings).unwrap();
+        assert_eq!(conversion.convert, convert);
+
+        // Without it, the workspace itself can be tabbed.
+        let workspace = tree("1", NodeLayout::Tabbed, tabs());
+        let new = find_node(102, &workspace).unwrap();
+        let conversion = plan_convert(&workspace, new, &settings).unwrap();
+        assert_eq!(conversion.convert, convert);
+    }
+
+    #[test]
+    fn restoring_a_snapshot_resizes_children_back() {
+        let mut before = opened_beside(NodeLayout::SplitH);
+        let children = &mut before.nodes[0].nodes[0].nodes;
+        children[0].percent = Some(0.6);
+        children[1].percent = Some(0.4);
+        let recorded = snapshot(&before.nodes[0].nodes[0], vec![101]).unwrap();
+        assert_eq!(recorded.percents, vec![(100, 0.6), (101, 0.4)]);
+
+        let mut after = opened_beside(NodeLayout::SplitH);
+        focus(&mut after, 101);
+        assert_eq!(
+            plan_restore(&after, &recorded, &default_settings()),
+            Some(vec![
+                Command::Layout {
+                    con_id: Some(101),
+                    direction: SplitDirection::Horizontal,
+                },
+                Command::Focus(100),
+                Command::Resize {
+                    con_id: None,
+                    horizontal: true,
+                    amount: 60,
+                    unit: ResizeUnit::Ppt,
+                },
+                Command::Focus(101),
+            ])
+        );
+    }
+
+    #[test]
+    fn snapshots_of_changed_containers_are_not_restored() {
+        let before = opened_beside(NodeLayout::SplitH);
+        let recorded = snapshot(&before.nodes[0].nodes[0], Vec::new()).unwrap();
+        let settings = default_settings();
+
+        // Window 101 closed.
+        let closed = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "A", (0, 0, 1000, 500))],
+        );
+        assert!(plan_restore(&closed, &recorded, &settings).is_none());
+
+        // The workspace is now a different one.
+        let mut elsewhere = tree(
+            "2",
+            NodeLayout::SplitH,
+            vec![window(102, "C", (0, 0, 1000, 500))],
+        );
+        elsewhere.nodes[0].nodes[0].id = 11;
+        assert!(plan_restore(&elsewhere, &recorded, &settings).is_none());
+
+        // Snapshots are only taken of splits.
+        let tabbed = opened_beside(NodeLayout::Tabbed);
+        assert!(snapshot(&tabbed.nodes[0].nodes[0], Vec::new()).is_none());
+    }
+
+    #[test]
+    fn dock_windows_get_no_commands() {
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        dock_bar(&mut tree, 300);
+        // Tree lookups leave out dock areas, so the bar comes straight from the event.
+        let bar = tree.nodes[0].nodes[0].nodes[0].clone();
+        let decision = plan_commands(
+            &tree,
+            &Extras::new(),
+            &Remembered::new(),
+            &bar,
+            Some(300),
+            &default_settings(),
+        );
+        assert_eq!(decision, Decision::Skip(SkipReason::Dock));
+        // The bar doesn't change what's planned for the windows.
+        assert!(plan(&tree, 101, &default_settings()).commands().is_some());
+    }
+
+    #[test]
+    fn ratios_on_either_side_of_a_half_are_the_new_windows_share() {
+        let tree = opened_beside(NodeLayout::SplitH);
+        let resize = |amount| Command::Resize {
+            con_id: Some(101),
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Ppt,
+        };
+        let last = |settings: &Settings| plan(&tree, 101, settings).commands().unwrap().pop();
+
+        for &(ratio, share, master) in &[(0.3, 30, 70), (0.7, 70, 30)] {
+            let mut settings = default_settings();
+            settings.ratio = ratio;
+            assert_eq!(last(&settings), Some(resize(share)));
+
+            // Swapping moves the new window, which keeps its share.
+            settings.swap_new = true;
+            assert_eq!(
+                swaps(plan(&tree, 101, &settings)),
+                vec![Command::Swap {
+                    con_id: 101,
+                    with: 100,
+                }]
+            );
+            assert_eq!(last(&settings), Some(resize(share)));
+
+            // focus_master gives the focused new window the rest instead.
+            settings.mode = Mode::FocusMaster;
+            assert_eq!(last(&settings), Some(resize(master)));
+        }
+    }
+
+    #[test]
+    fn rebalancing_finds_the_managed_window_on_either_side_of_a_half() {
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        let children = &mut tree.nodes[0].nodes[0].nodes;
+        children[0].percent = Some(0.35);
+        children[1].percent = Some(0.65);
+        let focused = find_node(100, &tree).unwrap();
+        let resize = |amount| Command::Resize {
+            con_id: None,
+            horizontal: true,
+            amount,
+            unit: ResizeUnit::Ppt,
+        };
+
+        for &(ratio, managed, amount) in &[(0.7, 101, 70), (0.3, 100, 30)] {
+            let mut settings = default_settings();
+            settings.ratio = ratio;
+            assert_eq!(
+                plan_rebalance(&tree, focused, &settings),
+                Some(vec![
+                    Command::Focus(managed),
+                    resize(amount),
+                    Command::Focus(100),
+                ])
+            );
+        }
+    }
+
+    #[test]
+    fn containers_overflow_and_restore_across_the_threshold() {
+        let mut settings = default_settings();
+        settings.overflow = OverflowPolicy::Layout;
+        settings.overflow_layout = OverflowLayout::Stacked;
+        settings.overflow_threshold = 2;
+        settings.overflow_restore = true;
+        let windows = |count: i64, layout| -> Node {
+            let width = 900 / count as i32;
+            tree(
+                "1",
+                layout,
+                (0..count)
+                    .map(|i| window(100 + i, "A", (width * i as i32, 0, width, 500)))
+                    .collect(),
+            )
+        };
+
+        // Going up: a second window is split beside the first, and a third stacks them.
+        let (horizontal, _) =
+            resize_and_layouts(plan(&windows(2, NodeLayout::SplitH), 101, &settings));
+        assert!(horizontal);
+        assert_eq!(
+            plan(&windows(3, NodeLayout::SplitH), 102, &settings).commands(),
+            Some(vec![Command::Overflow {
+                con_id: 102,
+                layout: OverflowLayout::Stacked,
+            }])
+        );
+
+        // Going down: the split comes back once fewer than two are left.
+        let stacked = |count| windows(count, NodeLayout::Stacked).nodes[0].nodes[0].clone();
+        let restore = |count, settings: &Settings| {
+            plan_overflow_restore(&stacked(count), SplitDirection::Horizontal, settings)
+        };
+        assert_eq!(restore(3, &settings), None);
+        assert_eq!(restore(2, &settings), None);
+        assert_eq!(
+            restore(1, &settings),
+            Some(vec![Command::Layout {
+                con_id: Some(100),
+                direction: SplitDirection::Horizontal,
+            }])
+        );
+
+        // Containers switched to another layout by hand stay that way.
+        let split = windows(1, NodeLayout::SplitV).nodes[0].nodes[0].clone();
+        assert_eq!(
+            plan_overflow_restore(&split, SplitDirection::Horizontal, &settings),
+            None
+        );
+        settings.overflow_restore = false;
+        assert_eq!(restore(1, &settings), None);
+    }
+
+    #[test]
+    fn swap_new_swaps_before_splitting_and_resizing() {
+        let mut settings = default_settings();
+        settings.swap_new = true;
+        let split = Command::Split {
+            con_id: None,
+            direction: SplitDirection::Vertical,
+        };
+        assert_eq!(
+            plan(&opened_beside(NodeLayout::SplitH), 101, &settings),
+            Decision::Act(vec![
+                Command::Swap {
+                    con_id: 101,
+                    with: 100,
+                },
+                Command::Focus(100),
+                split.clone(),
+                Command::Focus(101),
+                split,
+                Command::Focus(101),
+                Command::Resize {
+                    con_id: Some(101),
+                    horizontal: true,
+                    amount: 33,
+                    unit: ResizeUnit::Ppt,
+                },
+            ])
+        );
+
+        // Only a window with a single sibling is swapped.
+        settings.multi_child = MultiChild::Equalize;
+        let three = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 300, 500)),
+                window(101, "B", (300, 0, 300, 500)),
+                window(102, "C", (600, 0, 300, 500)),
+            ],
+        );
+        assert_eq!(swaps(plan(&three, 102, &settings)), vec![]);
+    }
+
+    #[test]
+    fn promote_new_to_master_swaps_into_the_first_slot_before_resizing() {
+        let mut settings = default_settings();
+        settings.promote_new_to_master = true;
+        settings.ratio_vertical = 0.7;
+        let split = Command::Split {
+            con_id: None,
+            direction: SplitDirection::Horizontal,
+        };
+        assert_eq!(
+            plan(&opened_beside(NodeLayout::SplitV), 101, &settings),
+            Decision::Act(vec![
+                Command::Swap {
+                    con_id: 101,
+                    with: 100,
+                },
+                Command::Focus(100),
+                split.clone(),
+                Command::Focus(101),
+                split,
+                Command::Focus(101),
+                Command::Resize {
+                    con_id: Some(101),
+                    horizontal: false,
+                    amount: 70,
+                    unit: ResizeUnit::Ppt,
+                },
+            ])
+        );
+
+        // A new window that already is first stays put.
+        let mut tree = opened_beside(NodeLayout::SplitV);
+        tree.nodes[0].nodes[0].nodes.reverse();
+        assert_eq!(swaps(plan(&tree, 101, &settings)), vec![]);
+    }
+
+    #[test]
+    fn windows_filling_their_parent_are_skipped_with_skip_if_fills_parent() {
+        let mut settings = default_settings();
+        settings.skip_if_fills_parent = true;
+        let beside = |rect| {
+            tree(
+                "1",
+                NodeLayout::SplitH,
+                vec![window(100, "A", (0, 0, 500, 500)), window(101, "B", rect)],
+            )
+        };
+        let fills = Decision::Skip(SkipReason::FillsParent);
+
+        // Exactly the workspace's rect, like a window i3 hasn't laid out yet.
+        assert_eq!(plan(&beside((0, 0, 1000, 500)), 101, &settings), fills);
+        // Within FILL_TOLERANCE of it, like with gaps or borders.
+        assert_eq!(plan(&beside((20, 5, 960, 490)), 101, &settings), fills);
+        // Short of it along either axis.
+        assert!(plan(&beside((0, 0, 940, 500)), 101, &settings)
+            .commands()
+            .is_some());
+        assert!(plan(&beside((0, 0, 1000, 470)), 101, &settings)
+            .commands()
+            .is_some());
+
+        settings.skip_if_fills_parent = false;
+        assert!(plan(&beside((0, 0, 1000, 500)), 101, &settings)
+            .commands()
+            .is_some());
+
+        // Nothing fills a parent with no size.
+        let window = window(101, "B", (0, 0, 0, 0));
+        assert!(!fills_parent(&window, &window));
+    }
+
+    #[test]
+    fn tabbed_siblings_get_an_equal_share_as_one_child() {
+        let mut settings = default_settings();
+        settings.multi_child = MultiChild::Equalize;
+        let tab = (250, 20, 500, 480);
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 250, 500)),
+                split(
+                    200,
+                    NodeLayout::Tabbed,
+                    (250, 0, 500, 500),
+                    vec![window(201, "B", tab), window(202, "C", tab)],
+                ),
+                window(102, "D", (750, 0, 250, 500)),
+            ],
+        );
+        let children = &mut tree.nodes[0].nodes[0].nodes;
+        children[0].percent = Some(0.25);
+        children[1].percent = Some(0.5);
+        children[2].percent = Some(0.25);
+
+        let resizes: Vec<_> = plan(&tree, 102, &settings)
+            .commands()
+            .unwrap()
+            .into_iter()
+            .filter_map(|command| match command {
+                Command::Resize {
+                    con_id: Some(id),
+                    amount,
+                    ..
+                } => Some((id, amount)),
+                _ => None,
+            })
+            .collect();
+        // The group is sized as a whole, by its own id rather than its focused tab's.
+        assert_eq!(resizes, vec![(100, 34), (200, 33), (102, 33)]);
+    }
+
+    #[test]
+    fn refused_resizes_give_the_sibling_the_rest() {
+        let two = opened_beside(NodeLayout::SplitH);
+        let resize = |con_id, amount, unit| Command::Resize {
+            con_id: Some(con_id),
+            horizontal: true,
+            amount,
+            unit,
+        };
+        assert_eq!(
+            plan_sibling_resize(&two, 101, &resize(101, 33, ResizeUnit::Ppt)),
+            Some(vec![resize(100, 67, ResizeUnit::Ppt)])
+        );
+        assert_eq!(
+            plan_sibling_resize(&two, 101, &resize(101, 330, ResizeUnit::Px)),
+            Some(vec![resize(100, 670, ResizeUnit::Px)])
+        );
+
+        // With more than one sibling, there's no telling which should give way.
+        let three = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 300, 500)),
+                window(101, "B", (300, 0, 300, 500)),
+                window(102, "C", (600, 0, 300, 500)),
+            ],
+        );
+        assert_eq!(
+            plan_sibling_resize(&three, 101, &resize(101, 33, ResizeUnit::Ppt)),
+            None
+        );
+    }
+
+    #[test]
+    fn new_windows_match_the_reference_windows_size() {
+        let mut settings = default_settings();
+        settings.mode = Mode::MatchReference;
+        assert_eq!(settings.reference_mark, "reference");
+        // Window 100 is 300 of the 700 pixels the container of windows 101 and 102 is wide.
+        let tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 300, 500)),
+                split(
+                    200,
+                    NodeLayout::SplitH,
+                    (300, 0, 700, 500),
+                    vec![
+                        window(101, "B", (300, 0, 350, 500)),
+                        window(102, "C", (650, 0, 350, 500)),
+                    ],
+                ),
+            ],
+        );
+        let last = |extras: &Extras, settings: &Settings| {
+            let new_node = find_node(102, &tree).unwrap();
+            plan_commands(
+                &tree,
+                extras,
+                &Remembered::new(),
+                new_node,
+                Some(102),
+                settings,
+            )
+            .commands()
+            .unwrap()
+            .pop()
+        };
+        let resize = |amount, unit| {
+            Some(Command::Resize {
+                con_id: Some(102),
+                horizontal: true,
+                amount,
+                unit,
+            })
+        };
+        let reference = marked(&[(100, "reference")]);
+
+        assert_eq!(last(&reference, &settings), resize(43, ResizeUnit::Ppt));
+        settings.resize_unit = ResizeUnit::Px;
+        assert_eq!(last(&reference, &settings), resize(300, ResizeUnit::Px));
+
+        // Without the mark, or with it on a window too large to match, the ratio is used.
+        settings.resize_unit = ResizeUnit::Ppt;
+        assert_eq!(last(&Extras::new(), &settings), resize(33, ResizeUnit::Ppt));
+        settings.reference_mark = "main".to_string();
+        assert_eq!(last(&reference, &settings), resize(33, ResizeUnit::Ppt));
+        let too_large = marked(&[(200, "main")]);
+        assert_eq!(last(&too_large, &settings), resize(33, ResizeUnit::Ppt));
+    }
+
+    #[test]
+    fn a_forced_direction_inside_a_spiral_only_splits_the_new_window() {
+        let mut settings = default_settings();
+        settings
+            .split_directions
+            .insert("Alacritty".to_string(), SplitDirection::Horizontal);
+        let spiral = |class: &str| {
+            tree(
+                "1",
+                NodeLayout::SplitH,
+                vec![
+                    window(100, "Firefox", (0, 0, 500, 500)),
+                    split(
+                        20,
+                        NodeLayout::SplitV,
+                        (500, 0, 500, 500),
+                        vec![
+                            window(101, "Emacs", (500, 0, 500, 250)),
+                            window(102, class, (500, 250, 500, 250)),
+                        ],
+                    ),
+                ],
+            )
+        };
+
+        let rendered = |decision: Decision| -> Vec<String> {
+            decision
+                .commands()
+                .unwrap()
+                .iter()
+                .map(|c| c.to_string())
+                .collect()
+        };
+        assert_eq!(
+            rendered(plan(&spiral("Kitty"), 102, &settings)),
+            [
+                "[con_id=101] focus",
+                "split horizontal",
+                "[con_id=102] focus",
+                "split horizontal",
+                "[con_id=102] focus",
+                "[con_id=102] resize set height 33 ppt",
+            ]
+        );
+        // The sibling keeps its split, and the new window's alternates from the forced direction.
+        assert_eq!(
+            rendered(plan(&spiral("Alacritty"), 102, &settings)),
+            [
+                "[con_id=102] focus",
+                "layout splith",
+                "[con_id=102] focus",
+                "split vertical",
+                "[con_id=102] focus",
+                "[con_id=102] resize set width 33 ppt",
+            ]
+        );
+
+        // The next window beside it carries on the spiral from there.
+        let next = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "Firefox", (0, 0, 500, 500)),
+                split(
+                    20,
+                    NodeLayout::SplitH,
+                    (500, 0, 500, 500),
+                    vec![
+                        window(101, "Emacs", (500, 0, 167, 500)),
+                        split(
+                            21,
+                            NodeLayout::SplitV,
+                            (667, 0, 333, 500),
+                            vec![
+                                window(102, "Alacritty", (667, 0, 333, 250)),
+                                window(103, "Kitty", (667, 250, 333, 250)),
+                            ],
+                        ),
+                    ],
+                ),
+            ],
+        );
+        assert_eq!(
+            resize_and_layouts(plan(&next, 103, &settings)),
+            (false, vec![])
+        );
+    }
+
+    #[test]
+    fn anchored_windows_keep_their_size_while_the_rest_share_what_is_left() {
+        let mut settings = default_settings();
+        settings.mode = Mode::Equalize;
+        settings.anchor_mark = Some("stay".to_string());
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 400, 500)),
+                window(101, "B", (400, 0, 450, 500)),
+                window(102, "C", (850, 0, 150, 500)),
+            ],
+        );
+        let workspace = &mut tree.nodes[0].nodes[0];
+        for (child, &percent) in workspace.nodes.iter_mut().zip(&[0.4, 0.45, 0.15]) {
+            child.percent = Some(percent);
+        }
+        let new_node = find_node(102, &tree).unwrap();
+
+        let decision = plan_commands(
+            &tree,
+            &marked(&[(100, "stay")]),
+            &Remembered::new(),
+            new_node,
+            Some(102),
+            &settings,
+        );
+        let rendered: Vec<String> = decision
+            .commands()
+            .unwrap()
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        // The anchor is put back last, at the 40 ppt it had.
+        assert_eq!(
+            rendered,
+            [
+                "[con_id=101] focus",
+                "[con_id=101] resize set width 30 ppt",
+                "[con_id=102] focus",
+                "[con_id=102] resize set width 30 ppt",
+                "[con_id=100] focus",
+                "[con_id=100] resize set width 40 ppt",
+                "[con_id=102] focus",
+            ]
+        );
+    }
+
+    #[test]
+    fn focus_is_left_where_it_moved_after_the_window_opened() {
+        let mut tree = opened_beside(NodeLayout::SplitH);
+        focus(&mut tree, 100);
+        let new_node = find_node(101, &tree).unwrap();
+        let rendered = |settings: &Settings| -> Vec<String> {
+            plan_commands(
+                &tree,
+                &Extras::new(),
+                &Remembered::new(),
+                new_node,
+                Some(100),
+                settings,
+            )
+            .commands()
+            .unwrap()
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+        };
+
+        // The window is still sized by its own id, and focus goes back to 100 afterwards.
+        assert_eq!(
+            rendered(&default_settings()),
+            [
+                "[con_id=100] focus",
+                "split vertical",
+                "[con_id=101] focus",
+                "split vertical",
+                "[con_id=101] focus",
+                "[con_id=101] resize set width 33 ppt",
+                "[con_id=100] focus",
+            ]
+        );
+        let mut settings = default_settings();
+        settings.never_change_focus = true;
+        assert_eq!(
+            rendered(&settings),
+            [
+                "[con_id=100] split vertical",
+                "[con_id=101] split vertical",
+                "[con_id=101] resize set width 33 ppt",
+            ]
+        );
+    }
+
+    #[test]
+    fn pinned_windows_are_swapped_only_when_on_the_wrong_side() {
+        let mut settings = default_settings();
+        settings
+            .positions
+            .insert("Firefox".to_string(), Position::Left);
+        settings
+            .positions
+            .insert("Alacritty".to_string(), Position::Right);
+        let pair = |first: &str, second: &str| {
+            tree(
+                "1",
+                NodeLayout::SplitH,
+                vec![
+                    window(100, first, (0, 0, 500, 500)),
+                    window(101, second, (500, 0, 500, 500)),
+                ],
+            )
+        };
+        let swap = |con_id, with| Command::Swap { con_id, with };
+
+        // Already on their sides, opening first or second.
+        assert_eq!(
+            swaps(plan(&pair("Firefox", "Alacritty"), 100, &settings)),
+            []
+        );
+        assert_eq!(
+            swaps(plan(&pair("Firefox", "Alacritty"), 101, &settings)),
+            []
+        );
+        // On the wrong sides, either way round.
+        assert_eq!(
+            swaps(plan(&pair("Alacritty", "Firefox"), 101, &settings)),
+            [swap(101, 100)]
+        );
+        assert_eq!(
+            swaps(plan(&pair("Alacritty", "Firefox"), 100, &settings)),
+            [swap(100, 101)]
+        );
+
+        // The moved window keeps its own share where it ends up.
+        let commands = plan(&pair("Alacritty", "Firefox"), 101, &settings)
+            .commands()
+            .unwrap();
+        assert_eq!(
+            commands.last().unwrap().to_string(),
+            "[con_id=101] resize set width 33 ppt"
+        );
+    }
+}
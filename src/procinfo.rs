@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::fs;
+use std::process::Command;
+
+/// How many pids `ProcessNames` remembers before starting over, since pids get reused.
+const CACHE_LIMIT: usize = 256;
+
+/// Looks up the names of the processes that own windows, remembering them so that `/proc` is
+/// only read once per process.
+pub struct ProcessNames {
+    names: HashMap<u32, Option<String>>,
+    resolve: Box<dyn FnMut(u32) -> Option<String>>,
+}
+
+impl ProcessNames {
+    pub fn new() -> ProcessNames {
+        ProcessNames::with_resolver(read_name)
+    }
+
+    /// Looks names up with `resolve` rather than in `/proc`.
+    pub fn with_resolver(resolve: impl FnMut(u32) -> Option<String> + 'static) -> ProcessNames {
+        ProcessNames {
+            names: HashMap::new(),
+            resolve: Box::new(resolve),
+        }
+    }
+
+    /// The name of the process with the given pid, from `/proc/<pid>/comm`.
+    pub fn name(&mut self, pid: u32) -> Option<&str> {
+        if self.names.len() >= CACHE_LIMIT && !self.names.contains_key(&pid) {
+            self.names.clear();
+        }
+        let resolve = &mut self.resolve;
+        self.names
+            .entry(pid)
+            .or_insert_with(|| resolve(pid))
+            .as_deref()
+    }
+
+    /// The name of the process with the given pid, if it's one of `excluded`, for
+    /// `exclude_processes`.
+    pub fn excluded(&mut self, pid: u32, excluded: &[String]) -> Option<String> {
+        let name = self.name(pid);
+        trace!("Process {} is {:?}", pid, name);
+        name.filter(|name| excluded.iter().any(|e| e == name))
+            .map(String::from)
+    }
+}
+
+fn read_name(pid: u32) -> Option<String> {
+    match fs::read_to_string(format!("/proc/{}/comm", pid)) {
+        Ok(comm) => Some(comm.trim_end().to_string()),
+        Err(error) => {
+            debug!("Error {:?} reading the name of process {}", error, pid);
+            None
+        }
+    }
+}
+
+/// The pid of the X11 window with the given id, from its `_NET_WM_PID` property. i3 doesn't
+/// put pids in its tree, so this asks `xprop`.
+pub fn x11_window_pid(window: i32) -> Option<u32> {
+    let output = match Command::new("xprop")
+        .arg("-id")
+        .arg(window.to_string())
+        .arg("_NET_WM_PID")
+        .output()
+    {
+        Ok(output) => output,
+        Err(error) => {
+            debug!("Error {:?} running xprop", error);
+            return None;
+        }
+    };
+
+    // Prints `_NET_WM_PID(CARDINAL) = 1234`, or a "not found" message without an `=`.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, pid) = stdout.split_once('=')?;
+    pid.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// Names processes `app<pid>`, except for pid 0, counting how often it's asked.
+    fn stub() -> (ProcessNames, Rc<Cell<u32>>) {
+        let lookups = Rc::new(Cell::new(0));
+        let counted = Rc::clone(&lookups);
+        let names = ProcessNames::with_resolver(move |pid| {
+            counted.set(counted.get() + 1);
+            match pid {
+                0 => None,
+                pid => Some(format!("app{}", pid)),
+            }
+        });
+        (names, lookups)
+    }
+
+    #[test]
+    fn names_are_looked_up_once() {
+        let (mut names, lookups) = stub();
+        assert_eq!(names.name(7), Some("app7"));
+        assert_eq!(names.name(7), Some("app7"));
+        assert_eq!(lookups.get(), 1);
+
+        assert_eq!(names.name(8), Some("app8"));
+        assert_eq!(lookups.get(), 2);
+    }
+
+    #[test]
+    fn missing_processes_are_remembered_too() {
+        let (mut names, lookups) = stub();
+        assert_eq!(names.name(0), None);
+        assert_eq!(names.name(0), None);
+        assert_eq!(lookups.get(), 1);
+    }
+
+    #[test]
+    fn the_cache_starts_over_when_full() {
+        let (mut names, lookups) = stub();
+        for pid in 1..=CACHE_LIMIT as u32 {
+            names.name(pid);
+        }
+        assert_eq!(lookups.get(), CACHE_LIMIT as u32);
+
+        // Looking up a pid it already has doesn't clear it.
+        names.name(1);
+        assert_eq!(names.names.len(), CACHE_LIMIT);
+
+        names.name(u32::MAX);
+        assert_eq!(names.names.len(), 1);
+        names.name(1);
+        assert_eq!(lookups.get(), CACHE_LIMIT as u32 + 2);
+    }
+
+    #[test]
+    fn only_listed_processes_are_excluded() {
+        let (mut names, _) = stub();
+        let excluded = vec!["app7".to_string(), "app".to_string()];
+        assert_eq!(names.excluded(7, &excluded), Some("app7".to_string()));
+        assert_eq!(names.excluded(70, &excluded), None);
+        assert_eq!(names.excluded(0, &excluded), None);
+    }
+}
@@ -0,0 +1,194 @@
+use i3ipc::reply::{Node, NodeLayout};
+use i3ipc::I3Connection;
+
+use crate::backend::{detect_backend, Backend};
+use crate::command::render_all;
+use crate::ipc::Extras;
+use crate::planner::{effective_ratio, plan_commands};
+use crate::settings::Settings;
+use crate::state::Remembered;
+use crate::tree::{find_focused, find_node, find_parent, workspaces};
+
+const SCRATCH_WORKSPACE: &str = "ratiosplit-self-test";
+/// How far, as a fraction of the parent, the resulting size may be from the ratio. i3 rounds to
+/// whole pixels and accounts for borders, so an exact match isn't expected.
+const TOLERANCE: f64 = 0.02;
+
+/// Exercises the planner against a real i3 on a scratch workspace, reporting whether the
+/// resulting layout matches the configured ratio. Returns whether the test passed.
+pub fn run(settings: &Settings) -> bool {
+    let mut connection = match I3Connection::connect() {
+        Ok(c) => c,
+        Err(error) => {
+            println!("FAIL: could not connect to i3: {}", error);
+            return false;
+        }
+    };
+
+    let backend = match detect_backend(&mut connection) {
+        Ok((backend, _)) => backend,
+        Err(error) => {
+            println!("FAIL: {}", error);
+            return false;
+        }
+    };
+
+    let previous_workspace = match connection.get_workspaces() {
+        Ok(reply) => reply
+            .workspaces
+            .into_iter()
+            .find(|w| w.focused)
+            .map(|w| w.name),
+        Err(error) => {
+            println!("FAIL: could not list workspaces: {}", error);
+            return false;
+        }
+    };
+
+    let mut created = Vec::new();
+    let result = run_test(&mut connection, backend, settings, &mut created);
+
+    for id in &created {
+        let _ = connection.run_command(&format!("[con_id={}] kill", id));
+    }
+    if let Some(name) = previous_workspace {
+        let _ = connection.run_command(&format!("workspace \"{}\"", name));
+    }
+
+    match result {
+        Ok(message) => {
+            println!("PASS: {}", message);
+            true
+        }
+        Err(message) => {
+            println!("FAIL: {}", message);
+            false
+        }
+    }
+}
+
+fn run_test(
+    connection: &mut I3Connection,
+    backend: Backend,
+    settings: &Settings,
+    created: &mut Vec<i64>,
+) -> Result<String, String> {
+    // Whatever is on the workspace would end up in the test layout, and closing the test's own
+    // containers afterwards would leave the user's changed.
+    check_scratch_workspace(&get_tree(connection)?)?;
+    run_checked(connection, &format!("workspace \"{}\"", SCRATCH_WORKSPACE))?;
+    run_checked(connection, "layout splith")?;
+
+    // `open` creates empty containers, which behave like windows for layout purposes without
+    // needing any application to be started.
+    for _ in 0..2 {
+        run_checked(connection, "open")?;
+        let tree = get_tree(connection)?;
+        let focused = find_focused(&tree).ok_or("could not find the opened container")?;
+        created.push(focused.id);
+    }
+
+    let tree = get_tree(connection)?;
+    let new_node = find_node(created[1], &tree).ok_or("opened container disappeared")?;
+    let commands = plan_commands(
+        &tree,
+        &Extras::new(),
+        &Remembered::new(),
+        new_node,
+        None,
+        settings,
+    )
+    .commands()
+    .ok_or("the planner decided not to resize the test containers")?;
+
+    for command in &commands {
+        run_checked(connection, &command.render(backend))?;
+    }
+
+    let tree = get_tree(connection)?;
+    let node = find_node(created[1], &tree).ok_or("resized container disappeared")?;
+    let parent = find_parent(created[1], &tree).ok_or("could not find the test parent")?;
+    let resize_horizontal = parent.layout == NodeLayout::SplitH;
+    let expected = effective_ratio(&tree, parent, resize_horizontal, settings);
+    let actual = if resize_horizontal {
+        f64::from(node.rect.2) / f64::from(parent.rect.2)
+    } else {
+        f64::from(node.rect.3) / f64::from(parent.rect.3)
+    };
+
+    if (actual - expected).abs() <= TOLERANCE {
+        Ok(format!(
+            "resized to {:.3} of the parent, expected {:.3}",
+            actual, expected
+        ))
+    } else {
+        Err(format!(
+            "resized to {:.3} of the parent, expected {:.3} after running {:?}",
+            actual,
+            expected,
+            render_all(&commands)
+        ))
+    }
+}
+
+/// Fails unless the scratch workspace is missing or empty.
+fn check_scratch_workspace(tree: &Node) -> Result<(), String> {
+    let in_use = workspaces(tree).into_iter().any(|workspace| {
+        workspace.name.as_deref() == Some(SCRATCH_WORKSPACE)
+            && !(workspace.nodes.is_empty() && workspace.floating_nodes.is_empty())
+    });
+    if in_use {
+        return Err(format!(
+            "workspace {:?} isn't empty, close what's on it first",
+            SCRATCH_WORKSPACE
+        ));
+    }
+    Ok(())
+}
+
+fn run_checked(connection: &mut I3Connection, command: &str) -> Result<(), String> {
+    let reply = connection
+        .run_command(command)
+        .map_err(|error| format!("error running {:?}: {}", command, error))?;
+
+    match reply.outcomes.iter().find(|o| !o.success) {
+        Some(outcome) => Err(format!(
+            "i3 rejected {:?}: {}",
+            command,
+            outcome.error.as_deref().unwrap_or("unknown error")
+        )),
+        None => Ok(()),
+    }
+}
+
+fn get_tree(connection: &mut I3Connection) -> Result<Node, String> {
+    connection
+        .get_tree()
+        .map_err(|error| format!("error retrieving the tree: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{tree, window};
+
+    #[test]
+    fn scratch_workspace_must_be_missing_or_empty() {
+        let other = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "Term", (0, 0, 1000, 500))],
+        );
+        assert!(check_scratch_workspace(&other).is_ok());
+
+        let empty = tree(SCRATCH_WORKSPACE, NodeLayout::SplitH, Vec::new());
+        assert!(check_scratch_workspace(&empty).is_ok());
+
+        let in_use = tree(
+            SCRATCH_WORKSPACE,
+            NodeLayout::SplitH,
+            vec![window(100, "Term", (0, 0, 1000, 500))],
+        );
+        assert!(check_scratch_workspace(&in_use).is_err());
+    }
+}
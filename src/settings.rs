@@ -1,76 +1,2602 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
 use ini::{Ini, Properties};
+use regex::Regex;
+
+use crate::backend::Backend;
+use crate::state_store::state_dir;
+use crate::tree::workspace_number;
 use log::LevelFilter;
+use serde::Serialize;
 
 const DEFAULT_RATIO: f64 = 0.33;
-const DEFAULT_LOG_PATH: &str = "~/.config/i3/ratiosplit.log";
+const DEFAULT_TARGET_ASPECT: f64 = 1.6;
+const USER_CONFIG_PATH: &str = "~/.config/i3/ratiosplit.ini";
+const SYSTEM_CONFIG_PATH: &str = "/etc/i3/ratiosplit.ini";
+/// Where the log went before it moved to the state directory. Still used if a log is there.
+const LEGACY_LOG_PATH: &str = "~/.config/i3/ratiosplit.log";
+const LOG_FILE_NAME: &str = "ratiosplit.log";
 const DEFAULT_LOG_FILE_LEVEL: LevelFilter = LevelFilter::Info;
 const DEFAULT_LOG_CONSOLE_LEVEL: LevelFilter = LevelFilter::Off;
+const DEFAULT_REBALANCE_ON_FULLSCREEN_EXIT: bool = false;
+const DEFAULT_MANUAL_COOLDOWN: u64 = 0;
+const DEFAULT_PHASE_DELAY_MS: u64 = 0;
+const DEFAULT_RESIZE_UNIT: ResizeUnit = ResizeUnit::Ppt;
+const DEFAULT_MULTI_CHILD: MultiChild = MultiChild::Skip;
+const DEFAULT_PORTRAIT_AWARE: bool = true;
+const DEFAULT_SUGGEST: bool = false;
+const DEFAULT_SUGGEST_INTERVAL: u64 = 10;
+const DEFAULT_OVERFLOW_LAYOUT: OverflowLayout = OverflowLayout::None;
+const DEFAULT_OVERFLOW_THRESHOLD: usize = 4;
+const DEFAULT_OVERFLOW_RESTORE: bool = false;
+const DEFAULT_QUIET_THRESHOLD: usize = 0;
+const DEFAULT_QUIET_WINDOW_MS: u64 = 1000;
+const DEFAULT_QUIET_SETTLE_MS: u64 = 500;
+const DEFAULT_OVERFLOW: OverflowPolicy = OverflowPolicy::Layout;
+const DEFAULT_MAX_WINDOWS: usize = 0;
+const DEFAULT_FOLLOW_FOCUS: bool = true;
+const DEFAULT_REPAIR_WINDOW_MS: u64 = 500;
+const DEFAULT_PROMOTE_NEW_TO_MASTER: bool = false;
+const DEFAULT_SKIP_IF_FILLS_PARENT: bool = false;
+const DEFAULT_ANIMATE_MS: u64 = 0;
+const DEFAULT_ANIMATE_STEPS: u32 = 4;
+const DEFAULT_MANAGE_TABBED: ManageTabbed = ManageTabbed::Skip;
+const DEFAULT_CLASS_MATCH_TIMEOUT_MS: u64 = 0;
+const DEFAULT_RESIZE_METHOD: ResizeMethod = ResizeMethod::Set;
+const DEFAULT_MODE: Mode = Mode::Spiral;
+const DEFAULT_VERIFY: bool = false;
+const DEFAULT_VERIFY_DELAY_MS: u64 = 100;
+const DEFAULT_VERIFY_RETRIES: u32 = 1;
+const DEFAULT_SPIRAL_SEED: SpiralSeed = SpiralSeed::Inherit;
+const DEFAULT_FOCUS_DELAY_MS: u64 = 150;
+const DEFAULT_RESPECT_RESTORED_LAYOUTS: bool = false;
+const DEFAULT_PRE_COMMAND_FAILURE: PreCommandFailure = PreCommandFailure::Abort;
+const DEFAULT_ON_RESIZE_NOOP: ResizeNoop = ResizeNoop::Ignore;
+const DEFAULT_REASSERT_INTERVAL_SECS: u64 = 0;
+const DEFAULT_ESCAPE_TABBED: bool = false;
+const DEFAULT_TITLE_RATIO_WINDOW_MS: u64 = 2000;
+const DEFAULT_CONFIG_MERGE: bool = false;
+const DEFAULT_NEW_WINDOW_SIDE: NewWindowSide = NewWindowSide::Auto;
+const DEFAULT_SWAP_NEW: bool = false;
+const DEFAULT_COLUMNS: usize = 3;
+const DEFAULT_REFERENCE_MARK: &str = "reference";
+const DEFAULT_COLUMN_RATIOS: &str = "25,50,25";
+const DEFAULT_COLUMN_FILL: ColumnFill = ColumnFill::Emptiest;
+const DEFAULT_ROUNDING: Rounding = Rounding::Nearest;
+const DEFAULT_VERIFY_LOG: bool = false;
+const DEFAULT_MULTI_OUTPUT_CONTAINER: MultiOutput = MultiOutput::Dominant;
+const DEFAULT_NEVER_CHANGE_FOCUS: bool = false;
+const DEFAULT_CONTROL: ControlChannel = ControlChannel::Socket;
+const DEFAULT_RATIO_LOCKS: bool = true;
+const DEFAULT_PAUSE_ON_FULLSCREEN: bool = false;
+const DEFAULT_MANAGE_MOVED_EXISTING: bool = false;
+const DEFAULT_HANDLE_DELAY_MS: u64 = 0;
+const DEFAULT_SETTLE_MS: u64 = 0;
+const DEFAULT_SKIP_URGENT: bool = false;
+const DEFAULT_DEBUG_MARKS: bool = false;
+const DEFAULT_FAILSAFE_THRESHOLD: u32 = 5;
+const DEFAULT_FAILSAFE_NOTIFY: bool = false;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Settings {
+    /// The new window's share of its parent, between 0 and 1.
     pub ratio: f64,
+    /// The ratio used for vertical resizes, or horizontal ones on portrait outputs. Defaults to
+    /// `ratio`.
+    pub ratio_vertical: f64,
+    /// Whether horizontal ratios are derived from each output's shape, from `ratio = auto`.
+    pub auto_ratio: bool,
+    /// How new windows are laid out. Can be switched at runtime with `set-mode`.
+    pub mode: Mode,
+    /// How many columns `mode = columns` keeps each workspace in.
+    pub columns: usize,
+    /// The share of each column, left to right, as fractions of the workspace.
+    pub column_ratios: Vec<f64>,
+    /// Which column `mode = columns` puts new windows in once every column exists.
+    pub column_fill: ColumnFill,
+    /// The mark of the window `mode = match_reference` sizes new windows like.
+    pub reference_mark: String,
+    /// Column ratios for particular outputs, by output name, from `[output_columns]`.
+    pub output_columns: HashMap<String, Vec<f64>>,
+    /// The aspect ratio (width / height) that `ratio = auto` aims for in the larger pane.
+    pub target_aspect: f64,
+    /// The ratios `cycle-ratio` steps through.
+    pub ratio_presets: Vec<f64>,
     pub log_file_level: LevelFilter,
     pub log_file: String,
     pub log_console_level: LevelFilter,
+    pub managed_workspaces: Vec<String>,
+    pub unmanaged_workspaces: Vec<String>,
+    pub rebalance_on_fullscreen_exit: bool,
+    /// Whether to leave new windows alone on an output showing a fullscreen window.
+    pub pause_on_fullscreen: bool,
+    /// Whether new windows asking for attention with the urgency hint are left alone.
+    pub skip_urgent: bool,
+    /// Marks each window ratiosplit handled with `_rs_managed_<con_id>`, for debugging.
+    pub debug_marks: bool,
+    /// Handled windows in a row with commands i3 refused that pause ratiosplit, 0 never does.
+    pub failsafe_threshold: u32,
+    /// Whether pausing for `failsafe_threshold` also sends a desktop notification.
+    pub failsafe_notify: bool,
+    /// Whether windows moved into a container are handled like new ones, even if they were
+    /// already open. Windows ratiosplit hasn't seen before always are.
+    pub manage_moved_existing: bool,
+    /// Whether new windows that already fill nearly all of their parent are left alone.
+    pub skip_if_fills_parent: bool,
+    /// Window classes of dropdown terminals and the like, which are never handled.
+    pub dropdown_classes: Vec<String>,
+    /// Names of processes whose windows are never handled.
+    pub exclude_processes: Vec<String>,
+    /// Windows that are never handled, by role, instance, class or title, in the order
+    /// `RuleMatch::precedence` checks them.
+    pub exclude: Vec<RuleMatch>,
+    /// Whether windows swallowed into placeholders from `append_layout` keep the size the layout gives them.
+    pub respect_restored_layouts: bool,
+    /// Window classes whose windows always split in a fixed direction, from `[split_direction]`.
+    pub split_directions: HashMap<String, SplitDirection>,
+    /// Window classes whose windows always take the same side of their container, from
+    /// `[position]`.
+    pub positions: HashMap<String, Position>,
+    /// Window classes whose windows are sized to a fixed width / height, from `[aspect]`.
+    pub aspects: HashMap<String, f64>,
+    /// Ratios for windows whose titles match a pattern, in order, from `[title_ratios]`.
+    pub title_ratios: Vec<TitleRatio>,
+    /// Ratios for windows with a given role (`WM_WINDOW_ROLE`), from `[role_ratios]`.
+    pub role_ratios: HashMap<String, f64>,
+    /// Ratios for windows with a given instance, the first part of `WM_CLASS`, from
+    /// `[instance_ratios]`.
+    pub instance_ratios: HashMap<String, f64>,
+    /// Settings for particular workspaces, from `[workspace ...]` sections.
+    pub workspace_overrides: Vec<WorkspaceOverride>,
+    /// Seconds to leave a workspace alone after the user changes its layout by hand.
+    pub manual_cooldown: u64,
+    /// Seconds between sweeps that put every managed workspace back to the ratios. 0 disables them.
+    pub reassert_interval_secs: u64,
+    /// How long to wait for a new window without a class to get one, when rules based
+    /// on the class exist. 0 handles such windows straight away.
+    pub class_match_timeout_ms: u64,
+    /// How long to wait after a window opens before handling it. 0 handles windows straight away.
+    pub handle_delay_ms: u64,
+    /// How long after a new window is handled a title change can still give it a `[title_ratios]` ratio.
+    pub title_ratio_window_ms: u64,
+    /// Milliseconds to wait between the split commands and the resize, for i3s that need a
+    /// moment before a resize after a split takes effect.
+    pub phase_delay_ms: u64,
+    /// How long after handling a new window a Floating or Move event for it repairs the container
+    /// it left. 0 disables repairs.
+    pub repair_window_ms: u64,
+    /// How long new windows take to resize, in `animate_steps` steps. 0 resizes instantly.
+    pub animate_ms: u64,
+    pub animate_steps: u32,
+    /// With `mode = focus_master`, how long focus has to stay on a window before it's enlarged.
+    pub focus_delay_ms: u64,
+    /// Whether to check, shortly after resizing a new window, that it ended up at the ratio.
+    pub verify: bool,
+    pub verify_delay_ms: u64,
+    pub verify_retries: u32,
+    /// Whether to log each handled window's container before and after its commands ran.
+    pub verify_log: bool,
+    /// How long after resizing a new window to check once whether the app resized it back, and
+    /// resize it again if so. 0 doesn't check.
+    pub settle_ms: u64,
+    pub resize_unit: ResizeUnit,
+    /// What to do with containers that span more than one output.
+    pub multi_output_container: MultiOutput,
+    /// How ratios are rounded to whole ppt.
+    pub rounding: Rounding,
+    pub resize_method: ResizeMethod,
+    /// What to do when i3 refuses to resize a window.
+    pub on_resize_noop: ResizeNoop,
+    /// What to do when a new window lands in a container with more than two children.
+    pub multi_child: MultiChild,
+    /// What to do for new windows by how many tiled windows their workspace holds, in order.
+    pub count_policy: Vec<CountRule>,
+    /// What to do when a new window lands in a tabbed or stacked container.
+    pub manage_tabbed: ManageTabbed,
+    /// Whether new windows that open in a tab group are moved out beside it.
+    pub escape_tabbed: bool,
+    /// Windows carrying this mark keep their size when multi-child containers are equalized.
+    pub anchor_mark: Option<String>,
+    /// Whether containers marked `ratio_lock:<percent>` are kept at that share.
+    pub ratio_locks: bool,
+    /// Whether the spiral starts vertically on portrait outputs, with the ratios swapped.
+    pub portrait_aware: bool,
+    /// The direction the first split of every workspace is forced to, if any.
+    pub spiral_seed: SpiralSeed,
+    /// Whether new windows are swapped into the first position of their container before resizing.
+    pub promote_new_to_master: bool,
+    /// Where new windows end up in their container, unless a `[position]` rule says otherwise.
+    pub new_window_side: NewWindowSide,
+    /// Whether new windows swap places with their only sibling.
+    pub swap_new: bool,
+    /// i3 commands to run before a new window is arranged, with the same placeholders as
+    /// `post_resize_command`.
+    pub pre_split_command: Option<String>,
+    /// Whether a new window is still arranged when `pre_split_command` fails.
+    pub pre_command_failure: PreCommandFailure,
+    /// i3 commands to run after a new window is arranged, with `{con_id}`, `{class}` and
+    /// `{workspace}` filled in from the window.
+    pub post_resize_command: Option<String>,
+    /// A script to run for the events in `hook_events`.
+    pub hook_script: Option<String>,
+    pub hook_events: Vec<String>,
+    /// When set, never run any commands, only log what would be done to fix drifted workspaces.
+    pub suggest: bool,
+    /// Minutes between suggestion reports in suggest mode. 0 only reports on demand.
+    pub suggest_interval: u64,
+    /// When set, the daemon exits rather than running against any other backend.
+    pub backend_guard: Option<Backend>,
+    /// Whether the user config was layered over the system-wide one rather than replacing it.
+    pub config_merge: bool,
+    /// How crowded containers and workspaces are handled.
+    pub overflow: OverflowPolicy,
+    /// With `overflow = move`, how many tiled windows a workspace holds before new ones are moved
+    /// to an empty workspace. 0 disables it.
+    pub max_windows: usize,
+    /// Whether focus follows windows moved by `overflow = move`.
+    pub follow_focus: bool,
+    /// Whether to skip anything that would move focus off the focused window.
+    pub never_change_focus: bool,
+    /// Which control channels to listen on.
+    pub control: ControlChannel,
+    /// The layout a container switches to instead of splitting once it holds `overflow_threshold`
+    /// windows.
+    pub overflow_layout: OverflowLayout,
+    pub overflow_threshold: usize,
+    /// Whether containers switched to `overflow_layout` go back to splitting when windows close.
+    pub overflow_restore: bool,
+    /// How many window events within `quiet_window_ms` put the daemon into quiet mode, holding off
+    /// until activity settles. 0 disables it.
+    pub quiet_threshold: usize,
+    pub quiet_window_ms: u64,
+    /// How long events must stop for before quiet mode ends.
+    pub quiet_settle_ms: u64,
 }
 
-pub fn load_settings() -> Settings {
-    let conf_file = match Ini::load_from_file(
-        shellexpand::full("~/.config/i3/ratiosplit.ini")
-            .unwrap()
-            .to_string(),
-    ) {
-        Ok(file) => file,
-        Err(err) => {
-            println!("Error {:?} loading settings, using defaults", err);
-            return default_settings();
+/// How new windows are laid out.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Alternate split directions, giving each new window `ratio` of its container.
+    Spiral,
+    /// Give every window in the new window's container an equal share.
+    Equalize,
+    /// Give the first window in the container `1 - ratio`, and the rest equal shares of `ratio`.
+    Master,
+    /// Spiral, but equalize containers that end up with more than two windows.
+    Dynamic,
+    /// Spiral, but give whichever of two windows has focus `1 - ratio` of their container.
+    FocusMaster,
+    /// Keep each workspace in columns, with widths from `column_ratios`.
+    Columns,
+    /// Spiral, but size new windows like the window marked `reference_mark`.
+    MatchReference,
+    /// Spiral, but give each container the split it was last resized to by hand.
+    Remember,
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spiral" => Ok(Mode::Spiral),
+            "equalize" => Ok(Mode::Equalize),
+            "master" => Ok(Mode::Master),
+            "dynamic" => Ok(Mode::Dynamic),
+            "focus_master" => Ok(Mode::FocusMaster),
+            "columns" => Ok(Mode::Columns),
+            "match_reference" => Ok(Mode::MatchReference),
+            "remember" => Ok(Mode::Remember),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Spiral => write!(f, "spiral"),
+            Mode::Equalize => write!(f, "equalize"),
+            Mode::Master => write!(f, "master"),
+            Mode::Dynamic => write!(f, "dynamic"),
+            Mode::FocusMaster => write!(f, "focus_master"),
+            Mode::Columns => write!(f, "columns"),
+            Mode::MatchReference => write!(f, "match_reference"),
+            Mode::Remember => write!(f, "remember"),
+        }
+    }
+}
+
+/// What to do with a new window when `pre_split_command` fails.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PreCommandFailure {
+    /// Leave the window alone.
+    Abort,
+    /// Arrange the window anyway.
+    Continue,
+}
+
+impl FromStr for PreCommandFailure {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(PreCommandFailure::Abort),
+            "continue" => Ok(PreCommandFailure::Continue),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What to do when i3 refuses a resize.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeNoop {
+    /// Log it and carry on.
+    Ignore,
+    /// Resize the sibling to the rest of the container instead.
+    RetrySibling,
+}
+
+impl FromStr for ResizeNoop {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(ResizeNoop::Ignore),
+            "retry_sibling" => Ok(ResizeNoop::RetrySibling),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether resizes set a size outright, or grow and shrink by the difference from the current one.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeMethod {
+    Set,
+    Relative,
+}
+
+impl FromStr for ResizeMethod {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "set" => Ok(ResizeMethod::Set),
+            "relative" => Ok(ResizeMethod::Relative),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The side of its container a window is kept on.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Position {
+    /// The split direction that has this side.
+    pub fn direction(self) -> SplitDirection {
+        match self {
+            Position::Left | Position::Right => SplitDirection::Horizontal,
+            Position::Top | Position::Bottom => SplitDirection::Vertical,
+        }
+    }
+
+    /// Whether this side comes first in its container.
+    pub fn is_first(self) -> bool {
+        matches!(self, Position::Left | Position::Top)
+    }
+}
+
+impl FromStr for Position {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Position::Left),
+            "right" => Ok(Position::Right),
+            "top" => Ok(Position::Top),
+            "bottom" => Ok(Position::Bottom),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where new windows are put in their container.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NewWindowSide {
+    /// Wherever i3 put it, usually right after the focused window.
+    Auto,
+    /// After all its siblings, so the spiral grows towards the bottom right.
+    RightBottom,
+    /// Before all its siblings, so the spiral grows towards the top left.
+    LeftTop,
+}
+
+impl FromStr for NewWindowSide {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(NewWindowSide::Auto),
+            "right_bottom" => Ok(NewWindowSide::RightBottom),
+            "left_top" => Ok(NewWindowSide::LeftTop),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Which column `mode = columns` puts a new window in, once every column exists.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnFill {
+    /// The column holding the fewest windows, the leftmost of those on a tie.
+    Emptiest,
+    /// Each column in turn, from the left.
+    RoundRobin,
+}
+
+impl FromStr for ColumnFill {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "emptiest" => Ok(ColumnFill::Emptiest),
+            "round_robin" => Ok(ColumnFill::RoundRobin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What to do with a container that spans more than one output.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiOutput {
+    /// Leave windows in it alone.
+    Skip,
+    /// Resize in ppt of the container, whatever `resize_unit` says.
+    Dominant,
+}
+
+impl FromStr for MultiOutput {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MultiOutput::Skip),
+            "dominant" => Ok(MultiOutput::Dominant),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where the daemon takes control commands from.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlChannel {
+    /// The control socket, which answers every request.
+    Socket,
+    /// A named pipe, whose requests are only answered in the log.
+    Fifo,
+    Both,
+}
+
+impl ControlChannel {
+    pub fn socket(self) -> bool {
+        self != ControlChannel::Fifo
+    }
+
+    pub fn fifo(self) -> bool {
+        self != ControlChannel::Socket
+    }
+}
+
+impl FromStr for ControlChannel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "socket" => Ok(ControlChannel::Socket),
+            "fifo" => Ok(ControlChannel::Fifo),
+            "both" => Ok(ControlChannel::Both),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a ratio is rounded to whole ppt.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// To the nearest ppt, halves rounding up.
+    Nearest,
+    Floor,
+    Ceil,
+}
+
+impl FromStr for Rounding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Rounding::Nearest),
+            "floor" => Ok(Rounding::Floor),
+            "ceil" => Ok(Rounding::Ceil),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What `count_policy` does for a new window.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CountAction {
+    /// Leave the window alone.
+    None,
+    /// Lay the window out according to `mode`.
+    Ratio,
+    /// Give every window in the new window's container an equal share.
+    Equalize,
+}
+
+impl FromStr for CountAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(CountAction::None),
+            "ratio" => Ok(CountAction::Ratio),
+            "equalize" => Ok(CountAction::Equalize),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A `count_policy` rule: the action for workspaces holding exactly `count` tiled windows, or
+/// `count` or more with `or_more`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CountRule {
+    pub count: usize,
+    pub or_more: bool,
+    pub action: CountAction,
+}
+
+impl FromStr for CountRule {
+    type Err = ();
+
+    /// Parses `COUNT:ACTION` or `COUNT+:ACTION`, like `3+:equalize`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, action) = s.split_once(':').ok_or(())?;
+        let count = count.trim();
+        let (count, or_more) = match count.strip_suffix('+') {
+            Some(count) => (count, true),
+            None => (count, false),
+        };
+        Ok(CountRule {
+            count: count.parse().map_err(|_| ())?,
+            or_more,
+            action: action.trim().parse()?,
+        })
+    }
+}
+
+/// A ratio for windows whose title matches a pattern.
+#[derive(Debug, Clone, Serialize)]
+pub struct TitleRatio {
+    pub pattern: String,
+    #[serde(skip)]
+    pub regex: Regex,
+    pub ratio: f64,
+}
+
+/// Which workspaces a `[workspace ...]` section applies to.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkspaceSelector {
+    /// `[workspace name=web]`, the workspace with exactly this name.
+    Name(String),
+    /// `[workspace num=2]`, the workspace with this number, whatever follows it in its name.
+    Number(i32),
+    /// `[workspace pattern=^web]`, workspaces whose names match a regular expression.
+    Pattern {
+        pattern: String,
+        #[serde(skip)]
+        regex: Regex,
+    },
+}
+
+impl WorkspaceSelector {
+    /// Where matches with this selector come, lowest first: an exact name, then a number, then a
+    /// pattern.
+    fn precedence(&self) -> u8 {
+        match self {
+            WorkspaceSelector::Name(_) => 0,
+            WorkspaceSelector::Number(_) => 1,
+            WorkspaceSelector::Pattern { .. } => 2,
+        }
+    }
+
+    fn matches(&self, number: Option<i32>, name: Option<&str>) -> bool {
+        match self {
+            WorkspaceSelector::Name(wanted) => name == Some(wanted.as_str()),
+            WorkspaceSelector::Number(wanted) => number == Some(*wanted),
+            WorkspaceSelector::Pattern { regex, .. } => {
+                name.is_some_and(|name| regex.is_match(name))
+            }
+        }
+    }
+}
+
+/// Parses the part of a section name after `workspace`, like `num=2` or `name="2: web"`.
+impl FromStr for WorkspaceSelector {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s.split_once('=').ok_or(())?;
+        let value = value.trim().trim_matches('"');
+        if value.is_empty() {
+            return Err(());
+        }
+        match key.trim() {
+            "name" => Ok(WorkspaceSelector::Name(value.to_string())),
+            "num" => value.parse().map(WorkspaceSelector::Number).map_err(|_| ()),
+            "pattern" => Ok(WorkspaceSelector::Pattern {
+                pattern: value.to_string(),
+                regex: Regex::new(value).map_err(|_| ())?,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for WorkspaceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkspaceSelector::Name(name) => write!(f, "[workspace name={:?}]", name),
+            WorkspaceSelector::Number(number) => write!(f, "[workspace num={}]", number),
+            WorkspaceSelector::Pattern { pattern, .. } => {
+                write!(f, "[workspace pattern={:?}]", pattern)
+            }
+        }
+    }
+}
+
+/// Settings that replace the global ones on some workspaces.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceOverride {
+    pub selector: WorkspaceSelector,
+    pub ratio: Option<f64>,
+    pub mode: Option<Mode>,
+}
+
+/// The `[workspace ...]` section for the workspace with the given number and name, if any. An
+/// exact name wins over a number, which wins over a pattern; patterns are tried in the order
+/// they're written.
+pub fn resolve_workspace<'a>(
+    overrides: &'a [WorkspaceOverride],
+    number: Option<i32>,
+    name: Option<&str>,
+) -> Option<&'a WorkspaceOverride> {
+    overrides
+        .iter()
+        .filter(|o| o.selector.matches(number, name))
+        .min_by_key(|o| o.selector.precedence())
+}
+
+/// What rules and exclusions can tell about a window. Any of it may be missing, and a missing
+/// property never matches.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WindowIdentity<'a> {
+    pub role: Option<&'a str>,
+    pub instance: Option<&'a str>,
+    pub class: Option<&'a str>,
+    pub title: Option<&'a str>,
+}
+
+/// A rule sizing new windows it matches, from `[role_ratios]`, `[instance_ratios]`, `[aspect]` or
+/// `[title_ratios]`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SizingRule {
+    /// Where the rule comes in the order rules are checked, starting at 1.
+    pub priority: usize,
+    pub section: &'static str,
+    #[serde(rename = "match")]
+    pub matcher: RuleMatch,
+    pub size: Sizing,
+}
+
+impl fmt::Display for SizingRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.section, self.matcher)
+    }
+}
+
+/// What a `SizingRule` checks new windows for.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleMatch {
+    Class(String),
+    Title {
+        pattern: String,
+        #[serde(skip)]
+        regex: Regex,
+    },
+    Role(String),
+    Instance(String),
+}
+
+impl RuleMatch {
+    pub fn matches(&self, window: &WindowIdentity) -> bool {
+        match self {
+            RuleMatch::Class(wanted) => window.class == Some(wanted.as_str()),
+            RuleMatch::Title { regex, .. } => {
+                window.title.is_some_and(|title| regex.is_match(title))
+            }
+            RuleMatch::Role(wanted) => window.role == Some(wanted.as_str()),
+            RuleMatch::Instance(wanted) => window.instance == Some(wanted.as_str()),
+        }
+    }
+
+    /// Where matches on this property come when several could apply, lowest first: role, then
+    /// instance, then class, then title. The more specific a property, the earlier it comes.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            RuleMatch::Role(_) => 0,
+            RuleMatch::Instance(_) => 1,
+            RuleMatch::Class(_) => 2,
+            RuleMatch::Title { .. } => 3,
+        }
+    }
+}
+
+impl fmt::Display for RuleMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleMatch::Class(class) => write!(f, "class = {}", class),
+            RuleMatch::Title { pattern, .. } => write!(f, "title ~ {}", pattern),
+            RuleMatch::Role(role) => write!(f, "role = {}", role),
+            RuleMatch::Instance(instance) => write!(f, "instance = {}", instance),
+        }
+    }
+}
+
+/// Parses `exclude` entries: `role:`, `instance:` or `class:` followed by the exact value, or
+/// `title:` followed by a regular expression.
+impl FromStr for RuleMatch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.split_once(':').ok_or(())?;
+        let value = value.trim();
+        if value.is_empty() {
+            return Err(());
+        }
+        match kind.trim() {
+            "role" => Ok(RuleMatch::Role(value.to_string())),
+            "instance" => Ok(RuleMatch::Instance(value.to_string())),
+            "class" => Ok(RuleMatch::Class(value.to_string())),
+            "title" => Ok(RuleMatch::Title {
+                pattern: value.to_string(),
+                regex: Regex::new(value).map_err(|_| ())?,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How a `SizingRule` sizes the windows it matches.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sizing {
+    /// Sized to this width over height, when that fits in the window's container.
+    Aspect(f64),
+    /// Given this share of its container instead of `ratio`.
+    Ratio(f64),
+}
+
+impl fmt::Display for Sizing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sizing::Aspect(aspect) => write!(f, "aspect {:.2}", aspect),
+            Sizing::Ratio(ratio) => write!(f, "ratio {:.2}", ratio),
+        }
+    }
+}
+
+/// Which direction the spiral starts in on each workspace.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpiralSeed {
+    /// Whatever the workspace is already split in, usually i3's default.
+    Inherit,
+    Horizontal,
+    Vertical,
+}
+
+impl FromStr for SpiralSeed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inherit" => Ok(SpiralSeed::Inherit),
+            "horizontal" => Ok(SpiralSeed::Horizontal),
+            "vertical" => Ok(SpiralSeed::Vertical),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether resizes are expressed as a percentage of the parent or in pixels.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeUnit {
+    Ppt,
+    Px,
+}
+
+impl FromStr for ResizeUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ppt" => Ok(ResizeUnit::Ppt),
+            "px" => Ok(ResizeUnit::Px),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MultiChild {
+    /// Leave the container alone.
+    Skip,
+    /// Give every child an equal share, except anchored ones.
+    Equalize,
+}
+
+impl FromStr for MultiChild {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(MultiChild::Skip),
+            "equalize" => Ok(MultiChild::Equalize),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManageTabbed {
+    /// Leave the container alone.
+    Skip,
+    /// Switch the container to a split layout, then handle the window as usual.
+    Convert,
+}
+
+impl FromStr for ManageTabbed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ManageTabbed::Skip),
+            "convert" => Ok(ManageTabbed::Convert),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What happens to a new window that would make a container or workspace too crowded.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Switch crowded containers to `overflow_layout`.
+    Layout,
+    /// Move new windows on workspaces holding `max_windows` to an empty workspace.
+    Move,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "layout" => Ok(OverflowPolicy::Layout),
+            "move" => Ok(OverflowPolicy::Move),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The layout a container switches to when it holds too many windows to split usefully.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowLayout {
+    /// Keep splitting, or skipping, however many windows there are.
+    None,
+    Stacked,
+    Tabbed,
+}
+
+impl FromStr for OverflowLayout {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(OverflowLayout::None),
+            "stacked" => Ok(OverflowLayout::Stacked),
+            "tabbed" => Ok(OverflowLayout::Tabbed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+impl Settings {
+    /// Switches to a fixed ratio for both axes, used when a ratio is chosen at runtime.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio;
+        self.ratio_vertical = ratio;
+        self.auto_ratio = false;
+    }
+
+    /// Whether any settings depend on a window's class, instance or role, which apps set along
+    /// with each other, so that a window without a class has none of them yet either.
+    pub fn has_class_rules(&self) -> bool {
+        !self.split_directions.is_empty()
+            || !self.positions.is_empty()
+            || !self.aspects.is_empty()
+            || !self.dropdown_classes.is_empty()
+            || !self.instance_ratios.is_empty()
+            || !self.role_ratios.is_empty()
+            || self.exclude.iter().any(|matcher| {
+                matches!(
+                    matcher,
+                    RuleMatch::Class(_) | RuleMatch::Instance(_) | RuleMatch::Role(_)
+                )
+            })
+    }
+
+    /// The `count_policy` action for a workspace holding `windows` tiled windows: the first rule
+    /// that covers it, or the last rule when none does. `None` without a policy.
+    pub fn count_action(&self, windows: usize) -> Option<CountAction> {
+        self.count_policy
+            .iter()
+            .find(|rule| windows == rule.count || (rule.or_more && windows > rule.count))
+            .or_else(|| self.count_policy.last())
+            .map(|rule| rule.action)
+    }
+
+    /// The column ratios for the given output: its `[output_columns]` entry, or `column_ratios`.
+    pub fn column_ratios(&self, output: Option<&str>) -> &[f64] {
+        output
+            .and_then(|output| self.output_columns.get(output))
+            .unwrap_or(&self.column_ratios)
+    }
+
+    /// Every rule sizing new windows, in the order they're checked, following
+    /// `RuleMatch::precedence`: `[role_ratios]`, then `[instance_ratios]`, then `[aspect]`, then
+    /// `[title_ratios]` in the order they're written. The others are sorted by name, since only
+    /// one of each section can match a window.
+    pub fn sizing_rules(&self) -> Vec<SizingRule> {
+        let mut aspects: Vec<_> = self.aspects.iter().collect();
+        aspects.sort_by(|a, b| a.0.cmp(b.0));
+        let mut roles: Vec<_> = self.role_ratios.iter().collect();
+        roles.sort_by(|a, b| a.0.cmp(b.0));
+        let mut instances: Vec<_> = self.instance_ratios.iter().collect();
+        instances.sort_by(|a, b| a.0.cmp(b.0));
+
+        let aspects = aspects.into_iter().map(|(class, &aspect)| {
+            (
+                "aspect",
+                RuleMatch::Class(class.clone()),
+                Sizing::Aspect(aspect),
+            )
+        });
+        let titles = self.title_ratios.iter().map(|rule| {
+            (
+                "title_ratios",
+                RuleMatch::Title {
+                    pattern: rule.pattern.clone(),
+                    regex: rule.regex.clone(),
+                },
+                Sizing::Ratio(rule.ratio),
+            )
+        });
+        let roles = roles.into_iter().map(|(role, &ratio)| {
+            (
+                "role_ratios",
+                RuleMatch::Role(role.clone()),
+                Sizing::Ratio(ratio),
+            )
+        });
+
+        let instances = instances.into_iter().map(|(instance, &ratio)| {
+            (
+                "instance_ratios",
+                RuleMatch::Instance(instance.clone()),
+                Sizing::Ratio(ratio),
+            )
+        });
+
+        roles
+            .chain(instances)
+            .chain(aspects)
+            .chain(titles)
+            .enumerate()
+            .map(|(i, (section, matcher, size))| SizingRule {
+                priority: i + 1,
+                section,
+                matcher,
+                size,
+            })
+            .collect()
+    }
+
+    /// The rules from `sizing_rules` that match a window, in the same order.
+    pub fn matching_rules(&self, window: &WindowIdentity) -> Vec<SizingRule> {
+        self.sizing_rules()
+            .into_iter()
+            .filter(|rule| rule.matcher.matches(window))
+            .collect()
+    }
+
+    /// The first `exclude` entry the window matches, if any.
+    pub fn excluded_by(&self, window: &WindowIdentity) -> Option<&RuleMatch> {
+        self.exclude.iter().find(|matcher| matcher.matches(window))
+    }
+
+    /// The ratio of the first `[title_ratios]` pattern the given title matches, if any.
+    pub fn title_ratio(&self, title: &str) -> Option<f64> {
+        self.title_ratios
+            .iter()
+            .find(|rule| rule.regex.is_match(title))
+            .map(|rule| rule.ratio)
+    }
+
+    /// A short description of the configured ratios, for status output.
+    pub fn ratio_description(&self) -> String {
+        let horizontal = if self.auto_ratio {
+            "auto".to_string()
+        } else {
+            format!("{:.2}", self.ratio)
+        };
+
+        if self.auto_ratio || (self.ratio - self.ratio_vertical).abs() > f64::EPSILON {
+            format!("{}/{:.2}", horizontal, self.ratio_vertical)
+        } else {
+            horizontal
+        }
+    }
+
+    /// Whether ratiosplit should touch windows on the workspace with the given name. When
+    /// `managed_workspaces` is non-empty, only those workspaces are managed.
+    pub fn is_workspace_managed(&self, name: &str) -> bool {
+        if !self.managed_workspaces.is_empty() && !self.managed_workspaces.iter().any(|w| w == name)
+        {
+            return false;
         }
+
+        !self.unmanaged_workspaces.iter().any(|w| w == name)
+    }
+
+    /// These settings as they apply on the workspace with the given name, with its
+    /// `[workspace ...]` section, if it has one, replacing the global `ratio` and `mode`.
+    pub fn for_workspace(&self, name: Option<&str>) -> Cow<'_, Settings> {
+        let number = name.and_then(workspace_number);
+        let workspace = match resolve_workspace(&self.workspace_overrides, number, name) {
+            Some(workspace) => workspace,
+            None => return Cow::Borrowed(self),
+        };
+        trace!("Using {} for workspace {:?}", workspace.selector, name);
+        let mut settings = self.clone();
+        if let Some(ratio) = workspace.ratio {
+            settings.set_ratio(ratio);
+        }
+        if let Some(mode) = workspace.mode {
+            settings.mode = mode;
+        }
+        Cow::Owned(settings)
+    }
+
+    /// Carries `managed_workspaces` and `unmanaged_workspaces` entries for a workspace over to
+    /// its new name when it's renamed. Entries the config already has for the new name win.
+    pub fn rename_workspace(&mut self, old: &str, new: &str) {
+        let listed = |list: &[String], name: &str| list.iter().any(|w| w == name);
+        if listed(&self.managed_workspaces, new) || listed(&self.unmanaged_workspaces, new) {
+            return;
+        }
+        if listed(&self.managed_workspaces, old) {
+            self.managed_workspaces.push(new.to_string());
+        }
+        if listed(&self.unmanaged_workspaces, old) {
+            self.unmanaged_workspaces.push(new.to_string());
+        }
+    }
+}
+
+/// The formats a config can be written in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Ini,
+}
+
+/// A problem with a config. `Invalid` values are replaced by their defaults, while the others
+/// keep the whole config from being used.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A value that can't be used, and what was done instead.
+    Invalid(String),
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file isn't valid in its format.
+    Parse(String),
+    /// The config has no `[main]` section.
+    NoMainSection,
+}
+
+impl ConfigError {
+    /// Whether the error kept the whole config from being used.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, ConfigError::Invalid(_))
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Error reading the config: {}", error),
+            ConfigError::Parse(error) => write!(f, "Error parsing the config: {}", error),
+            ConfigError::NoMainSection => write!(f, "No main section found in config"),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Loads the settings from the config files, or the defaults when there are none or they can't
+/// be used. Returns the problems found along the way for the caller to report, since the logger
+/// can only be set up from the settings.
+pub fn load_settings() -> (Settings, Vec<ConfigError>) {
+    let mut problems = Vec::new();
+    let conf_file = match load_config(&mut problems) {
+        Some(file) => file,
+        None => return (default_settings(), problems),
     };
 
-    let main_section = match conf_file.section(Some("main")) {
-        Some(s) => s,
-        None => {
-            println!("No main section found in config, using defaults");
-            return default_settings();
+    match settings_from_ini(&conf_file) {
+        Ok((settings, warnings)) => {
+            problems.extend(warnings);
+            (settings, problems)
+        }
+        Err(error) => {
+            problems.push(error);
+            (default_settings(), problems)
+        }
+    }
+}
+
+/// Parses settings from the contents of a config file, without reading any file, along with the
+/// values that had to be replaced by defaults.
+pub fn parse_settings_from_str(
+    contents: &str,
+    format: Format,
+) -> Result<(Settings, Vec<ConfigError>), ConfigError> {
+    let conf_file = match format {
+        Format::Ini => {
+            Ini::load_from_str(contents).map_err(|error| ConfigError::Parse(error.to_string()))?
         }
     };
+    settings_from_ini(&conf_file)
+}
 
-    let ratio = match main_section.get("ratio") {
-        Some(ratio_string) => ratio_string.parse::<f64>().unwrap_or(DEFAULT_RATIO),
-        None => DEFAULT_RATIO,
+/// Reads and parses the config file at the given path, on its own.
+pub fn load_settings_from_file(path: &str) -> Result<(Settings, Vec<ConfigError>), ConfigError> {
+    let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    parse_settings_from_str(&contents, Format::Ini)
+}
+
+fn settings_from_ini(conf_file: &Ini) -> Result<(Settings, Vec<ConfigError>), ConfigError> {
+    let mut warnings = Vec::new();
+    let main_section = conf_file
+        .section(Some("main"))
+        .ok_or(ConfigError::NoMainSection)?;
+
+    let auto_ratio = main_section.get("ratio") == Some("auto");
+    let ratio = if auto_ratio {
+        DEFAULT_RATIO
+    } else {
+        get_ratio(&mut warnings, main_section, "ratio", DEFAULT_RATIO)
+    };
+    let ratio_vertical = get_ratio(&mut warnings, main_section, "ratio_vertical", ratio);
+    let ratio_presets = get_list(main_section, "ratio_presets")
+        .iter()
+        .filter_map(|preset| match preset.parse::<f64>() {
+            Ok(r) if r > 0.0 && r < 1.0 => Some(r),
+            _ => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "Ratio preset {:?} must be a number between 0 and 1, ignoring",
+                    preset
+                )));
+                None
+            }
+        })
+        .collect();
+    let target_aspect = match get_parsed(
+        &mut warnings,
+        main_section,
+        "target_aspect",
+        DEFAULT_TARGET_ASPECT,
+    ) {
+        a if a > 0.0 => a,
+        _ => DEFAULT_TARGET_ASPECT,
     };
 
     let log_file = main_section
         .get("log_file")
-        .unwrap_or(DEFAULT_LOG_PATH)
+        .and_then(|path| expand_path(&mut warnings, "log_file", path))
+        .unwrap_or_else(default_log_file);
+
+    let log_file_level = get_parsed(
+        &mut warnings,
+        main_section,
+        "log_file_level",
+        DEFAULT_LOG_FILE_LEVEL,
+    );
+    let log_console_level = get_parsed(
+        &mut warnings,
+        main_section,
+        "log_console_level",
+        DEFAULT_LOG_CONSOLE_LEVEL,
+    );
+    let managed_workspaces = get_list(main_section, "managed_workspaces");
+    let unmanaged_workspaces = get_list(main_section, "unmanaged_workspaces");
+    let rebalance_on_fullscreen_exit = get_bool(
+        &mut warnings,
+        main_section,
+        "rebalance_on_fullscreen_exit",
+        DEFAULT_REBALANCE_ON_FULLSCREEN_EXIT,
+    );
+
+    let manual_cooldown = get_parsed(
+        &mut warnings,
+        main_section,
+        "manual_cooldown",
+        DEFAULT_MANUAL_COOLDOWN,
+    );
+    let phase_delay_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "phase_delay_ms",
+        DEFAULT_PHASE_DELAY_MS,
+    );
+    let resize_unit = get_parsed(
+        &mut warnings,
+        main_section,
+        "resize_unit",
+        DEFAULT_RESIZE_UNIT,
+    );
+    let multi_child = get_parsed(
+        &mut warnings,
+        main_section,
+        "multi_child",
+        DEFAULT_MULTI_CHILD,
+    );
+    let anchor_mark = main_section.get("anchor_mark").map(String::from);
+    let portrait_aware = get_bool(
+        &mut warnings,
+        main_section,
+        "portrait_aware",
+        DEFAULT_PORTRAIT_AWARE,
+    );
+    let suggest = get_bool(&mut warnings, main_section, "suggest", DEFAULT_SUGGEST);
+    let suggest_interval = get_parsed(
+        &mut warnings,
+        main_section,
+        "suggest_interval",
+        DEFAULT_SUGGEST_INTERVAL,
+    );
+    let backend_guard =
+        main_section
+            .get("backend_guard")
+            .and_then(|backend| match backend.parse() {
+                Ok(backend) => Some(backend),
+                Err(()) => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "backend_guard {:?} must be i3 or sway, running on any backend",
+                        backend
+                    )));
+                    None
+                }
+            });
+    let overflow_layout = get_parsed(
+        &mut warnings,
+        main_section,
+        "overflow_layout",
+        DEFAULT_OVERFLOW_LAYOUT,
+    );
+    let overflow_threshold = get_parsed(
+        &mut warnings,
+        main_section,
+        "overflow_threshold",
+        DEFAULT_OVERFLOW_THRESHOLD,
+    )
+    .max(2);
+    let overflow_restore = get_bool(
+        &mut warnings,
+        main_section,
+        "overflow_restore",
+        DEFAULT_OVERFLOW_RESTORE,
+    );
+    let quiet_threshold = get_parsed(
+        &mut warnings,
+        main_section,
+        "quiet_threshold",
+        DEFAULT_QUIET_THRESHOLD,
+    );
+    let quiet_window_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "quiet_window_ms",
+        DEFAULT_QUIET_WINDOW_MS,
+    );
+    let quiet_settle_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "quiet_settle_ms",
+        DEFAULT_QUIET_SETTLE_MS,
+    );
+    let overflow = get_parsed(&mut warnings, main_section, "overflow", DEFAULT_OVERFLOW);
+    let max_windows = get_parsed(
+        &mut warnings,
+        main_section,
+        "max_windows",
+        DEFAULT_MAX_WINDOWS,
+    );
+    let follow_focus = get_bool(
+        &mut warnings,
+        main_section,
+        "follow_focus",
+        DEFAULT_FOLLOW_FOCUS,
+    );
+    let repair_window_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "repair_window_ms",
+        DEFAULT_REPAIR_WINDOW_MS,
+    );
+    let promote_new_to_master = get_bool(
+        &mut warnings,
+        main_section,
+        "promote_new_to_master",
+        DEFAULT_PROMOTE_NEW_TO_MASTER,
+    );
+    let skip_if_fills_parent = get_bool(
+        &mut warnings,
+        main_section,
+        "skip_if_fills_parent",
+        DEFAULT_SKIP_IF_FILLS_PARENT,
+    );
+    let animate_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "animate_ms",
+        DEFAULT_ANIMATE_MS,
+    );
+    let animate_steps = get_parsed(
+        &mut warnings,
+        main_section,
+        "animate_steps",
+        DEFAULT_ANIMATE_STEPS,
+    );
+    let manage_tabbed = get_parsed(
+        &mut warnings,
+        main_section,
+        "manage_tabbed",
+        DEFAULT_MANAGE_TABBED,
+    );
+    let class_match_timeout_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "class_match_timeout_ms",
+        DEFAULT_CLASS_MATCH_TIMEOUT_MS,
+    );
+    let resize_method = get_parsed(
+        &mut warnings,
+        main_section,
+        "resize_method",
+        DEFAULT_RESIZE_METHOD,
+    );
+    let mode = get_parsed(&mut warnings, main_section, "mode", DEFAULT_MODE);
+    let verify = get_bool(&mut warnings, main_section, "verify", DEFAULT_VERIFY);
+    let verify_delay_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "verify_delay_ms",
+        DEFAULT_VERIFY_DELAY_MS,
+    );
+    let verify_retries = get_parsed(
+        &mut warnings,
+        main_section,
+        "verify_retries",
+        DEFAULT_VERIFY_RETRIES,
+    );
+    let spiral_seed = get_parsed(
+        &mut warnings,
+        main_section,
+        "spiral_seed",
+        DEFAULT_SPIRAL_SEED,
+    );
+    let focus_delay_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "focus_delay_ms",
+        DEFAULT_FOCUS_DELAY_MS,
+    );
+    let respect_restored_layouts = get_bool(
+        &mut warnings,
+        main_section,
+        "respect_restored_layouts",
+        DEFAULT_RESPECT_RESTORED_LAYOUTS,
+    );
+    let post_resize_command = main_section
+        .get("post_resize_command")
+        .filter(|command| !command.is_empty())
+        .map(String::from);
+    let pre_split_command = main_section
+        .get("pre_split_command")
+        .filter(|command| !command.is_empty())
+        .map(String::from);
+    let pre_command_failure = get_parsed(
+        &mut warnings,
+        main_section,
+        "pre_command_failure",
+        DEFAULT_PRE_COMMAND_FAILURE,
+    );
+    let on_resize_noop = get_parsed(
+        &mut warnings,
+        main_section,
+        "on_resize_noop",
+        DEFAULT_ON_RESIZE_NOOP,
+    );
+    let hook_script = main_section
+        .get("hook_script")
+        .filter(|script| !script.is_empty())
+        .and_then(|script| expand_path(&mut warnings, "hook_script", script));
+    let hook_events = get_list(main_section, "hook_events");
+    let reassert_interval_secs = get_parsed(
+        &mut warnings,
+        main_section,
+        "reassert_interval_secs",
+        DEFAULT_REASSERT_INTERVAL_SECS,
+    );
+    let dropdown_classes = get_list(main_section, "dropdown_classes");
+    let escape_tabbed = get_bool(
+        &mut warnings,
+        main_section,
+        "escape_tabbed",
+        DEFAULT_ESCAPE_TABBED,
+    );
+    let title_ratio_window_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "title_ratio_window_ms",
+        DEFAULT_TITLE_RATIO_WINDOW_MS,
+    );
+    let config_merge = get_bool(
+        &mut warnings,
+        main_section,
+        "config_merge",
+        DEFAULT_CONFIG_MERGE,
+    );
+    let new_window_side = get_parsed(
+        &mut warnings,
+        main_section,
+        "new_window_side",
+        DEFAULT_NEW_WINDOW_SIDE,
+    );
+    let swap_new = get_bool(&mut warnings, main_section, "swap_new", DEFAULT_SWAP_NEW);
+    let count_policy = get_list(main_section, "count_policy")
+        .iter()
+        .filter_map(|rule| match rule.parse() {
+            Ok(rule) => Some(rule),
+            Err(()) => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "Invalid count_policy rule {:?}, expected COUNT:ACTION or COUNT+:ACTION, ignoring",
+                    rule
+                )));
+                None
+            }
+        })
+        .collect();
+    let exclude_processes = get_list(main_section, "exclude_processes");
+    let mut exclude: Vec<RuleMatch> = get_list(main_section, "exclude")
+        .iter()
+        .filter_map(|entry| match entry.parse() {
+            Ok(matcher) => Some(matcher),
+            Err(()) => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "Invalid exclude entry {:?}, expected role:, instance:, class: or title: and a value, ignoring",
+                    entry
+                )));
+                None
+            }
+        })
+        .collect();
+    exclude.sort_by_key(RuleMatch::precedence);
+    let columns = match get_parsed(&mut warnings, main_section, "columns", DEFAULT_COLUMNS) {
+        0 => DEFAULT_COLUMNS,
+        columns => columns,
+    };
+    let column_ratios = match main_section.get("column_ratios") {
+        Some(list) => match parse_column_ratios(list) {
+            Some(ratios) if ratios.len() == columns => ratios,
+            _ => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "column_ratios {:?} must be {} positive numbers, using equal columns",
+                    list, columns
+                )));
+                vec![1.0 / columns as f64; columns]
+            }
+        },
+        None if columns == DEFAULT_COLUMNS => parse_column_ratios(DEFAULT_COLUMN_RATIOS).unwrap(),
+        None => vec![1.0 / columns as f64; columns],
+    };
+    let column_fill = get_parsed(
+        &mut warnings,
+        main_section,
+        "column_fill",
+        DEFAULT_COLUMN_FILL,
+    );
+    let rounding = get_parsed(&mut warnings, main_section, "rounding", DEFAULT_ROUNDING);
+    let reference_mark = main_section
+        .get("reference_mark")
+        .unwrap_or(DEFAULT_REFERENCE_MARK)
         .to_string();
+    let verify_log = get_bool(
+        &mut warnings,
+        main_section,
+        "verify_log",
+        DEFAULT_VERIFY_LOG,
+    );
+    let multi_output_container = get_parsed(
+        &mut warnings,
+        main_section,
+        "multi_output_container",
+        DEFAULT_MULTI_OUTPUT_CONTAINER,
+    );
+    let never_change_focus = get_bool(
+        &mut warnings,
+        main_section,
+        "never_change_focus",
+        DEFAULT_NEVER_CHANGE_FOCUS,
+    );
+    let control = get_parsed(&mut warnings, main_section, "control", DEFAULT_CONTROL);
+    let ratio_locks = get_bool(
+        &mut warnings,
+        main_section,
+        "ratio_locks",
+        DEFAULT_RATIO_LOCKS,
+    );
+    let pause_on_fullscreen = get_bool(
+        &mut warnings,
+        main_section,
+        "pause_on_fullscreen",
+        DEFAULT_PAUSE_ON_FULLSCREEN,
+    );
+    let manage_moved_existing = get_bool(
+        &mut warnings,
+        main_section,
+        "manage_moved_existing",
+        DEFAULT_MANAGE_MOVED_EXISTING,
+    );
+    let handle_delay_ms = get_parsed(
+        &mut warnings,
+        main_section,
+        "handle_delay_ms",
+        DEFAULT_HANDLE_DELAY_MS,
+    );
+    let settle_ms = get_parsed(&mut warnings, main_section, "settle_ms", DEFAULT_SETTLE_MS);
+    let skip_urgent = get_bool(
+        &mut warnings,
+        main_section,
+        "skip_urgent",
+        DEFAULT_SKIP_URGENT,
+    );
+    let debug_marks = get_bool(
+        &mut warnings,
+        main_section,
+        "debug_marks",
+        DEFAULT_DEBUG_MARKS,
+    );
+    let failsafe_threshold = get_parsed(
+        &mut warnings,
+        main_section,
+        "failsafe_threshold",
+        DEFAULT_FAILSAFE_THRESHOLD,
+    );
+    let failsafe_notify = get_bool(
+        &mut warnings,
+        main_section,
+        "failsafe_notify",
+        DEFAULT_FAILSAFE_NOTIFY,
+    );
+    let split_directions = match conf_file.section(Some("split_direction")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(class, direction)| match direction {
+                "horizontal" => Some((class.to_string(), SplitDirection::Horizontal)),
+                "vertical" => Some((class.to_string(), SplitDirection::Vertical)),
+                _ => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Unknown split direction {:?} for {:?}, ignoring",
+                        direction, class
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+    let positions = match conf_file.section(Some("position")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(class, position)| match position.parse() {
+                Ok(position) => Some((class.to_string(), position)),
+                Err(()) => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Unknown position {:?} for {:?}, ignoring",
+                        position, class
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+    let aspects = match conf_file.section(Some("aspect")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(class, aspect)| match parse_aspect(aspect) {
+                Some(aspect) => Some((class.to_string(), aspect)),
+                None => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Invalid aspect {:?} for {:?}, expected W:H or a number, ignoring",
+                        aspect, class
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let title_ratios = match conf_file.section(Some("title_ratios")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(pattern, ratio)| {
+                let regex = match Regex::new(pattern) {
+                    Ok(regex) => regex,
+                    Err(error) => {
+                        warnings.push(ConfigError::Invalid(format!(
+                            "Invalid title pattern {:?}, ignoring: {}",
+                            pattern, error
+                        )));
+                        return None;
+                    }
+                };
+                match ratio.parse::<f64>() {
+                    Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Some(TitleRatio {
+                        pattern: pattern.to_string(),
+                        regex,
+                        ratio,
+                    }),
+                    _ => {
+                        warnings.push(ConfigError::Invalid(format!(
+                            "Invalid ratio {:?} for title pattern {:?}, ignoring",
+                            ratio, pattern
+                        )));
+                        None
+                    }
+                }
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut workspace_overrides = Vec::new();
+    for (section, properties) in conf_file.iter() {
+        let selector = match section.and_then(|s| s.strip_prefix("workspace ")) {
+            Some(selector) => selector,
+            None => continue,
+        };
+        let selector: WorkspaceSelector = match selector.parse() {
+            Ok(selector) => selector,
+            Err(()) => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "Invalid section [workspace {}], expected num=N, name=NAME or pattern=REGEX, ignoring",
+                    selector
+                )));
+                continue;
+            }
+        };
+        let ratio = properties
+            .get("ratio")
+            .and_then(|ratio| match ratio.parse::<f64>() {
+                Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Some(ratio),
+                _ => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Invalid ratio {:?} in {}, ignoring",
+                        ratio, selector
+                    )));
+                    None
+                }
+            });
+        let mode = properties.get("mode").and_then(|mode| match mode.parse() {
+            Ok(mode) => Some(mode),
+            Err(()) => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "Invalid mode {:?} in {}, ignoring",
+                    mode, selector
+                )));
+                None
+            }
+        });
+        workspace_overrides.push(WorkspaceOverride {
+            selector,
+            ratio,
+            mode,
+        });
+    }
+    warn_overlapping_workspaces(&workspace_overrides, &mut warnings);
 
-    let log_file_level = get_level(main_section, "log_file_level", DEFAULT_LOG_FILE_LEVEL);
-    let log_console_level = get_level(main_section, "log_console_level", DEFAULT_LOG_CONSOLE_LEVEL);
+    let output_columns = match conf_file.section(Some("output_columns")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(output, list)| match parse_column_ratios(list) {
+                Some(ratios) => Some((output.to_string(), ratios)),
+                None => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Invalid column ratios {:?} for output {:?}, ignoring",
+                        list, output
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let role_ratios = match conf_file.section(Some("role_ratios")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(role, ratio)| match ratio.parse::<f64>() {
+                Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Some((role.to_string(), ratio)),
+                _ => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Invalid ratio {:?} for role {:?}, ignoring",
+                        ratio, role
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
+    };
 
-    return Settings {
-        ratio,
-        log_file: shellexpand::full(log_file.as_str()).unwrap().to_string(),
-        log_file_level,
-        log_console_level,
+    let instance_ratios = match conf_file.section(Some("instance_ratios")) {
+        Some(section) => section
+            .iter()
+            .filter_map(|(instance, ratio)| match ratio.parse::<f64>() {
+                Ok(ratio) if ratio > 0.0 && ratio < 1.0 => Some((instance.to_string(), ratio)),
+                _ => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "Invalid ratio {:?} for instance {:?}, ignoring",
+                        ratio, instance
+                    )));
+                    None
+                }
+            })
+            .collect(),
+        None => HashMap::new(),
     };
 
-    fn get_level(main_section: &Properties, path: &str, default: LevelFilter) -> LevelFilter {
+    return Ok((
+        Settings {
+            ratio,
+            ratio_vertical,
+            auto_ratio,
+            mode,
+            columns,
+            column_ratios,
+            column_fill,
+            reference_mark,
+            target_aspect,
+            ratio_presets,
+            log_file,
+            log_file_level,
+            log_console_level,
+            managed_workspaces,
+            unmanaged_workspaces,
+            rebalance_on_fullscreen_exit,
+            pause_on_fullscreen,
+            skip_urgent,
+            debug_marks,
+            failsafe_threshold,
+            failsafe_notify,
+            manage_moved_existing,
+            skip_if_fills_parent,
+            dropdown_classes,
+            exclude_processes,
+            exclude,
+            respect_restored_layouts,
+            split_directions,
+            positions,
+            aspects,
+            title_ratios,
+            role_ratios,
+            instance_ratios,
+            workspace_overrides,
+            output_columns,
+            manual_cooldown,
+            reassert_interval_secs,
+            class_match_timeout_ms,
+            handle_delay_ms,
+            title_ratio_window_ms,
+            phase_delay_ms,
+            repair_window_ms,
+            animate_ms,
+            animate_steps,
+            focus_delay_ms,
+            verify,
+            verify_delay_ms,
+            verify_retries,
+            verify_log,
+            settle_ms,
+            resize_unit,
+            multi_output_container,
+            rounding,
+            resize_method,
+            on_resize_noop,
+            multi_child,
+            count_policy,
+            manage_tabbed,
+            escape_tabbed,
+            anchor_mark,
+            ratio_locks,
+            portrait_aware,
+            spiral_seed,
+            promote_new_to_master,
+            new_window_side,
+            swap_new,
+            pre_split_command,
+            pre_command_failure,
+            post_resize_command,
+            hook_script,
+            hook_events,
+            suggest,
+            suggest_interval,
+            backend_guard,
+            config_merge,
+            overflow,
+            max_windows,
+            follow_focus,
+            never_change_focus,
+            control,
+            overflow_layout,
+            overflow_threshold,
+            overflow_restore,
+            quiet_threshold,
+            quiet_window_ms,
+            quiet_settle_ms,
+        },
+        warnings,
+    ));
+
+    // A ratio is the new window's share of its parent, so anything strictly between 0 and 1 is
+    // meaningful. Values above 0.5 make the new window the larger pane.
+    fn get_ratio(
+        warnings: &mut Vec<ConfigError>,
+        main_section: &Properties,
+        path: &str,
+        default: f64,
+    ) -> f64 {
         match main_section.get(path) {
             None => default,
-            Some(level_str) => match level_str.parse() {
-                Ok(l) => l,
-                Err(_) => default,
+            Some(ratio_string) => match ratio_string.parse::<f64>() {
+                Ok(r) if r > 0.0 && r < 1.0 => r,
+                _ => {
+                    warnings.push(ConfigError::Invalid(format!(
+                        "{} {:?} must be a number between 0 and 1, using {}",
+                        path, ratio_string, default
+                    )));
+                    default
+                }
             },
         }
     }
+
+    fn get_bool(
+        warnings: &mut Vec<ConfigError>,
+        main_section: &Properties,
+        path: &str,
+        default: bool,
+    ) -> bool {
+        match main_section.get(path) {
+            None => default,
+            Some(bool_str) => bool_str.parse().unwrap_or_else(|_| {
+                warnings.push(ConfigError::Invalid(format!(
+                    "{} {:?} must be true or false, using {}",
+                    path, bool_str, default
+                )));
+                default
+            }),
+        }
+    }
+
+    fn get_parsed<T: FromStr + fmt::Debug>(
+        warnings: &mut Vec<ConfigError>,
+        main_section: &Properties,
+        path: &str,
+        default: T,
+    ) -> T {
+        match main_section.get(path) {
+            None => default,
+            Some(value_str) => value_str.parse().unwrap_or_else(|_| {
+                warnings.push(ConfigError::Invalid(format!(
+                    "{} {:?} isn't a valid value, using {:?}",
+                    path, value_str, default
+                )));
+                default
+            }),
+        }
+    }
+
+    /// Expands `~` and environment variables in a path, leaving the setting at its default when
+    /// a variable isn't set.
+    fn expand_path(warnings: &mut Vec<ConfigError>, key: &str, path: &str) -> Option<String> {
+        match shellexpand::full(path) {
+            Ok(expanded) => Some(expanded.into_owned()),
+            Err(error) => {
+                warnings.push(ConfigError::Invalid(format!(
+                    "{} {:?} can't be expanded: {}, using the default",
+                    key, path, error
+                )));
+                None
+            }
+        }
+    }
+
+    fn get_list(main_section: &Properties, path: &str) -> Vec<String> {
+        match main_section.get(path) {
+            None => Vec::new(),
+            Some(list_str) => list_str
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// Parses a `W:H` aspect into width / height, like 16:9 into 1.78, or takes one written as a
+/// number already.
+fn parse_aspect(aspect: &str) -> Option<f64> {
+    let ratio = match aspect.split_once(':') {
+        Some((width, height)) => {
+            width.trim().parse::<f64>().ok()? / height.trim().parse::<f64>().ok()?
+        }
+        None => aspect.trim().parse().ok()?,
+    };
+    Some(ratio).filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+}
+
+/// Parses comma-separated column widths, like `25,50,25`, into fractions of the workspace.
+fn parse_column_ratios(list: &str) -> Option<Vec<f64>> {
+    let widths = list
+        .split(',')
+        .map(|width| width.trim().parse::<f64>().ok().filter(|&w| w > 0.0))
+        .collect::<Option<Vec<f64>>>()?;
+    let total: f64 = widths.iter().sum();
+    Some(widths.iter().map(|width| width / total).collect())
+}
+
+/// Loads the user config, falling back to the system-wide one when there is none. When both
+/// exist and `config_merge` is set in either, the user config is layered over the system-wide
+/// one instead. Problems with either file are added to `problems`.
+fn load_config(problems: &mut Vec<ConfigError>) -> Option<Ini> {
+    load_config_from(USER_CONFIG_PATH, SYSTEM_CONFIG_PATH, problems)
+}
+
+/// `load_config` with the user and system-wide configs at the given paths.
+fn load_config_from(
+    user_path: &str,
+    system_path: &str,
+    problems: &mut Vec<ConfigError>,
+) -> Option<Ini> {
+    let user = load_ini(user_path);
+    let system = match load_ini(system_path) {
+        Ok(file) => Some(file),
+        Err(ini::Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            problems.push(ConfigError::Invalid(format!(
+                "Error {:?} loading {}, ignoring it",
+                err, system_path
+            )));
+            None
+        }
+    };
+
+    match (user, system) {
+        (Ok(user), Some(system)) => {
+            let merged = merge_configs(&system, &user);
+            let config_merge = merged
+                .section(Some("main"))
+                .and_then(|main| main.get("config_merge"))
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_CONFIG_MERGE);
+            Some(if config_merge { merged } else { user })
+        }
+        (Ok(user), None) => Some(user),
+        // Only a missing user config falls back; one that can't be read is reported.
+        (Err(ini::Error::Io(err)), system) if err.kind() == io::ErrorKind::NotFound => system,
+        (Err(ini::Error::Io(err)), _) => {
+            problems.push(ConfigError::Io(err));
+            None
+        }
+        (Err(ini::Error::Parse(err)), _) => {
+            problems.push(ConfigError::Parse(err.to_string()));
+            None
+        }
+    }
+}
+
+fn load_ini(path: &str) -> Result<Ini, ini::Error> {
+    let expanded = shellexpand::full(path).map_err(|error| {
+        ini::Error::Io(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be expanded: {}", path, error),
+        ))
+    })?;
+    Ini::load_from_file(expanded.as_ref())
+}
+
+/// Layers `overrides` over `base` key by key. Every section of either is kept, and a key set in
+/// both takes its value from `overrides`, staying where `base` had it.
+fn merge_configs(base: &Ini, overrides: &Ini) -> Ini {
+    let mut merged = Ini::new();
+    for config in &[base, overrides] {
+        for (section, properties) in config.iter() {
+            for (key, value) in properties.iter() {
+                merged.with_section(section).set(key, value);
+            }
+        }
+    }
+    merged
+}
+
+/// The log file when `log_file` isn't set: `ratiosplit.log` in the state directory, unless
+/// there's already a log at the old place in the i3 config directory, which keeps being used.
+fn default_log_file() -> String {
+    choose_log_file(legacy_log_file(), state_log_file())
+}
+
+fn choose_log_file(legacy: String, state: String) -> String {
+    if Path::new(&legacy).exists() {
+        legacy
+    } else {
+        state
+    }
+}
+
+/// Where the log goes by default, in the state directory.
+pub fn state_log_file() -> String {
+    state_dir()
+        .join(LOG_FILE_NAME)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Where the log went by default before it moved to the state directory.
+pub fn legacy_log_file() -> String {
+    shellexpand::tilde(LEGACY_LOG_PATH).to_string()
 }
 
-fn default_settings() -> Settings {
+/// Warns about `[workspace ...]` sections that can apply to the same workspace, where only the
+/// one with the highest precedence is used: a name section for a numbered name and the number's
+/// section, and a name section a pattern also matches.
+fn warn_overlapping_workspaces(overrides: &[WorkspaceOverride], warnings: &mut Vec<ConfigError>) {
+    for named in overrides {
+        let name = match &named.selector {
+            WorkspaceSelector::Name(name) => name,
+            _ => continue,
+        };
+        let number = workspace_number(name);
+        for other in overrides {
+            let overlaps = match &other.selector {
+                WorkspaceSelector::Number(_) => other.selector.matches(number, None),
+                WorkspaceSelector::Pattern { .. } => other.selector.matches(None, Some(name)),
+                WorkspaceSelector::Name(_) => false,
+            };
+            if overlaps {
+                warnings.push(ConfigError::Invalid(format!(
+                    "{} and {} both apply to workspace {:?}, using {}",
+                    named.selector, other.selector, name, named.selector
+                )));
+            }
+        }
+    }
+}
+
+pub fn default_settings() -> Settings {
     Settings {
         ratio: DEFAULT_RATIO,
-        log_file: shellexpand::full(DEFAULT_LOG_PATH).unwrap().to_string(),
+        ratio_vertical: DEFAULT_RATIO,
+        auto_ratio: false,
+        mode: DEFAULT_MODE,
+        columns: DEFAULT_COLUMNS,
+        column_ratios: parse_column_ratios(DEFAULT_COLUMN_RATIOS).unwrap(),
+        column_fill: DEFAULT_COLUMN_FILL,
+        reference_mark: DEFAULT_REFERENCE_MARK.to_string(),
+        target_aspect: DEFAULT_TARGET_ASPECT,
+        ratio_presets: Vec::new(),
+        log_file: default_log_file(),
         log_file_level: DEFAULT_LOG_FILE_LEVEL,
         log_console_level: DEFAULT_LOG_CONSOLE_LEVEL,
+        managed_workspaces: Vec::new(),
+        unmanaged_workspaces: Vec::new(),
+        rebalance_on_fullscreen_exit: DEFAULT_REBALANCE_ON_FULLSCREEN_EXIT,
+        pause_on_fullscreen: DEFAULT_PAUSE_ON_FULLSCREEN,
+        skip_urgent: DEFAULT_SKIP_URGENT,
+        debug_marks: DEFAULT_DEBUG_MARKS,
+        failsafe_threshold: DEFAULT_FAILSAFE_THRESHOLD,
+        failsafe_notify: DEFAULT_FAILSAFE_NOTIFY,
+        manage_moved_existing: DEFAULT_MANAGE_MOVED_EXISTING,
+        skip_if_fills_parent: DEFAULT_SKIP_IF_FILLS_PARENT,
+        dropdown_classes: Vec::new(),
+        exclude_processes: Vec::new(),
+        exclude: Vec::new(),
+        respect_restored_layouts: DEFAULT_RESPECT_RESTORED_LAYOUTS,
+        split_directions: HashMap::new(),
+        positions: HashMap::new(),
+        aspects: HashMap::new(),
+        title_ratios: Vec::new(),
+        role_ratios: HashMap::new(),
+        instance_ratios: HashMap::new(),
+        workspace_overrides: Vec::new(),
+        output_columns: HashMap::new(),
+        manual_cooldown: DEFAULT_MANUAL_COOLDOWN,
+        reassert_interval_secs: DEFAULT_REASSERT_INTERVAL_SECS,
+        class_match_timeout_ms: DEFAULT_CLASS_MATCH_TIMEOUT_MS,
+        handle_delay_ms: DEFAULT_HANDLE_DELAY_MS,
+        title_ratio_window_ms: DEFAULT_TITLE_RATIO_WINDOW_MS,
+        phase_delay_ms: DEFAULT_PHASE_DELAY_MS,
+        repair_window_ms: DEFAULT_REPAIR_WINDOW_MS,
+        animate_ms: DEFAULT_ANIMATE_MS,
+        animate_steps: DEFAULT_ANIMATE_STEPS,
+        focus_delay_ms: DEFAULT_FOCUS_DELAY_MS,
+        verify: DEFAULT_VERIFY,
+        verify_delay_ms: DEFAULT_VERIFY_DELAY_MS,
+        verify_retries: DEFAULT_VERIFY_RETRIES,
+        verify_log: DEFAULT_VERIFY_LOG,
+        settle_ms: DEFAULT_SETTLE_MS,
+        resize_unit: DEFAULT_RESIZE_UNIT,
+        multi_output_container: DEFAULT_MULTI_OUTPUT_CONTAINER,
+        rounding: DEFAULT_ROUNDING,
+        resize_method: DEFAULT_RESIZE_METHOD,
+        on_resize_noop: DEFAULT_ON_RESIZE_NOOP,
+        multi_child: DEFAULT_MULTI_CHILD,
+        count_policy: Vec::new(),
+        manage_tabbed: DEFAULT_MANAGE_TABBED,
+        escape_tabbed: DEFAULT_ESCAPE_TABBED,
+        anchor_mark: None,
+        ratio_locks: DEFAULT_RATIO_LOCKS,
+        portrait_aware: DEFAULT_PORTRAIT_AWARE,
+        spiral_seed: DEFAULT_SPIRAL_SEED,
+        promote_new_to_master: DEFAULT_PROMOTE_NEW_TO_MASTER,
+        new_window_side: DEFAULT_NEW_WINDOW_SIDE,
+        swap_new: DEFAULT_SWAP_NEW,
+        pre_split_command: None,
+        pre_command_failure: DEFAULT_PRE_COMMAND_FAILURE,
+        post_resize_command: None,
+        hook_script: None,
+        hook_events: Vec::new(),
+        suggest: DEFAULT_SUGGEST,
+        suggest_interval: DEFAULT_SUGGEST_INTERVAL,
+        backend_guard: None,
+        config_merge: DEFAULT_CONFIG_MERGE,
+        overflow: DEFAULT_OVERFLOW,
+        max_windows: DEFAULT_MAX_WINDOWS,
+        follow_focus: DEFAULT_FOLLOW_FOCUS,
+        never_change_focus: DEFAULT_NEVER_CHANGE_FOCUS,
+        control: DEFAULT_CONTROL,
+        overflow_layout: DEFAULT_OVERFLOW_LAYOUT,
+        overflow_threshold: DEFAULT_OVERFLOW_THRESHOLD,
+        overflow_restore: DEFAULT_OVERFLOW_RESTORE,
+        quiet_threshold: DEFAULT_QUIET_THRESHOLD,
+        quiet_window_ms: DEFAULT_QUIET_WINDOW_MS,
+        quiet_settle_ms: DEFAULT_QUIET_SETTLE_MS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn parse(contents: &str) -> (Settings, Vec<ConfigError>) {
+        parse_settings_from_str(contents, Format::Ini).unwrap()
+    }
+
+    fn messages(problems: &[ConfigError]) -> Vec<String> {
+        problems.iter().map(ConfigError::to_string).collect()
+    }
+
+    #[test]
+    fn parses_valid_settings_without_problems() {
+        let (settings, problems) =
+            parse("[main]\nratio = 0.6\nskip_urgent = true\nmanual_cooldown = 30\n");
+        assert!(problems.is_empty(), "{:?}", messages(&problems));
+        assert_eq!(settings.ratio, 0.6);
+        assert!(settings.skip_urgent);
+        assert_eq!(settings.manual_cooldown, 30);
+    }
+
+    #[test]
+    fn missing_main_section_is_fatal() {
+        let error = parse_settings_from_str("[other]\nratio = 0.6\n", Format::Ini).unwrap_err();
+        assert!(matches!(error, ConfigError::NoMainSection));
+        assert!(error.is_fatal());
+    }
+
+    #[test]
+    fn invalid_bool_is_reported_and_defaulted() {
+        let (settings, problems) = parse("[main]\nskip_urgent = yes please\n");
+        assert_eq!(settings.skip_urgent, default_settings().skip_urgent);
+        assert_eq!(
+            messages(&problems),
+            vec![format!(
+                "skip_urgent \"yes please\" must be true or false, using {}",
+                default_settings().skip_urgent
+            )]
+        );
+        assert!(!problems[0].is_fatal());
+    }
+
+    #[test]
+    fn invalid_parsed_value_is_reported_and_defaulted() {
+        let (settings, problems) = parse("[main]\nmanual_cooldown = soon\n");
+        assert_eq!(settings.manual_cooldown, DEFAULT_MANUAL_COOLDOWN);
+        assert_eq!(
+            messages(&problems),
+            vec![format!(
+                "manual_cooldown \"soon\" isn't a valid value, using {:?}",
+                DEFAULT_MANUAL_COOLDOWN
+            )]
+        );
+    }
+
+    #[test]
+    fn invalid_log_levels_are_reported_and_defaulted() {
+        let (settings, problems) = parse("[main]\nlog_file_level = loud\nlog_console_level = 3\n");
+        assert_eq!(settings.log_file_level, DEFAULT_LOG_FILE_LEVEL);
+        assert_eq!(settings.log_console_level, DEFAULT_LOG_CONSOLE_LEVEL);
+        assert_eq!(
+            messages(&problems),
+            vec![
+                format!(
+                    "log_file_level \"loud\" isn't a valid value, using {:?}",
+                    DEFAULT_LOG_FILE_LEVEL
+                ),
+                format!(
+                    "log_console_level \"3\" isn't a valid value, using {:?}",
+                    DEFAULT_LOG_CONSOLE_LEVEL
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_backend_guard_is_reported_and_ignored() {
+        let (settings, problems) = parse("[main]\nbackend_guard = wayfire\n");
+        assert_eq!(settings.backend_guard, None);
+        assert_eq!(
+            messages(&problems),
+            vec!["backend_guard \"wayfire\" must be i3 or sway, running on any backend"]
+        );
+
+        let (settings, problems) = parse("[main]\nbackend_guard = sway\n");
+        assert_eq!(settings.backend_guard, Some(Backend::Sway));
+        assert!(problems.is_empty(), "{:?}", messages(&problems));
+    }
+
+    #[test]
+    fn invalid_ratio_is_reported_and_defaulted() {
+        let (settings, problems) = parse("[main]\nratio = 1.5\n");
+        assert_eq!(settings.ratio, DEFAULT_RATIO);
+        assert!(matches!(problems.as_slice(), [ConfigError::Invalid(_)]));
+    }
+
+    #[test]
+    fn unset_variables_in_paths_are_reported_and_defaulted() {
+        let (settings, problems) = parse(
+            "[main]\nlog_file = $RATIOSPLIT_UNSET_VARIABLE/ratiosplit.log\n\
+             hook_script = $RATIOSPLIT_UNSET_VARIABLE/hook\n",
+        );
+        assert_eq!(settings.log_file, default_log_file());
+        assert_eq!(settings.hook_script, None);
+        assert_eq!(problems.len(), 2, "{:?}", messages(&problems));
+        assert!(problems
+            .iter()
+            .all(|problem| matches!(problem, ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn unexpandable_config_path_is_an_error() {
+        assert!(matches!(
+            load_ini("$RATIOSPLIT_UNSET_VARIABLE/ratiosplit.ini"),
+            Err(ini::Error::Io(error)) if error.kind() == io::ErrorKind::InvalidInput
+        ));
+    }
+
+    /// A directory of its own under the system temp directory, for config files.
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ratiosplit-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn path(dir: &Path, file: &str) -> String {
+        dir.join(file).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn missing_user_config_falls_back_to_the_system_one() {
+        let dir = config_dir("fallback");
+        fs::write(dir.join("system.ini"), "[main]\nratio = 0.7\n").unwrap();
+
+        let mut problems = Vec::new();
+        let config = load_config_from(
+            &path(&dir, "missing.ini"),
+            &path(&dir, "system.ini"),
+            &mut problems,
+        )
+        .unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(config.get_from(Some("main"), "ratio"), Some("0.7"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_user_config_is_reported() {
+        let dir = config_dir("unreadable");
+        fs::write(dir.join("system.ini"), "[main]\nratio = 0.7\n").unwrap();
+        // A directory where the user config should be can't be read as one.
+        fs::create_dir_all(dir.join("user.ini")).unwrap();
+
+        let mut problems = Vec::new();
+        let config = load_config_from(
+            &path(&dir, "user.ini"),
+            &path(&dir, "system.ini"),
+            &mut problems,
+        );
+        assert!(config.is_none());
+        assert!(matches!(problems.as_slice(), [ConfigError::Io(_)]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_configs_are_not_a_problem() {
+        let dir = config_dir("missing");
+        let mut problems = Vec::new();
+        let config = load_config_from(
+            &path(&dir, "user.ini"),
+            &path(&dir, "system.ini"),
+            &mut problems,
+        );
+        assert!(config.is_none());
+        assert!(problems.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn merged_configs_take_overridden_keys_from_the_overrides() {
+        let base =
+            Ini::load_from_str("[main]\nratio = 0.6\nmode = spiral\n[exclude]\nclass = Foo\n")
+                .unwrap();
+        let overrides = Ini::load_from_str("[main]\nratio = 0.7\n[columns]\neDP-1 = 3\n").unwrap();
+
+        let merged = merge_configs(&base, &overrides);
+        assert_eq!(merged.get_from(Some("main"), "ratio"), Some("0.7"));
+        assert_eq!(merged.get_from(Some("main"), "mode"), Some("spiral"));
+        assert_eq!(merged.get_from(Some("exclude"), "class"), Some("Foo"));
+        assert_eq!(merged.get_from(Some("columns"), "eDP-1"), Some("3"));
+    }
+
+    const WORKSPACES: &str = "[main]\nratio = 0.6\n\
+        [workspace pattern=^web]\nratio = 0.7\n\
+        [workspace num=2]\nratio = 0.55\nmode = master\n\
+        [workspace name=\"2: mail\"]\nmode = equalize\n";
+
+    #[test]
+    fn workspace_sections_apply_by_name_number_then_pattern() {
+        let (settings, _) = parse(WORKSPACES);
+
+        let mail = settings.for_workspace(Some("2: mail"));
+        assert_eq!(mail.mode, Mode::Equalize);
+        assert_eq!(mail.ratio, 0.6);
+
+        let chat = settings.for_workspace(Some("2: chat"));
+        assert_eq!(chat.mode, Mode::Master);
+        assert_eq!(chat.ratio, 0.55);
+
+        let web = settings.for_workspace(Some("web"));
+        assert_eq!(web.ratio, 0.7);
+        assert_eq!(web.mode, settings.mode);
+
+        assert!(matches!(
+            settings.for_workspace(Some("3")),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(settings.for_workspace(None), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn overlapping_workspace_sections_are_reported() {
+        let (_, problems) = parse(WORKSPACES);
+        assert_eq!(
+            messages(&problems),
+            vec![
+                "[workspace name=\"2: mail\"] and [workspace num=2] both apply to workspace \
+                  \"2: mail\", using [workspace name=\"2: mail\"]"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_workspace_sections_are_reported() {
+        let (settings, problems) = parse(
+            "[main]\n[workspace number=2]\nratio = 0.7\n\
+             [workspace pattern=(]\nratio = 0.7\n\
+             [workspace num=3]\nratio = 2\nmode = sideways\n",
+        );
+        assert_eq!(problems.len(), 4, "{:?}", messages(&problems));
+        let three = settings.for_workspace(Some("3"));
+        assert_eq!(three.ratio, settings.ratio);
+        assert_eq!(three.mode, settings.mode);
+    }
+
+    #[test]
+    fn an_existing_legacy_log_keeps_being_used() {
+        let dir = config_dir("log");
+        let legacy = path(&dir, "ratiosplit.log");
+        let state = path(&dir, "state/ratiosplit.log");
+        assert_eq!(choose_log_file(legacy.clone(), state.clone()), state);
+
+        fs::write(&legacy, "").unwrap();
+        assert_eq!(choose_log_file(legacy.clone(), state), legacy);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn log_file_defaults_to_the_state_directory() {
+        assert!(Path::new(&state_log_file()).starts_with(state_dir()));
+        assert!(state_log_file().ends_with(LOG_FILE_NAME));
+    }
+
+    #[test]
+    fn aspects_are_parsed_from_either_form() {
+        assert!((parse_aspect("16:9").unwrap() - 1.778).abs() < 0.001);
+        assert_eq!(parse_aspect(" 4 : 3 "), Some(4.0 / 3.0));
+        assert_eq!(parse_aspect("1.78"), Some(1.78));
+        for &garbage in &["wide", "16:", ":9", "16:0", "0:9", "-16:9", "0", "16x9"] {
+            assert_eq!(parse_aspect(garbage), None, "{:?}", garbage);
+        }
+    }
+
+    #[test]
+    fn count_rules_are_parsed() {
+        let rule: CountRule = "3+:equalize".parse().unwrap();
+        assert_eq!(
+            (rule.count, rule.or_more, rule.action),
+            (3, true, CountAction::Equalize)
+        );
+        let rule: CountRule = " 2 : ratio ".parse().unwrap();
+        assert_eq!(
+            (rule.count, rule.or_more, rule.action),
+            (2, false, CountAction::Ratio)
+        );
+        let rule: CountRule = "1:none".parse().unwrap();
+        assert_eq!(
+            (rule.count, rule.or_more, rule.action),
+            (1, false, CountAction::None)
+        );
+        for &invalid in &["2", "two:ratio", "2:huge", "+:none", "-1:none", "2++:ratio"] {
+            assert!(invalid.parse::<CountRule>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn the_first_matching_title_pattern_wins() {
+        let (settings, problems) = parse("[main]\n[title_ratios]\nZoom = 0.7\nMeeting$ = 0.25\n");
+        assert!(problems.is_empty(), "{:?}", messages(&problems));
+        assert_eq!(settings.title_ratio("Zoom Meeting"), Some(0.7));
+        assert_eq!(settings.title_ratio("Team Meeting"), Some(0.25));
+        assert_eq!(settings.title_ratio("Zoom"), Some(0.7));
+        assert_eq!(settings.title_ratio("Slack"), None);
+
+        let (settings, _) = parse("[main]\n[title_ratios]\nMeeting$ = 0.25\nZoom = 0.7\n");
+        assert_eq!(settings.title_ratio("Zoom Meeting"), Some(0.25));
+    }
+
+    #[test]
+    fn role_rules_wait_for_the_class_like_class_rules() {
+        assert!(!default_settings().has_class_rules());
+
+        let (settings, _) = parse("[main]\n[role_ratios]\npop-up = 0.6\n");
+        assert_eq!(settings.role_ratios.get("pop-up"), Some(&0.6));
+        assert!(settings.has_class_rules());
+
+        let mut settings = default_settings();
+        settings.exclude = vec![RuleMatch::Role("pop-up".to_string())];
+        assert!(settings.has_class_rules());
+        settings.exclude = vec![RuleMatch::Title {
+            pattern: "x".to_string(),
+            regex: Regex::new("x").unwrap(),
+        }];
+        assert!(!settings.has_class_rules());
+    }
+
+    #[test]
+    fn matching_rules_come_role_then_instance_then_class_then_title() {
+        let (settings, _) = parse(
+            "[main]\n[title_ratios]\nwindow$ = 0.7\n[aspect]\nAlacritty = 1:1\n\
+             [instance_ratios]\nalacritty = 0.55\n[role_ratios]\npop-up = 0.6\n",
+        );
+        let window = WindowIdentity {
+            role: Some("pop-up"),
+            instance: Some("alacritty"),
+            class: Some("Alacritty"),
+            title: Some("Alacritty window"),
+        };
+        let rules: Vec<_> = settings
+            .matching_rules(&window)
+            .iter()
+            .map(|rule| (rule.priority, rule.to_string()))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (1, "[role_ratios] role = pop-up".to_string()),
+                (2, "[instance_ratios] instance = alacritty".to_string()),
+                (3, "[aspect] class = Alacritty".to_string()),
+                (4, "[title_ratios] title ~ window$".to_string()),
+            ]
+        );
     }
 }
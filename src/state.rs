@@ -0,0 +1,131 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state_store;
+
+/// Where the state is saved, in the state directory.
+const STATE_FILE: &str = "state.json";
+
+/// Bumped whenever the on-disk format changes in a way older versions can't read.
+const STATE_VERSION: u32 = 1;
+
+/// Runtime state that can be changed while the daemon is running. This is persisted so that it
+/// survives daemon restarts, which happen on every i3 restart.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub paused: bool,
+    /// Whether ratiosplit paused itself after `failsafe_threshold` failures, rather than being
+    /// paused by hand.
+    pub failsafe: bool,
+    /// The index into `ratio_presets` selected with `cycle-ratio`, if any.
+    pub ratio_preset: Option<usize>,
+    /// The splits containers were last resized to by hand, for `mode = remember`.
+    pub remembered: Remembered,
+    /// Classes of windows that resized themselves again after the `settle_ms` retry.
+    pub self_resizing: BTreeSet<String>,
+    /// Whether the log has said once that it's still in the i3 config directory.
+    pub legacy_log_noticed: bool,
+}
+
+/// The share of its container the last window took up, keyed by the container's
+/// `container_path`.
+pub type Remembered = HashMap<String, f64>;
+
+/// Moves the splits remembered on a workspace over to its new name. Returns whether there were
+/// any.
+pub fn rename_remembered(remembered: &mut Remembered, old: &str, new: &str) -> bool {
+    let prefix = format!("{}/", old);
+    let moved: Vec<String> = remembered
+        .keys()
+        .filter(|path| *path == old || path.starts_with(&prefix))
+        .cloned()
+        .collect();
+    for path in &moved {
+        if let Some(share) = remembered.remove(path) {
+            remembered.insert(format!("{}{}", new, &path[old.len()..]), share);
+        }
+    }
+    !moved.is_empty()
+}
+
+/// Forgets the splits remembered on workspaces other than the given ones, which no longer exist.
+/// Returns whether there were any.
+pub fn prune_remembered(remembered: &mut Remembered, workspaces: &[&str]) -> bool {
+    let before = remembered.len();
+    remembered.retain(|path, _| {
+        workspaces.iter().any(|name| {
+            path.strip_prefix(name)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    });
+    remembered.len() != before
+}
+
+/// Loads the saved state, or the default state when there is none that can be used.
+pub fn load_state() -> State {
+    match state_store::load(STATE_FILE, STATE_VERSION) {
+        Some(state) => {
+            info!("Loaded saved state {:?}", state);
+            state
+        }
+        None => State::default(),
+    }
+}
+
+pub fn save_state(state: &State) -> io::Result<()> {
+    state_store::save(STATE_FILE, STATE_VERSION, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remembered(paths: &[&str]) -> Remembered {
+        paths.iter().map(|path| (path.to_string(), 0.6)).collect()
+    }
+
+    #[test]
+    fn state_round_trips_through_json() {
+        let state = State {
+            paused: true,
+            failsafe: true,
+            ratio_preset: Some(2),
+            remembered: remembered(&["1/0", "web/1/0"]),
+            self_resizing: vec!["Steam".to_string()].into_iter().collect(),
+            legacy_log_noticed: true,
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: State = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&state).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let state: State = serde_json::from_str("{\"paused\": true}").unwrap();
+        assert!(state.paused);
+        assert_eq!(state.ratio_preset, None);
+        assert!(state.remembered.is_empty());
+    }
+
+    #[test]
+    fn pruning_keeps_only_existing_workspaces() {
+        let mut splits = remembered(&["1", "1/0", "10/0", "web/1/0", "mail/0"]);
+        assert!(prune_remembered(&mut splits, &["1", "web"]));
+        assert_eq!(splits, remembered(&["1", "1/0", "web/1/0"]));
+        assert!(!prune_remembered(&mut splits, &["1", "web"]));
+    }
+
+    #[test]
+    fn renaming_moves_only_that_workspace() {
+        let mut splits = remembered(&["1/0", "10/0"]);
+        assert!(rename_remembered(&mut splits, "1", "code"));
+        assert_eq!(splits, remembered(&["code/0", "10/0"]));
+        assert!(!rename_remembered(&mut splits, "mail", "inbox"));
+    }
+}
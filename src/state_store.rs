@@ -0,0 +1,208 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The directory everything ratiosplit persists lives in, under `$XDG_STATE_HOME` with the usual
+/// `~/.local/state` fallback.
+pub fn state_dir() -> PathBuf {
+    state_dir_in(env::var_os("XDG_STATE_HOME"))
+}
+
+fn state_dir_in(xdg_state_home: Option<OsString>) -> PathBuf {
+    let state_home = match xdg_state_home {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(shellexpand::tilde("~/.local/state").to_string()),
+    };
+    state_home.join("ratiosplit")
+}
+
+/// The version a file was written with, around what was saved, so that formats can change
+/// without older versions misreading newer files.
+#[derive(Deserialize)]
+struct Envelope {
+    version: u32,
+    state: Value,
+}
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a, T> {
+    version: u32,
+    state: &'a T,
+}
+
+/// Loads the value saved as `name` in the state directory, if there is one in the given version.
+/// A missing file is normal. Unreadable files are logged, and corrupt files or files of another
+/// version are also renamed aside so they aren't lost, but none of these are ever fatal.
+pub fn load<T: DeserializeOwned>(name: &str, version: u32) -> Option<T> {
+    load_from(&state_dir(), name, version)
+}
+
+fn load_from<T: DeserializeOwned>(dir: &Path, name: &str, version: u32) -> Option<T> {
+    let path = dir.join(name);
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            info!("Nothing saved at {:?}", path);
+            return None;
+        }
+        Err(error) => {
+            warn!("Error {:?} reading {:?}, ignoring it", error, path);
+            return None;
+        }
+    };
+
+    let envelope = match serde_json::from_str::<Envelope>(&contents) {
+        Ok(envelope) => envelope,
+        Err(error) => {
+            set_aside(&path, &format!("is corrupt ({})", error));
+            return None;
+        }
+    };
+    if envelope.version != version {
+        set_aside(
+            &path,
+            &format!("has unsupported version {}", envelope.version),
+        );
+        return None;
+    }
+    match serde_json::from_value(envelope.state) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            set_aside(&path, &format!("is corrupt ({})", error));
+            None
+        }
+    }
+}
+
+/// Saves `value` as `name` in the state directory atomically: it's written to a temporary file
+/// next to the real one, synced, and renamed over it, so a crash can never leave a half-written
+/// file behind.
+pub fn save<T: Serialize>(name: &str, version: u32, value: &T) -> io::Result<()> {
+    save_to(&state_dir(), name, version, value)
+}
+
+fn save_to<T: Serialize>(dir: &Path, name: &str, version: u32, value: &T) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+
+    let contents = serde_json::to_string_pretty(&EnvelopeRef {
+        version,
+        state: value,
+    })?;
+
+    let temp_path = dir.join(format!("{}.tmp", name));
+    let mut temp = File::create(&temp_path)?;
+    temp.write_all(contents.as_bytes())?;
+    temp.sync_all()?;
+    fs::rename(&temp_path, &path)?;
+    // The rename itself only survives a crash once the directory is synced too.
+    File::open(dir)?.sync_all()
+}
+
+/// Moves a file that can't be loaded out of the way, to `<name>.bad`, so the next save doesn't
+/// overwrite what may still be recoverable by hand.
+fn set_aside(path: &Path, problem: &str) {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".bad");
+    match fs::rename(path, &aside) {
+        Ok(()) => warn!("{:?} {}, moved it to {:?}", path, problem, aside),
+        Err(error) => warn!(
+            "{:?} {}, and moving it aside failed: {:?}",
+            path, problem, error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A directory of its own under the system temp directory, standing in for the state
+    /// directory.
+    fn store(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ratiosplit-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn saved_values_load_back() {
+        let dir = store("round-trip");
+        let value: HashMap<String, f64> = vec![("1/0".to_string(), 0.6)].into_iter().collect();
+        save_to(&dir, "state.json", 1, &value).unwrap();
+
+        assert_eq!(load_from(&dir, "state.json", 1), Some(value));
+        assert!(!dir.join("state.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_loads_nothing() {
+        let dir = store("missing");
+        assert_eq!(load_from::<u32>(&dir, "state.json", 1), None);
+    }
+
+    #[test]
+    fn corrupt_file_is_set_aside() {
+        let dir = store("corrupt");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("state.json"), "{\"version\": 1, \"sta").unwrap();
+
+        assert_eq!(load_from::<u32>(&dir, "state.json", 1), None);
+        assert!(!dir.join("state.json").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("state.json.bad")).unwrap(),
+            "{\"version\": 1, \"sta"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn state_of_the_wrong_shape_is_set_aside() {
+        let dir = store("shape");
+        save_to(&dir, "state.json", 1, &"not a number").unwrap();
+
+        assert_eq!(load_from::<u32>(&dir, "state.json", 1), None);
+        assert!(dir.join("state.json.bad").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn other_versions_are_set_aside() {
+        let dir = store("version");
+        save_to(&dir, "state.json", 2, &7u32).unwrap();
+
+        assert_eq!(load_from::<u32>(&dir, "state.json", 1), None);
+        assert!(dir.join("state.json.bad").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn state_dir_follows_xdg_state_home() {
+        assert_eq!(
+            state_dir_in(Some(OsString::from("/tmp/state"))),
+            PathBuf::from("/tmp/state/ratiosplit")
+        );
+        let fallback = PathBuf::from(shellexpand::tilde("~/.local/state/ratiosplit").to_string());
+        assert_eq!(state_dir_in(Some(OsString::new())), fallback);
+        assert_eq!(state_dir_in(None), fallback);
+    }
+
+    #[test]
+    fn saving_replaces_the_previous_file() {
+        let dir = store("replace");
+        save_to(&dir, "state.json", 1, &1u32).unwrap();
+        save_to(&dir, "state.json", 1, &2u32).unwrap();
+        assert_eq!(load_from(&dir, "state.json", 1), Some(2u32));
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, BufRead, Write};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::control::{self, Response};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const NOT_RUNNING: &str = "daemon not running";
+
+/// Runs as a persistent status emitter for a bar. Subscribes to the daemon's state changes and
+/// prints a line for each, either as plain text or using the i3bar JSON protocol. Never exits
+/// because the daemon went away; it prints a "not running" status and keeps reconnecting.
+pub fn run(json_protocol: bool) {
+    let stdout = io::stdout();
+
+    if json_protocol {
+        let mut out = stdout.lock();
+        let _ = writeln!(out, "{}", json!({ "version": 1, "click_events": true }));
+        let _ = writeln!(out, "[");
+        let _ = out.flush();
+
+        // i3bar only sends click events when we ask for them, so there's no point in reading
+        // stdin in plain text mode.
+        thread::spawn(|| read_clicks(io::stdin().lock()));
+    }
+
+    // Only print the "not running" status once per outage, rather than on every retry.
+    let mut reported_not_running = false;
+    loop {
+        if let Ok(reader) = control::connect("subscribe") {
+            reported_not_running = false;
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(l) => l,
+                    Err(_) => break,
+                };
+                if let Some(response) = Response::from_line(&line) {
+                    print_status(&mut stdout.lock(), json_protocol, &response.message, false);
+                }
+            }
+        }
+
+        if !reported_not_running {
+            print_status(&mut stdout.lock(), json_protocol, NOT_RUNNING, true);
+            reported_not_running = true;
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+/// Writes a single status update. In JSON mode every update is an element of the infinite
+/// array i3bar expects, so each line is followed by a comma.
+pub fn print_status(out: &mut impl Write, json_protocol: bool, status: &str, urgent: bool) {
+    let text = format!("ratiosplit: {}", status);
+    let result = if json_protocol {
+        writeln!(
+            out,
+            "{},",
+            json!([{ "name": "ratiosplit", "full_text": text, "urgent": urgent }])
+        )
+    } else {
+        writeln!(out, "{}", text)
+    };
+
+    if result.and_then(|_| out.flush()).is_err() {
+        // Our bar has gone away, so there's no one left to print for.
+        std::process::exit(0);
+    }
+}
+
+/// Reads i3bar click events from the given input and toggles the daemon's paused state for
+/// each click on our block.
+pub fn read_clicks(input: impl BufRead) {
+    forward_clicks(input, |line| control::request(line).map(drop));
+}
+
+/// Sends a `toggle` request with `request` for every click on our block read from `input`.
+fn forward_clicks(input: impl BufRead, mut request: impl FnMut(&str) -> io::Result<()>) {
+    for line in input.lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(_) => return,
+        };
+
+        if is_click(&line) {
+            if let Err(error) = request("toggle") {
+                trace!("Error toggling daemon from click: {:?}", error);
+            }
+        }
+    }
+}
+
+/// i3bar sends clicks as an infinite JSON array, one object per line, with the opening bracket
+/// and separating commas attached to the lines.
+pub fn is_click(line: &str) -> bool {
+    let line = line.trim().trim_start_matches(['[', ',']);
+    match serde_json::from_str::<Value>(line) {
+        Ok(event) => event.get("name").and_then(Value::as_str) == Some("ratiosplit"),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn printed(json_protocol: bool, status: &str, urgent: bool) -> String {
+        let mut out = Vec::new();
+        print_status(&mut out, json_protocol, status, urgent);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn clicks_on_our_block_are_recognized() {
+        assert!(is_click("[{\"name\":\"ratiosplit\",\"button\":1}"));
+        assert!(is_click(",{\"name\":\"ratiosplit\",\"button\":3}"));
+        assert!(is_click("  {\"name\":\"ratiosplit\"}  "));
+    }
+
+    #[test]
+    fn other_lines_are_not_clicks() {
+        assert!(!is_click("[{\"name\":\"clock\",\"button\":1}"));
+        assert!(!is_click("{\"button\":1}"));
+        assert!(!is_click("["));
+        assert!(!is_click("not json"));
+    }
+
+    #[test]
+    fn clicks_on_our_block_toggle_the_daemon() {
+        let input = [
+            "[",
+            "{\"name\":\"ratiosplit\",\"button\":1}",
+            ",{\"name\":\"clock\",\"button\":1}",
+            ",{\"name\":\"ratiosplit\",\"button\":3}",
+        ]
+        .join("\n");
+        let mut requests = Vec::new();
+        forward_clicks(io::Cursor::new(input), |line| {
+            requests.push(line.to_string());
+            // A daemon that went away doesn't stop the clicks after it.
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert_eq!(requests, vec!["toggle", "toggle"]);
+    }
+
+    #[test]
+    fn plain_status_is_a_line_of_text() {
+        assert_eq!(printed(false, "paused", true), "ratiosplit: paused\n");
+    }
+
+    #[test]
+    fn json_status_is_an_array_element() {
+        let line = printed(true, "running", false);
+        assert!(line.ends_with(",\n"));
+        let value: Value = serde_json::from_str(line.trim_end().trim_end_matches(',')).unwrap();
+        assert_eq!(
+            value,
+            json!([{ "name": "ratiosplit", "full_text": "ratiosplit: running", "urgent": false }])
+        );
+    }
+}
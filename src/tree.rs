@@ -0,0 +1,475 @@
+use i3ipc::reply::{Node, NodeLayout, NodeType, WindowProperty};
+
+use crate::ipc::Extras;
+use crate::settings::{SplitDirection, WindowIdentity};
+
+/// Finds the direct parent of the node with the given id, if it exists in the tree.
+pub fn find_parent(child_id: i64, node: &Node) -> Option<&Node> {
+    // In order to find the child node, we loop through all the children of the tree.
+    // There are a few possible failure conditions:
+    // 1. The node isn't in the tree
+    // 2. The node is a floating node (no need to dynamically resize these, so just don't check that field).
+    // 3. The given id is for the root node.
+    // 4. The node is a bar or other dock window, or in the scratchpad, which are never laid out.
+
+    for child in tiling_children(node) {
+        if child.id == child_id {
+            return Some(node);
+        } else if let Some(found) = find_parent(child_id, child) {
+            return Some(found);
+        }
+    }
+
+    None
+}
+
+/// Finds the node with the given id, if it exists in the tree. Floating nodes and dock windows
+/// are not considered, matching `find_parent`.
+pub fn find_node(id: i64, node: &Node) -> Option<&Node> {
+    if node.id == id {
+        return Some(node);
+    }
+
+    tiling_children(node).find_map(|child| find_node(id, child))
+}
+
+/// Finds the currently focused node. Floating nodes and dock windows are not considered, matching
+/// `find_parent`.
+pub fn find_focused(node: &Node) -> Option<&Node> {
+    if node.focused {
+        return Some(node);
+    }
+
+    tiling_children(node).find_map(find_focused)
+}
+
+/// Whether the node with the given id is a bar or other dock window.
+pub fn is_docked(id: i64, tree: &Node) -> bool {
+    find_ancestor(id, tree, NodeType::DockArea).is_some()
+}
+
+/// The output i3 keeps the scratchpad on, which is never shown.
+const SCRATCH_OUTPUT: &str = "__i3";
+
+/// The scratchpad's workspace, on `SCRATCH_OUTPUT`.
+const SCRATCH_WORKSPACE: &str = "__i3_scratch";
+
+/// The children of a node that are ever laid out, leaving out the dock areas above and below each
+/// output's content, and the hidden output and workspace holding the scratchpad.
+fn tiling_children(node: &Node) -> impl Iterator<Item = &Node> {
+    node.nodes.iter().filter(|child| match child.nodetype {
+        NodeType::DockArea => false,
+        NodeType::Output => child.name.as_deref() != Some(SCRATCH_OUTPUT),
+        NodeType::Workspace => child.name.as_deref() != Some(SCRATCH_WORKSPACE),
+        _ => true,
+    })
+}
+
+/// A name for the container with the given id that stays the same across restarts for as long as
+/// the layout does: its workspace's name, followed by the index of each container on the way
+/// down to it, like `3/1/0`.
+pub fn container_path(id: i64, tree: &Node) -> Option<String> {
+    let workspace = find_workspace(id, tree)?;
+    let mut path = workspace.name.clone()?;
+    let mut node = workspace;
+    while node.id != id {
+        let index = node
+            .nodes
+            .iter()
+            .position(|child| find_node(id, child).is_some())?;
+        path.push_str(&format!("/{}", index));
+        node = &node.nodes[index];
+    }
+    Some(path)
+}
+
+/// Finds the workspace that contains the node with the given id.
+pub fn find_workspace(child_id: i64, node: &Node) -> Option<&Node> {
+    find_ancestor(child_id, node, NodeType::Workspace)
+}
+
+/// Finds the output that contains the node with the given id.
+pub fn find_output(child_id: i64, node: &Node) -> Option<&Node> {
+    find_ancestor(child_id, node, NodeType::Output)
+}
+
+/// Whether the node with the given id is currently fullscreen, going by its `fullscreen_mode`.
+pub fn is_fullscreen(id: i64, extras: &Extras) -> bool {
+    extras.get(&id).is_some_and(|extras| extras.fullscreen)
+}
+
+/// A fullscreen container other than `except` on the workspace the given output is showing, if
+/// any, going by `fullscreen_mode`.
+pub fn shown_fullscreen<'a>(output: &'a Node, extras: &Extras, except: i64) -> Option<&'a Node> {
+    // The output's content container lists the workspace it shows first in its focus order.
+    let shown = output
+        .nodes
+        .iter()
+        .find(|child| child.nodetype == NodeType::Con)
+        .and_then(|content| {
+            let shown = *content.focus.first()?;
+            content.nodes.iter().find(|workspace| workspace.id == shown)
+        })?;
+    find_fullscreen(shown, extras, except)
+}
+
+fn find_fullscreen<'a>(node: &'a Node, extras: &Extras, except: i64) -> Option<&'a Node> {
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .find_map(|child| {
+            if child.id != except && is_fullscreen(child.id, extras) {
+                Some(child)
+            } else {
+                find_fullscreen(child, extras, except)
+            }
+        })
+}
+
+/// Whether the given node's rect isn't entirely within any one output.
+pub fn spans_outputs(node: &Node, tree: &Node) -> bool {
+    let (x, y, width, height) = node.rect;
+    !tree
+        .nodes
+        .iter()
+        .filter(|output| output.nodetype == NodeType::Output)
+        .any(|output| {
+            let (ox, oy, owidth, oheight) = output.rect;
+            x >= ox && y >= oy && x + width <= ox + owidth && y + height <= oy + oheight
+        })
+}
+
+/// Whether the node with the given id is on an output that is taller than it is wide.
+pub fn is_portrait(id: i64, tree: &Node) -> bool {
+    find_output(id, tree).is_some_and(|output| output.rect.3 > output.rect.2)
+}
+
+/// All workspaces in the tree, in tree order.
+pub fn workspaces(node: &Node) -> Vec<&Node> {
+    if node.nodetype == NodeType::Workspace {
+        return vec![node];
+    }
+
+    tiling_children(node).flat_map(workspaces).collect()
+}
+
+/// The direction the given container splits its children in, if it is a split container.
+pub fn split_direction(node: &Node) -> Option<SplitDirection> {
+    match node.layout {
+        NodeLayout::SplitH => Some(SplitDirection::Horizontal),
+        NodeLayout::SplitV => Some(SplitDirection::Vertical),
+        _ => None,
+    }
+}
+
+/// The split containers under the given node that hold exactly two children, the ones
+/// `mode = remember` remembers ratios for. The root reports a split layout too, and holds the
+/// scratchpad's output beside the real one, but isn't laid out by ratio.
+pub fn split_pairs(node: &Node) -> Vec<&Node> {
+    let laid_out = matches!(node.nodetype, NodeType::Workspace | NodeType::Con);
+    let own =
+        (laid_out && split_direction(node).is_some() && node.nodes.len() == 2).then_some(node);
+    own.into_iter()
+        .chain(tiling_children(node).flat_map(split_pairs))
+        .collect()
+}
+
+/// The share of `parent` that `child` takes up. i3 leaves `percent` empty for some freshly created
+/// containers, in which case it's worked out from their rects along the parent's split axis.
+/// Children of tabbed and stacked containers each take up the whole parent.
+pub fn effective_percent(child: &Node, parent: &Node) -> f64 {
+    if let Some(percent) = child.percent {
+        return percent;
+    }
+
+    let (size, parent_size) = match parent.layout {
+        NodeLayout::SplitH => (child.rect.2, parent.rect.2),
+        NodeLayout::SplitV => (child.rect.3, parent.rect.3),
+        _ => return 1.0,
+    };
+
+    if parent_size <= 0 {
+        return 1.0 / parent.nodes.len().max(1) as f64;
+    }
+    f64::from(size) / f64::from(parent_size)
+}
+
+/// How many tiled windows are under the given node. Floating windows are not counted.
+pub fn tiled_windows(node: &Node) -> usize {
+    if node.nodes.is_empty() {
+        return usize::from(node.nodetype == NodeType::Con);
+    }
+
+    tiling_children(node).map(tiled_windows).sum()
+}
+
+/// The tiled windows under the given node, in tree order.
+pub fn tiled_leaves(node: &Node) -> Vec<&Node> {
+    if node.nodes.is_empty() {
+        return if node.nodetype == NodeType::Con {
+            vec![node]
+        } else {
+            Vec::new()
+        };
+    }
+
+    tiling_children(node).flat_map(tiled_leaves).collect()
+}
+
+/// The ids of every window in the tree, tiled, floating, or in the scratchpad.
+pub fn window_ids(node: &Node) -> Vec<i64> {
+    let own = node.window.map(|_| node.id);
+    own.into_iter()
+        .chain(
+            node.nodes
+                .iter()
+                .chain(node.floating_nodes.iter())
+                .flat_map(window_ids),
+        )
+        .collect()
+}
+
+/// Whether the focused window is a floating one, which `find_focused` doesn't look for.
+pub fn is_floating_focused(node: &Node) -> bool {
+    node.floating_nodes
+        .iter()
+        .any(|floating| find_focused(floating).is_some())
+        || node.nodes.iter().any(is_floating_focused)
+}
+
+/// Whether any container under the given node is a placeholder from `append_layout`, still
+/// waiting to swallow a window: a leaf container without one.
+pub fn has_placeholders(node: &Node) -> bool {
+    if node.nodes.is_empty() {
+        return node.nodetype == NodeType::Con && node.window.is_none();
+    }
+
+    node.nodes.iter().any(has_placeholders)
+}
+
+/// The number i3 gives a workspace with the given name: its leading digits, if any.
+pub fn workspace_number(name: &str) -> Option<i32> {
+    let digits: String = name.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+/// The X11 class of the node's window, if it has one.
+pub fn window_class(node: &Node) -> Option<&str> {
+    node.window_properties
+        .as_ref()?
+        .get(&WindowProperty::Class)
+        .map(String::as_str)
+}
+
+/// The role (`WM_WINDOW_ROLE`) of the given window, if it has one.
+pub fn window_role(node: &Node) -> Option<&str> {
+    node.window_properties
+        .as_ref()?
+        .get(&WindowProperty::WindowRole)
+        .map(String::as_str)
+}
+
+/// The instance (the first part of `WM_CLASS`) of the given window, if it has one.
+pub fn window_instance(node: &Node) -> Option<&str> {
+    node.window_properties
+        .as_ref()?
+        .get(&WindowProperty::Instance)
+        .map(String::as_str)
+}
+
+/// Everything rules and exclusions match windows on.
+pub fn window_identity(node: &Node) -> WindowIdentity<'_> {
+    WindowIdentity {
+        role: window_role(node),
+        instance: window_instance(node),
+        class: window_class(node),
+        title: node.name.as_deref(),
+    }
+}
+
+/// Finds the closest ancestor of the given type for the node with the given id. Floating nodes
+/// are not considered, matching `find_parent`.
+fn find_ancestor(child_id: i64, node: &Node, nodetype: NodeType) -> Option<&Node> {
+    return find_ancestor_inner(child_id, node, &nodetype, None);
+
+    fn find_ancestor_inner<'a>(
+        child_id: i64,
+        node: &'a Node,
+        nodetype: &NodeType,
+        ancestor: Option<&'a Node>,
+    ) -> Option<&'a Node> {
+        let ancestor = if node.nodetype == *nodetype {
+            Some(node)
+        } else {
+            ancestor
+        };
+
+        if node.id == child_id {
+            return ancestor;
+        }
+
+        node.nodes
+            .iter()
+            .find_map(|child| find_ancestor_inner(child_id, child, nodetype, ancestor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{focus, node, scratchpad, split, tree, window};
+    use crate::ipc::NodeExtras;
+
+    #[test]
+    fn fullscreen_follows_fullscreen_mode() {
+        let mut extras = Extras::new();
+        extras.insert(
+            100,
+            NodeExtras {
+                fullscreen: true,
+                ..NodeExtras::default()
+            },
+        );
+        extras.insert(101, NodeExtras::default());
+
+        assert!(is_fullscreen(100, &extras));
+        assert!(!is_fullscreen(101, &extras));
+        assert!(!is_fullscreen(102, &extras));
+    }
+
+    #[test]
+    fn covering_the_output_is_not_fullscreen() {
+        // The only window on a workspace without gaps covers its output as well.
+        let tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![window(100, "Alone", (0, 0, 1000, 500))],
+        );
+        assert_eq!(find_node(100, &tree).unwrap().rect, tree.rect);
+        assert!(!is_fullscreen(100, &Extras::new()));
+    }
+
+    #[test]
+    fn shown_fullscreen_looks_at_the_shown_workspace() {
+        let workspace = |id, windows| {
+            let mut workspace = node(
+                id,
+                NodeType::Workspace,
+                NodeLayout::SplitH,
+                (0, 0, 1000, 500),
+            );
+            workspace.nodes = windows;
+            workspace
+        };
+        let mut content = node(3, NodeType::Con, NodeLayout::SplitH, (0, 0, 1000, 500));
+        content.nodes = vec![
+            workspace(10, vec![window(100, "Video", (0, 0, 1000, 500))]),
+            workspace(11, vec![window(110, "Game", (0, 0, 1000, 500))]),
+        ];
+        content.focus = vec![10, 11];
+        let mut output = node(2, NodeType::Output, NodeLayout::Output, (0, 0, 1000, 500));
+        output.nodes = vec![content];
+
+        let mut extras = Extras::new();
+        for id in [100, 110] {
+            extras.insert(
+                id,
+                NodeExtras {
+                    fullscreen: true,
+                    ..NodeExtras::default()
+                },
+            );
+        }
+
+        assert_eq!(
+            shown_fullscreen(&output, &extras, 0).map(|node| node.id),
+            Some(100)
+        );
+        assert!(shown_fullscreen(&output, &extras, 100).is_none());
+        assert!(shown_fullscreen(&output, &Extras::new(), 0).is_none());
+    }
+
+    #[test]
+    fn workspace_numbers_are_leading_digits() {
+        assert_eq!(workspace_number("2"), Some(2));
+        assert_eq!(workspace_number("2: mail"), Some(2));
+        assert_eq!(workspace_number("10web"), Some(10));
+        assert_eq!(workspace_number("web 2"), None);
+        assert_eq!(workspace_number(""), None);
+    }
+
+    #[test]
+    fn missing_percents_come_from_rects_along_the_split() {
+        let child = |id, rect| {
+            let mut child = window(id, "A", rect);
+            child.percent = None;
+            child
+        };
+        let mut parent = split(20, NodeLayout::SplitH, (0, 0, 1000, 500), Vec::new());
+        parent.nodes = vec![child(100, (0, 0, 250, 500)), child(101, (250, 0, 750, 500))];
+        assert_eq!(effective_percent(&parent.nodes[0], &parent), 0.25);
+        assert_eq!(effective_percent(&parent.nodes[1], &parent), 0.75);
+
+        parent.layout = NodeLayout::SplitV;
+        parent.nodes = vec![
+            child(100, (0, 0, 1000, 100)),
+            child(101, (0, 100, 1000, 400)),
+        ];
+        assert_eq!(effective_percent(&parent.nodes[0], &parent), 0.2);
+        assert_eq!(effective_percent(&parent.nodes[1], &parent), 0.8);
+
+        parent.layout = NodeLayout::Tabbed;
+        assert_eq!(effective_percent(&parent.nodes[0], &parent), 1.0);
+    }
+
+    #[test]
+    fn missing_percents_in_a_zero_size_parent_are_shared_equally() {
+        let mut parent = split(20, NodeLayout::SplitH, (0, 0, 0, 0), Vec::new());
+        parent.nodes = vec![
+            window(100, "A", (0, 0, 0, 0)),
+            window(101, "B", (0, 0, 0, 0)),
+        ];
+        for child in &mut parent.nodes {
+            child.percent = None;
+        }
+        assert_eq!(effective_percent(&parent.nodes[0], &parent), 0.5);
+
+        // A percent i3 did report is used as it is.
+        parent.nodes[1].percent = Some(0.7);
+        assert_eq!(effective_percent(&parent.nodes[1], &parent), 0.7);
+    }
+
+    #[test]
+    fn the_scratchpad_is_left_out_of_every_search() {
+        let mut tree = tree(
+            "1",
+            NodeLayout::SplitH,
+            vec![
+                window(100, "A", (0, 0, 500, 500)),
+                window(101, "B", (500, 0, 500, 500)),
+            ],
+        );
+        scratchpad(
+            &mut tree,
+            vec![
+                window(200, "Hidden", (0, 0, 500, 500)),
+                window(201, "Hidden", (500, 0, 500, 500)),
+            ],
+        );
+        focus(&mut tree, 200);
+
+        let names: Vec<_> = workspaces(&tree)
+            .iter()
+            .map(|w| w.name.as_deref())
+            .collect();
+        assert_eq!(names, [Some("1")]);
+        let pairs: Vec<i64> = split_pairs(&tree).iter().map(|n| n.id).collect();
+        assert_eq!(pairs, [10]);
+        assert_eq!(tiled_windows(&tree), 2);
+        let leaves: Vec<i64> = tiled_leaves(&tree).iter().map(|n| n.id).collect();
+        assert_eq!(leaves, [100, 101]);
+        assert!(find_node(200, &tree).is_none());
+        assert!(find_parent(200, &tree).is_none());
+        assert!(find_focused(&tree).is_none());
+    }
+}